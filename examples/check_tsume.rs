@@ -0,0 +1,25 @@
+//! ```
+//! ### 以下のコマンドで実行。sfen を1行に1問ずつ標準入力から読む。
+//! echo "4k4/9/4G4/9/9/9/9/9/9 b G 1" | cargo run --release --example check_tsume
+//! ```
+
+extern crate kifuwarabe_usi;
+use kifuwarabe_usi::mate::MateLimits;
+use kifuwarabe_usi::tsume::check_tsume;
+use std::io;
+use std::io::BufRead;
+
+fn main() {
+    let limits = MateLimits{ nodes : Some(1_000_000), ..MateLimits::default() };
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        let sfen = line.trim();
+        if sfen.is_empty() { continue; }
+        let report = check_tsume(sfen, &limits);
+        println!("{}", sfen);
+        print!("{}", report);
+        println!("{}", if report.is_sound() { "完全作" } else { "要検討" });
+        println!();
+    }
+}
@@ -0,0 +1,35 @@
+//! ```
+//! ### 以下のコマンドで実行。エンジンを指定しなければモック・エンジンを動かす。
+//! cargo build --example mock_engine
+//! cargo run --example drive_engine -- [engine path]
+//! ```
+
+extern crate kifuwarabe_usi;
+use kifuwarabe_usi::*;
+use kifuwarabe_usi::engine_process::*;
+use std::env;
+use std::time::Duration;
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| "target/debug/examples/mock_engine".to_string());
+    let timeout = Duration::from_secs(10);
+
+    let mut engine = UsiEngineProcess::spawn(&path, timeout).unwrap();
+    println!("{:?}", engine.id());
+    for option in engine.options() {
+        println!("{:?}", option);
+    }
+
+    engine.is_ready(timeout).unwrap();
+    engine.new_game().unwrap();
+    engine.position("startpos", &[]).unwrap();
+    engine.go(&GoParams{ byoyomi : 1000, ..GoParams::default() }).unwrap();
+    for event in engine.events() {
+        match event {
+            EngineEvent::Info(info) => { println!("{}", info); },
+            EngineEvent::BestMove(bestmove) => { println!("{}", bestmove); break; },
+            _ => { println!("{:?}", event); },
+        }
+    }
+    engine.quit(timeout).unwrap();
+}
@@ -0,0 +1,52 @@
+//! ```
+//! ### GUI側の動作確認に使う、何も考えないエンジン。
+//! cargo build --example mock_engine
+//! ```
+//!
+//! `go` には決まった `info` を1行出して投了する。
+//! `go mate` だけは本当に詰みを探して `checkmate` を返す。
+
+extern crate kifuwarabe_usi;
+
+use std::io;
+use std::io::BufRead;
+use std::time::Duration;
+
+use kifuwarabe_usi::mate::{MateLimits, solve_mate};
+use kifuwarabe_usi::position::{Position, parse_position};
+
+fn main() {
+    let mut position = Position::startpos();
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        let line = line.trim_end();
+        if line=="usi" {
+            println!("id name MockEngine");
+            println!("id author muzudho");
+            println!("option name USI_Hash type spin default 16 min 1 max 1024");
+            println!("option name Style type combo default Normal var Normal var Aggressive");
+            println!("option name BookFile type filename default <empty>");
+            println!("usiok");
+        } else if line=="isready" {
+            println!("readyok");
+        } else if let Some(rest) = line.strip_prefix("position ") {
+            let mut starts = 0;
+            let (start, moves) = parse_position(rest, &mut starts, rest.len());
+            position = start;
+            for movement in moves.iter() { position.do_move(movement); }
+        } else if let Some(rest) = line.strip_prefix("go mate") {
+            // `go mate <ミリ秒>` か `go mate infinite`。
+            let limits = MateLimits{
+                time : rest.trim().parse().ok().map(Duration::from_millis),
+                ..MateLimits::default()
+            };
+            println!("{}", solve_mate(&position, &limits));
+        } else if line.starts_with("go") {
+            println!("info depth 1 nodes 1 score cp 0 pv 7g7f");
+            println!("bestmove resign");
+        } else if line=="quit" {
+            break;
+        }
+    }
+}
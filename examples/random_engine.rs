@@ -0,0 +1,91 @@
+//! ```
+//! ### 合法手から1つを選んで指すだけのエンジン。GUI 側の道具の対局相手にする。
+//! cargo build --example random_engine
+//! ```
+//!
+//! 乱数の種は `Seed` オプションで決める。0 なら時刻から作る。
+//! `go ponder` と `go infinite` は、`stop` か `ponderhit` が来てから答える。
+
+extern crate kifuwarabe_usi;
+
+use std::io;
+use std::io::BufRead;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use kifuwarabe_usi::*;
+use kifuwarabe_usi::engine_output::BestMove;
+use kifuwarabe_usi::position::{Position, parse_position};
+
+/// xorshift64。0 にならない種で始める。
+struct Random{
+    state : u64,
+}
+impl Random{
+    fn new(seed:u64) -> Random {
+        let seed = if seed==0 {
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_nanos() as u64).unwrap_or(1)
+        } else {
+            seed
+        };
+        Random{ state : seed | 1 }
+    }
+
+    /// 0 以上 `n` 未満。
+    fn below(&mut self, n:usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % n as u64) as usize
+    }
+}
+
+fn bestmove(position:&Position, random:&mut Random) -> BestMove {
+    let moves = position.legal_moves();
+    if moves.is_empty() { return BestMove::Resign; }
+    BestMove::Move{ best : moves[random.below(moves.len())], ponder : None }
+}
+
+fn main() {
+    let mut position = Position::startpos();
+    let mut seed = 0;
+    let mut random = Random::new(seed);
+    // 先読み中なら真。答えを `stop` か `ponderhit` まで待たせる。
+    let mut pending = false;
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        let line = line.trim_end();
+        if line=="usi" {
+            println!("id name RandomEngine");
+            println!("id author muzudho");
+            println!("option name Seed type spin default 0 min 0 max 2147483647");
+            println!("usiok");
+        } else if line=="isready" {
+            random = Random::new(seed);
+            println!("readyok");
+        } else if let Some(rest) = line.strip_prefix("setoption name Seed value ") {
+            seed = rest.trim().parse().unwrap_or(0);
+        } else if let Some(rest) = line.strip_prefix("position ") {
+            let mut starts = 0;
+            let (start, moves) = parse_position(rest, &mut starts, rest.len());
+            position = start;
+            for movement in moves.iter() { position.do_move(movement); }
+        } else if let Some(rest) = line.strip_prefix("go") {
+            let mut starts = 0;
+            let params = parse_go(rest, &mut starts, rest.len());
+            if params.ponder || params.infinite {
+                pending = true;
+            } else {
+                println!("{}", bestmove(&position, &mut random));
+            }
+        } else if line=="stop" || line=="ponderhit" {
+            if pending {
+                pending = false;
+                println!("{}", bestmove(&position, &mut random));
+            }
+        } else if line=="quit" {
+            break;
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! ```
+//! ### このクレートだけで作った、反復深化アルファベータ探索のエンジン。
+//! cargo build --release --example reference_engine
+//! ```
+//!
+//! 評価は駒の損得だけ。`transposition_table` の置換表を引き、反復深化と `info` の出力は `search::think` に任せる。
+//! 新しくエンジンを書くときのひな形と、フレームワークの通しの動作確認を兼ねる。
+//!
+//! 探索は別スレッドで回し、`stop` と `ponderhit` は読み取りスレッドから `SearchClock` で伝える。
+//! `Threads` を増やすと `search::think_parallel` で並べて読む。置換表は全部のスレッドで分け合う。
+
+extern crate kifuwarabe_usi;
+
+use std::io;
+use std::io::BufRead;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use kifuwarabe_usi::*;
+use kifuwarabe_usi::diagram::to_bod;
+use kifuwarabe_usi::engine_output::ScoreBound;
+use kifuwarabe_usi::mate::{MateLimits, solve_mate};
+use kifuwarabe_usi::position::{Position, parse_position};
+use kifuwarabe_usi::search::{INFINITE, MATE, MAX_PLY, Search, SearchClock, SearchOptions, think_parallel_to_stdout};
+use kifuwarabe_usi::see::PieceValues;
+use kifuwarabe_usi::time_manager::{TimeBudget, TimeOptions, allot};
+use kifuwarabe_usi::transposition_table::{TranspositionTable, score_from_table, score_to_table};
+
+/// 駒を取る手なら真。
+fn is_capture(position:&Position, movement:&UsiMovement) -> bool {
+    !position.piece(movement.destination_file, movement.destination_rank).is_space()
+}
+
+struct Searcher{
+    table : Arc<TranspositionTable>,
+    values : PieceValues,
+    /// 対局の開始から考える局面までと、読み筋の局面の `key`。千日手を見つける。
+    history : Vec<u64>,
+    nodes : u64,
+    seldepth : u32,
+    aborted : bool,
+}
+impl Searcher{
+    fn new(table:Arc<TranspositionTable>) -> Searcher {
+        Searcher{
+            table,
+            values : PieceValues::default(),
+            history : Vec::new(),
+            nodes : 0,
+            seldepth : 0,
+            aborted : false,
+        }
+    }
+
+    /// 時々 `clock` を見る。一度打ち切ったら、この探索の間は打ち切ったまま。
+    #[allow(clippy::manual_is_multiple_of)]
+    fn is_over(&mut self, clock:&SearchClock) -> bool {
+        if !self.aborted && self.nodes % 1024 == 0 { self.aborted = clock.is_over(); }
+        self.aborted
+    }
+
+    /// 手番の側から見た駒の損得。
+    fn evaluate(&self, position:&Position) -> i32 {
+        let mut score = 0;
+        for &pc in position.board.iter() {
+            if pc.is_space() { continue; }
+            let pt = pc.piece_type();
+            if pt==PieceType::K { continue; }
+            let value = self.values.value(pt);
+            score += if pc.color()==Color::Sente { value } else { -value };
+        }
+        for color in Color::all() {
+            for (pt, count) in position.hand[color.index()].iter() {
+                let value = self.values.value(pt) * count as i32;
+                score += if color==Color::Sente { value } else { -value };
+            }
+        }
+        if position.turn==Color::Sente { score } else { -score }
+    }
+
+    /// 置換表の手、得をする取る手、それ以外の順に並べる。
+    fn order(&self, position:&Position, moves:&mut [UsiMovement], best:Option<UsiMovement>) {
+        let values = self.values;
+        moves.sort_by_cached_key(|x| {
+            if best==Some(*x) { return i32::MIN; }
+            if is_capture(position, x) || x.promotion { -position.see(x, &values) } else { 0 }
+        });
+    }
+
+    /// 駒を取る手だけを読む。王手されていれば全部の受けを読む。
+    fn quiesce(&mut self, position:&Position, ply:u32, mut alpha:i32, beta:i32, clock:&SearchClock) -> i32 {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if self.is_over(clock) { return 0; }
+        let in_check = position.in_check(position.turn);
+        if MAX_PLY<=ply { return self.evaluate(position); }
+        let mut moves = if in_check {
+            position.evasions()
+        } else {
+            let stand_pat = self.evaluate(position);
+            if beta<=stand_pat { return stand_pat; }
+            alpha = alpha.max(stand_pat);
+            position.pseudo_legal_moves().into_iter()
+                .filter(|x| is_capture(position, x) && position.see_ge(x, 0, &self.values) && position.is_legal_pseudo(x))
+                .collect()
+        };
+        if in_check && moves.is_empty() { return -MATE + ply as i32; }
+        self.order(position, &mut moves, None);
+        for movement in moves.iter() {
+            let mut next = *position;
+            next.do_move(movement);
+            let score = -self.quiesce(&next, ply + 1, -beta, -alpha, clock);
+            if self.aborted { return 0; }
+            if beta<=score { return score; }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    /// アルファベータ探索。手番の側から見た評価値を返す。
+    fn alpha_beta(&mut self, position:&Position, depth:u32, ply:u32, mut alpha:i32, beta:i32, clock:&SearchClock) -> i32 {
+        let key = position.key();
+        if self.history.contains(&key) { return 0; }
+        if depth==0 { return self.quiesce(position, ply, alpha, beta, clock); }
+        self.nodes += 1;
+        if self.is_over(clock) { return 0; }
+
+        let found = self.table.probe(key);
+        if let Some(entry) = found {
+            let score = score_from_table(entry.score, ply);
+            if depth<=entry.depth {
+                match entry.bound {
+                    ScoreBound::Exact => { return score; },
+                    ScoreBound::Lower if beta<=score => { return score; },
+                    ScoreBound::Upper if score<=alpha => { return score; },
+                    _ => {},
+                }
+            }
+        }
+
+        let mut moves = position.pseudo_legal_moves();
+        self.order(position, &mut moves, found.and_then(|x| x.movement));
+        let original_alpha = alpha;
+        let mut best_score = -INFINITE;
+        let mut best = None;
+        self.history.push(key);
+        for movement in moves.iter() {
+            if !position.is_legal_pseudo(movement) { continue; }
+            let mut next = *position;
+            next.do_move(movement);
+            let score = -self.alpha_beta(&next, depth - 1, ply + 1, -beta, -alpha, clock);
+            if self.aborted { break; }
+            if best_score<score {
+                best_score = score;
+                best = Some(*movement);
+            }
+            if alpha<score { alpha = score; }
+            if beta<=alpha { break; }
+        }
+        self.history.pop();
+        if self.aborted { return 0; }
+        // 指せる手が無ければ負け。
+        if best.is_none() { return -MATE + ply as i32; }
+
+        let bound = if beta<=best_score {
+            ScoreBound::Lower
+        } else if original_alpha<best_score {
+            ScoreBound::Exact
+        } else {
+            ScoreBound::Upper
+        };
+        self.table.save(key, best, score_to_table(best_score, ply), depth, bound);
+        best_score
+    }
+
+    /// 置換表をたどって読み筋を作る。
+    fn principal_variation(&self, position:&Position, depth:u32) -> Vec<UsiMovement> {
+        let mut pv = Vec::new();
+        let mut position = *position;
+        let mut seen = Vec::new();
+        while (pv.len() as u32)<depth {
+            let key = position.key();
+            if seen.contains(&key) { break; }
+            seen.push(key);
+            let movement = match self.table.probe(key).and_then(|x| x.movement) {
+                Some(x) => x,
+                None => { break; },
+            };
+            if !position.pseudo_legal_moves().contains(&movement) || !position.is_legal_pseudo(&movement) { break; }
+            pv.push(movement);
+            position.do_move(&movement);
+        }
+        pv
+    }
+}
+impl Search for Searcher{
+    fn new_search(&mut self) {
+        self.nodes = 0;
+        self.seldepth = 0;
+        self.aborted = false;
+    }
+
+    fn search(&mut self, position:&Position, depth:u32, alpha:i32, beta:i32, clock:&SearchClock) -> (i32, Vec<UsiMovement>) {
+        let score = self.alpha_beta(position, depth, 0, alpha, beta, clock);
+        (score, self.principal_variation(position, depth))
+    }
+
+    fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    fn seldepth(&self) -> Option<u32> {
+        Some(self.seldepth)
+    }
+
+    fn hashfull(&self) -> Option<u32> {
+        Some(self.table.hashfull())
+    }
+}
+
+fn main() {
+    let clock = SearchClock::new();
+    let mut hash_megabytes = 16;
+    let mut threads = 1;
+    let mut table = Arc::new(TranspositionTable::new(hash_megabytes));
+    let mut searchers = Some(vec![Searcher::new(table.clone())]);
+    let mut worker : Option<JoinHandle<Vec<Searcher>>> = None;
+    let mut options = SearchOptions::default();
+    let mut position = Position::startpos();
+    let mut history = Vec::new();
+    let mut time_options = TimeOptions::default();
+    let mut budget = TimeBudget{ optimum : Duration::from_millis(0), maximum : Duration::from_millis(0) };
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        let line = line.trim_end();
+        // 読み終わっていない探索は止めて、探索部を取り戻す。
+        if line=="stop" || line=="quit" || line.starts_with("gameover") || line.starts_with("go") {
+            clock.stop();
+            if let Some(x) = worker.take() { searchers = Some(x.join().unwrap()); }
+        }
+
+        if line=="usi" {
+            println!("id name ReferenceEngine");
+            println!("id author muzudho");
+            println!("option name USI_Hash type spin default 16 min 1 max 4096");
+            println!("option name Threads type spin default 1 min 1 max 256");
+            println!("option name MultiPV type spin default 1 min 1 max 16");
+            println!("option name NetworkDelay type spin default {} min 0 max 10000", time_options.network_delay);
+            println!("option name MinimumThinkingTime type spin default {} min 0 max 60000", time_options.minimum_thinking_time);
+            println!("option name SlowMover type spin default {} min 1 max 1000", time_options.slow_mover);
+            println!("usiok");
+        } else if line=="isready" {
+            let searchers = searchers.as_mut().unwrap();
+            if table.megabytes()!=hash_megabytes {
+                // 古い表を手放してから作る。
+                searchers.clear();
+                table = Arc::new(TranspositionTable::new(hash_megabytes));
+            }
+            searchers.resize_with(threads, || Searcher::new(table.clone()));
+            println!("readyok");
+        } else if let Some(rest) = line.strip_prefix("setoption name USI_Hash value ") {
+            hash_megabytes = rest.trim().parse().unwrap_or(hash_megabytes);
+        } else if let Some(rest) = line.strip_prefix("setoption name Threads value ") {
+            threads = rest.trim().parse().unwrap_or(threads).max(1);
+        } else if let Some(rest) = line.strip_prefix("setoption name MultiPV value ") {
+            options.multipv = rest.trim().parse().unwrap_or(options.multipv);
+        } else if let Some(rest) = line.strip_prefix("setoption name NetworkDelay value ") {
+            time_options.network_delay = rest.trim().parse().unwrap_or(time_options.network_delay);
+        } else if let Some(rest) = line.strip_prefix("setoption name MinimumThinkingTime value ") {
+            time_options.minimum_thinking_time = rest.trim().parse().unwrap_or(time_options.minimum_thinking_time);
+        } else if let Some(rest) = line.strip_prefix("setoption name SlowMover value ") {
+            time_options.slow_mover = rest.trim().parse().unwrap_or(time_options.slow_mover);
+        } else if line=="usinewgame" {
+            table.clear();
+        } else if let Some(rest) = line.strip_prefix("position ") {
+            let mut starts = 0;
+            let (start, moves) = parse_position(rest, &mut starts, rest.len());
+            position = start;
+            history.clear();
+            for movement in moves.iter() {
+                history.push(position.key());
+                position.do_move(movement);
+            }
+            history.push(position.key());
+        } else if let Some(rest) = line.strip_prefix("go mate") {
+            let limits = MateLimits{
+                time : rest.trim().parse().ok().map(Duration::from_millis),
+                ..MateLimits::default()
+            };
+            println!("{}", solve_mate(&position, &limits));
+        } else if let Some(rest) = line.strip_prefix("go") {
+            let mut starts = 0;
+            let params = parse_go(rest, &mut starts, rest.len());
+            budget = allot(&params, position.turn, position.ply, &time_options);
+            clock.start();
+            if !params.ponder && !params.infinite { clock.set_limit(budget); }
+            let mut searchers = searchers.take().unwrap();
+            searchers.resize_with(threads, || Searcher::new(table.clone()));
+            for searcher in searchers.iter_mut() { searcher.history = history.clone(); }
+            table.new_search();
+            let (position, options, clock) = (position, options, clock.clone());
+            worker = Some(thread::spawn(move || {
+                think_parallel_to_stdout(&mut searchers, &position, &options, &clock);
+                searchers
+            }));
+        } else if line=="ponderhit" {
+            clock.set_limit(budget);
+        } else if line=="d" {
+            // USI にはないデバッグ用のコマンド。局面図と sfen を出す。
+            print!("{}", to_bod(&position));
+            println!("sfen {}", position.to_sfen());
+        } else if line=="quit" {
+            break;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! ```
+//! ### 台本どおりに答えるエンジン。GUI 側の道具の、時間切れや反則の扱いを試す。
+//! cargo build --example scripted_engine
+//! cargo run --example scripted_engine -- <script file>
+//! ```
+//!
+//! 台本は起動時の引数か、`ScriptFile` オプションで渡す。
+//!
+//! # Script.
+//!
+//! `[見出し]` の行から次の見出しまでが1つの応答。`#` で始まる行は注釈。
+//!
+//! * `[usi]`、`[isready]`、`[usinewgame]`、`[gameover]`、`[quit]` - そのコマンドが来たとき。
+//! * `[<position の後ろ>]` - その局面で `go` が来たとき。例えば `[startpos moves 7g7f]`。
+//! * `[go]` - 局面の見出しが無い局面で `go` が来たとき。
+//!
+//! 応答の中身は1行ずつ順に実行する。
+//!
+//! * `delay <ms>` - 待つ。
+//! * `noreply` - 既定の応答を出さない。`readyok` を返さないエンジンなどを作る。
+//! * `exit` - その場でプロセスを終える。
+//! * それ以外の行はそのまま出力する。余計な出力や反則手の `bestmove` も書ける。
+//!
+//! 既定の応答は、`usi` には `id` と `usiok`、`isready` には `readyok`、`go` には最初の合法手の `bestmove`。
+//! 書いた行がその応答で始まっていれば、既定の応答は出さない。
+//!
+//! ```text
+//! [isready]
+//! delay 3000
+//!
+//! [startpos moves 7g7f]
+//! info depth 1 score cp 0 pv 3c3d
+//! bestmove 3c3d
+//!
+//! [go]
+//! bestmove 5e5a
+//! ```
+
+extern crate kifuwarabe_usi;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use kifuwarabe_usi::*;
+use kifuwarabe_usi::position::{Position, parse_position};
+
+/// 見出しごとの応答の行。
+fn parse_script(text:&str) -> HashMap<String, Vec<String>> {
+    let mut script = HashMap::new();
+    let mut heading : Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_string();
+            script.entry(name.clone()).or_insert_with(Vec::new);
+            heading = Some(name);
+        } else if let Some(ref name) = heading {
+            script.entry(name.clone()).or_insert_with(Vec::new).push(line.to_string());
+        }
+    }
+    script
+}
+
+fn load(path:&str) -> HashMap<String, Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse_script(&text),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            HashMap::new()
+        },
+    }
+}
+
+/// 応答を実行する。`keyword` で始まる行を書いていなければ、続けて `default` を出す。
+fn respond(lines:Option<&Vec<String>>, keyword:&str, default:&[String]) {
+    let mut replied = false;
+    for line in lines.map(|x| x.as_slice()).unwrap_or(&[]) {
+        if let Some(ms) = line.strip_prefix("delay ") {
+            thread::sleep(Duration::from_millis(ms.trim().parse().unwrap_or(0)));
+        } else if line=="noreply" {
+            replied = true;
+        } else if line=="exit" {
+            process::exit(0);
+        } else {
+            if !keyword.is_empty() && line.starts_with(keyword) { replied = true; }
+            println!("{}", line);
+        }
+    }
+    if !replied {
+        for line in default.iter() { println!("{}", line); }
+    }
+}
+
+/// 台本に無い局面では、最初の合法手を指す。
+fn default_bestmove(position:&Position) -> String {
+    match position.legal_moves().first() {
+        Some(movement) => format!("bestmove {}", movement),
+        None => "bestmove resign".to_string(),
+    }
+}
+
+fn main() {
+    let mut script = env::args().nth(1).map(|x| load(&x)).unwrap_or_default();
+    let mut position = Position::startpos();
+    // 最後の `position` コマンドの、`position ` より後ろ。
+    let mut position_line = "startpos".to_string();
+    // 先読み中なら真。答えを `stop` か `ponderhit` まで待たせる。
+    let mut pending = false;
+
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        let line = line.trim_end();
+        let command = line.split(' ').next().unwrap_or("");
+        let mut go = false;
+        match command {
+            "usi" => {
+                respond(script.get("usi"), "usiok", &[
+                    "id name ScriptedEngine".to_string(),
+                    "id author muzudho".to_string(),
+                    "option name ScriptFile type filename default <empty>".to_string(),
+                    "usiok".to_string(),
+                ]);
+            },
+            "isready" => { respond(script.get("isready"), "readyok", &["readyok".to_string()]); },
+            "setoption" => {
+                if let Some(path) = line.strip_prefix("setoption name ScriptFile value ") { script = load(path.trim()); }
+            },
+            "position" => {
+                position_line = line["position".len()..].trim().to_string();
+                let mut starts = 0;
+                let (start, moves) = parse_position(&position_line, &mut starts, position_line.len());
+                position = start;
+                for movement in moves.iter() { position.do_move(movement); }
+            },
+            "go" => {
+                let rest = &line[command.len()..];
+                let mut starts = 0;
+                let params = parse_go(rest, &mut starts, rest.len());
+                if params.ponder || params.infinite { pending = true; } else { go = true; }
+            },
+            "stop" | "ponderhit" => {
+                go = pending;
+                pending = false;
+            },
+            "quit" => {
+                respond(script.get("quit"), "", &[]);
+                break;
+            },
+            _ => { respond(script.get(command), "", &[]); },
+        }
+        if go {
+            let lines = script.get(&position_line).or_else(|| script.get("go"));
+            respond(lines, "bestmove", &[default_bestmove(&position)]);
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! ```
+//! ### CSA プロトコルの対局サーバー。ログインした2人を順に対局させて、棋譜を .csa で保存する。
+//! cargo run --bin csa_server -- --port 4081 --time 600 --byoyomi 10
+//! ```
+//!
+//! 先にログインした方が先手。対局が終わったら、もう1局指すにはログインし直す。
+//! 時間の単位は秒で、消費時間は1手ごとに秒未満を切り捨てる。
+//!
+//! # Options.
+//!
+//! * `--port <n>` - 待ち受けるポート。既定は 4081。0 なら空いているポート。
+//! * `--time <s>`, `--byoyomi <s>`, `--inc <s>` - 持ち時間、秒読み、1手ごとの加算。
+//! * `--max-moves <n>` - この手数に達したら引き分け。既定は 256。
+//! * `--sfen <sfen>` - 開始局面。既定は平手初期局面。
+//! * `--games <n>` - この数の対局を終えたら止まる。既定は 0 で、止まらない。
+//! * `--out <dir>` - 棋譜の保存先。既定はカレント・フォルダー。
+//!
+//! # Judgement.
+//!
+//! * 合法手でない手、手番の印や駒名の合わない手は `#ILLEGAL_MOVE` で負け。
+//! * 持ち時間と秒読みを使い切ったら `#TIME_UP` で負け。
+//! * 同じ局面が4回で `#SENNICHITE` の引き分け。王手を掛け続けていれば `#OUTE_SENNICHITE` で掛けた側の負け。
+//! * `%KACHI` は 27点法で判定し、勝ちなら `#JISHOGI`、そうでなければ `#ILLEGAL_MOVE` で負け。
+//! * 手番の側の接続が切れたら `#ABNORMAL` で負け。
+
+extern crate kifuwarabe_usi;
+
+use kifuwarabe_usi::*;
+use kifuwarabe_usi::position::*;
+use kifuwarabe_usi::record::*;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::Path;
+use std::process;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// コマンドラインで指定された設定。
+struct Settings{
+    port : u16,
+    time : u64,
+    byoyomi : u64,
+    inc : u64,
+    max_moves : usize,
+    start : Position,
+    games : u32,
+    out : String,
+}
+
+fn usage(message:&str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("usage: csa_server [--port <n>] [--time <s>] [--byoyomi <s>] [--inc <s>] [--games <n>] [--out <dir>] ...");
+    process::exit(2);
+}
+
+fn parse_args() -> Settings {
+    let mut settings = Settings{
+        port : 4081,
+        time : 0,
+        byoyomi : 0,
+        inc : 0,
+        max_moves : 256,
+        start : Position::startpos(),
+        games : 0,
+        out : ".".to_string(),
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(key) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage(&format!("{} needs a value.", key)));
+        let number = |x:&str| x.parse::<u64>().unwrap_or_else(|_| usage(&format!("{} needs a number.", key)));
+        match key.as_str() {
+            "--port" => { settings.port = number(&value) as u16; },
+            "--time" => { settings.time = number(&value); },
+            "--byoyomi" => { settings.byoyomi = number(&value); },
+            "--inc" => { settings.inc = number(&value); },
+            "--max-moves" => { settings.max_moves = number(&value) as usize; },
+            "--sfen" => {
+                settings.start = Position::from_sfen(&value);
+                if let Err(errors) = settings.start.validate(validation::ValidationRules::Normal) {
+                    usage(&format!("{} is not a valid position: {}", value, errors[0]));
+                }
+            },
+            "--games" => { settings.games = number(&value) as u32; },
+            "--out" => { settings.out = value; },
+            _ => usage(&format!("unknown option {}.", key)),
+        }
+    }
+    if settings.time==0 && settings.byoyomi==0 {
+        usage("set at least one of --time and --byoyomi.");
+    }
+    settings
+}
+
+/// 接続ごとの読み取りスレッドから届く出来事。
+enum Event{
+    Connected(usize, TcpStream),
+    Line(usize, String),
+    Closed(usize),
+}
+
+/// 接続を受け付けて、1行ずつ `sender` へ送るスレッドを接続ごとに立てる。
+fn accept(listener:TcpListener, sender:Sender<Event>) {
+    for (id, stream) in listener.incoming().enumerate() {
+        let stream = match stream { Ok(x) => x, Err(_) => continue };
+        let reader = match stream.try_clone() { Ok(x) => x, Err(_) => continue };
+        if sender.send(Event::Connected(id, stream)).is_err() { break; }
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let line = match line { Ok(x) => x, Err(_) => break };
+                if sender.send(Event::Line(id, line.trim_end().to_string())).is_err() { return; }
+            }
+            let _ = sender.send(Event::Closed(id));
+        });
+    }
+}
+
+/// 接続している相手。`name` はログインしていれば入る。
+struct Client{
+    stream : TcpStream,
+    name : Option<String>,
+}
+
+/// `Game_Summary`。`color` はこれを受け取る側。
+fn game_summary(settings:&Settings, game_id:&str, names:&[String;2], color:Color) -> String {
+    format!("BEGIN Game_Summary\n\
+        Protocol_Version:1.2\n\
+        Protocol_Mode:Server\n\
+        Format:Shogi 1.0\n\
+        Declaration:Jishogi 1.1\n\
+        Game_ID:{}\n\
+        Name+:{}\n\
+        Name-:{}\n\
+        Your_Turn:{}\n\
+        To_Move:{}\n\
+        Max_Moves:{}\n\
+        BEGIN Time\n\
+        Time_Unit:1sec\n\
+        Total_Time:{}\n\
+        Byoyomi:{}\n\
+        Increment:{}\n\
+        Least_Time_Per_Move:0\n\
+        END Time\n\
+        BEGIN Position\n\
+        {}END Position\n\
+        END Game_Summary",
+        game_id, names[0], names[1], color.to_csa(), settings.start.turn.to_csa(), settings.max_moves,
+        settings.time, settings.byoyomi, settings.inc,
+        csa_position(&settings.start))
+}
+
+/// 同じ局面が4回目なら千日手。`history` は開始局面からの、局面のハッシュと手番の側に王手が掛かっているか。
+///
+/// 前の同じ局面から今までの、片方の側の手が全部王手なら、連続王手の千日手でその側の負け。
+fn judge_repetition(history:&[(u64, bool)], turn:Color) -> Option<GameEnding> {
+    let key = history.last()?.0;
+    let same : Vec<usize> = history.iter().enumerate().filter(|x| (x.1).0==key).map(|x| x.0).collect();
+    if same.len()<4 { return None; }
+    let now = history.len() - 1;
+    let since = same[same.len() - 2];
+    // `offset` が 0 なら手番でない側、1 なら手番の側が指した後の局面。
+    let all_checks = |offset:usize| (since + 1..now + 1).filter(|i| (now - i) % 2==offset).all(|i| history[i].1);
+    if all_checks(0) { return Some(GameEnding::PerpetualCheck{ checker : turn.opponent() }); }
+    if all_checks(1) { return Some(GameEnding::PerpetualCheck{ checker : turn }); }
+    Some(GameEnding::Repetition)
+}
+
+struct Server{
+    settings : Settings,
+    events : Receiver<Event>,
+    clients : HashMap<usize, Client>,
+    /// ログインして対局を待っている接続。先に来た順。
+    waiting : Vec<usize>,
+}
+impl Server{
+    /// 1つのメッセージを送る。複数行でもいい。送れなくても、切断の知らせが後で届くので無視する。
+    fn send(&mut self, id:usize, text:&str) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            let _ = writeln!(client.stream, "{}", text);
+        }
+    }
+
+    fn close(&mut self, id:usize) {
+        if let Some(client) = self.clients.remove(&id) {
+            let _ = client.stream.shutdown(Shutdown::Both);
+        }
+        self.waiting.retain(|x| *x!=id);
+    }
+
+    fn name(&self, id:usize) -> String {
+        self.clients.get(&id).and_then(|x| x.name.clone()).unwrap_or_default()
+    }
+
+    /// 対局していない接続の、接続、ログイン、ログアウト、切断。
+    fn lobby(&mut self, event:Event) {
+        match event {
+            Event::Connected(id, stream) => { self.clients.insert(id, Client{ stream, name : None }); },
+            Event::Closed(id) => { self.close(id); },
+            Event::Line(id, line) => {
+                let mut words = line.split_whitespace();
+                match words.next() {
+                    Some("LOGIN") => {
+                        let name = words.next().unwrap_or("").to_string();
+                        let ok = words.next().is_some()
+                            && !name.is_empty()
+                            && self.clients.get(&id).map(|x| x.name.is_none()).unwrap_or(false)
+                            && !self.clients.values().any(|x| x.name.as_ref()==Some(&name));
+                        if ok {
+                            self.send(id, &format!("LOGIN:{} OK", name));
+                            if let Some(client) = self.clients.get_mut(&id) { client.name = Some(name); }
+                            self.waiting.push(id);
+                        } else {
+                            self.send(id, "LOGIN:incorrect");
+                        }
+                    },
+                    Some("LOGOUT") => {
+                        self.send(id, "LOGOUT:completed");
+                        self.close(id);
+                    },
+                    _ => {},
+                }
+            },
+        }
+    }
+
+    /// 対局者の行か切断なら、その側と行。切断なら行は None。対局者でない接続の出来事はロビーで扱う。
+    fn player_event(&mut self, event:Event, players:[usize;2]) -> Option<(Color, Option<String>)> {
+        let (id, line) = match event {
+            Event::Line(id, line) => (id, Some(line)),
+            Event::Closed(id) => (id, None),
+            other => {
+                self.lobby(other);
+                return None;
+            },
+        };
+        match players.iter().position(|x| *x==id).and_then(Color::from_index) {
+            Some(color) => Some((color, line)),
+            None => {
+                self.lobby(match line { Some(line) => Event::Line(id, line), None => Event::Closed(id) });
+                None
+            },
+        }
+    }
+
+    /// 1局指す。`players` は先手、後手の接続。どちらかが `REJECT` するか、始まる前に切れたら None。
+    fn play_game(&mut self, game_id:&str, players:[usize;2]) -> Option<GameRecord> {
+        let names = [self.name(players[0]), self.name(players[1])];
+        for color in Color::all() {
+            let summary = game_summary(&self.settings, game_id, &names, color);
+            self.send(players[color.index()], &summary);
+        }
+
+        let mut agreed = [false;2];
+        while !(agreed[0] && agreed[1]) {
+            let event = self.events.recv().ok()?;
+            let (color, line) = match self.player_event(event, players) { Some(x) => x, None => continue };
+            match line {
+                Some(ref line) if line.starts_with("AGREE") => { agreed[color.index()] = true; },
+                Some(ref line) if !line.starts_with("REJECT") => {},
+                _ => {
+                    let message = format!("REJECT:{} by {}", game_id, names[color.index()]);
+                    for &id in players.iter() {
+                        self.send(id, &message);
+                        self.close(id);
+                    }
+                    return None;
+                },
+            }
+        }
+        for &id in players.iter() { self.send(id, &format!("START:{}", game_id)); }
+
+        let start = self.settings.start;
+        let mut position = start;
+        let mut moves : Vec<RecordedMove> = Vec::new();
+        let mut history = vec![(position.key(), position.in_check(position.turn))];
+        let mut remaining = [self.settings.time; 2];
+        let mut disconnected = [false; 2];
+        let mut started = Instant::now();
+        let (ending, messages) = loop {
+            let color = position.turn;
+            if disconnected[color.index()] { break (GameEnding::IllegalAction, vec!["#ABNORMAL".to_string()]); }
+            // 秒未満は切り捨てるので、持ち時間と秒読みの合計に1秒足りなくなるまで待つ。
+            let allowed = Duration::from_secs(remaining[color.index()] + self.settings.byoyomi + 1);
+            let elapsed = started.elapsed();
+            if allowed<=elapsed { break (GameEnding::TimeUp, vec!["#TIME_UP".to_string()]); }
+            let event = match self.events.recv_timeout(allowed - elapsed) {
+                Ok(x) => x,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => { return None; },
+            };
+            let (sender, line) = match self.player_event(event, players) { Some(x) => x, None => continue };
+            let line = match line {
+                Some(x) => x,
+                // 切れた側は、その手番で負けにする。接続はここで閉じて、名前を次のログインに空ける。
+                None => {
+                    disconnected[sender.index()] = true;
+                    self.close(players[sender.index()]);
+                    continue;
+                },
+            };
+            // 手番でない側の行と、空行（接続の維持）は読み捨てる。
+            if sender!=color || line.is_empty() { continue; }
+
+            let seconds = started.elapsed().as_secs();
+            if remaining[color.index()] + self.settings.byoyomi<seconds { break (GameEnding::TimeUp, vec!["#TIME_UP".to_string()]); }
+            match line.as_str() {
+                "%TORYO" => { break (GameEnding::Resign, vec![format!("%TORYO,T{}", seconds), "#RESIGN".to_string()]); },
+                "%KACHI" => {
+                    let verdict = if position.can_declare_win() { "#JISHOGI" } else { "#ILLEGAL_MOVE" };
+                    let ending = if position.can_declare_win() { GameEnding::Declaration } else { GameEnding::IllegalAction };
+                    break (ending, vec!["%KACHI".to_string(), verdict.to_string()]);
+                },
+                _ if line.starts_with('%') => { continue; },
+                _ => {},
+            }
+
+            // `+7776FU,'コメント` のように、カンマの後ろは指し手ではない。
+            let text = line.split(',').next().unwrap_or("");
+            let movement = match parse_csa_move(&position, text) {
+                Some(movement) if position.legal_moves().contains(&movement) => movement,
+                _ => { break (GameEnding::IllegalAction, vec!["#ILLEGAL_MOVE".to_string()]); },
+            };
+            remaining[color.index()] = remaining[color.index()].saturating_sub(seconds) + self.settings.inc;
+            let echo = format!("{},T{}", text, seconds);
+            for &id in players.iter() { self.send(id, &echo); }
+            moves.push(RecordedMove{ movement, millis : seconds * 1000 });
+            position.do_move(&movement);
+            started = Instant::now();
+
+            history.push((position.key(), position.in_check(position.turn)));
+            if let Some(ending) = judge_repetition(&history, position.turn) {
+                let message = if ending==GameEnding::Repetition { "#SENNICHITE" } else { "#OUTE_SENNICHITE" };
+                break (ending, vec![message.to_string()]);
+            }
+            if self.settings.max_moves<=moves.len() { break (GameEnding::MaxPly, vec!["#MAX_MOVES".to_string()]); }
+        };
+
+        let winner = ending.winner(position.turn);
+        for color in Color::all() {
+            let result = match winner {
+                Some(x) if x==color => "#WIN",
+                Some(_) => "#LOSE",
+                None if ending==GameEnding::MaxPly => "#CENSORED",
+                None => "#DRAW",
+            };
+            let text = format!("{}\n{}", messages.join("\n"), result);
+            self.send(players[color.index()], &text);
+        }
+        // 続けて指すには、ログインし直す。
+        for id in players.iter() {
+            if let Some(client) = self.clients.get_mut(id) { client.name = None; }
+        }
+        Some(GameRecord{ names, start, moves, ending })
+    }
+}
+
+/// 棋譜を `<Game_ID>.csa` に保存する。
+fn save_record(settings:&Settings, game_id:&str, record:&GameRecord) -> io::Result<()> {
+    let dir = Path::new(&settings.out);
+    fs::create_dir_all(dir)?;
+    write_csa(&mut File::create(dir.join(format!("{}.csa", game_id)))?, record)
+}
+
+fn main() {
+    let settings = parse_args();
+    let listener = TcpListener::bind(("0.0.0.0", settings.port)).unwrap_or_else(|e| usage(&format!("cannot listen on port {}: {}", settings.port, e)));
+    let port = listener.local_addr().map(|x| x.port()).unwrap_or(settings.port);
+    println!("listening on port {}", port);
+
+    let (sender, events) = channel();
+    thread::spawn(move || accept(listener, sender));
+    let mut server = Server{ settings, events, clients : HashMap::new(), waiting : Vec::new() };
+
+    let session = SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0);
+    let mut number = 0;
+    let mut played = 0;
+    while server.settings.games==0 || played<server.settings.games {
+        if server.waiting.len()<2 {
+            match server.events.recv() {
+                Ok(event) => { server.lobby(event); },
+                Err(_) => { break; },
+            }
+            continue;
+        }
+        let players = [server.waiting.remove(0), server.waiting.remove(0)];
+        number += 1;
+        let game_id = format!("{}_{}", session, number);
+        let record = match server.play_game(&game_id, players) {
+            Some(record) => record,
+            None => {
+                println!("{}: rejected", game_id);
+                continue;
+            },
+        };
+        played += 1;
+        println!("{}: {} vs {} {:?} after {} plies", game_id, record.names[0], record.names[1], record.ending, record.moves.len());
+        if let Err(e) = save_record(&server.settings, &game_id, &record) {
+            eprintln!("{}: cannot save the record: {}", game_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ハッシュと王手の並びから、千日手の判定。
+    fn judge(keys:&[u64], checks:&[bool], turn:Color) -> Option<GameEnding> {
+        let history : Vec<(u64, bool)> = keys.iter().cloned().zip(checks.iter().cloned()).collect();
+        judge_repetition(&history, turn)
+    }
+
+    #[test]
+    fn repetition_needs_four_times() {
+        let keys = [1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 4, 1];
+        let checks = [false; 13];
+        assert_eq!(judge(&keys[..12], &checks[..12], Color::Gote), None);
+        assert_eq!(judge(&keys, &checks, Color::Sente), Some(GameEnding::Repetition));
+    }
+
+    #[test]
+    fn perpetual_check() {
+        // 後手番の局面。手番でない先手が、直前の4手の中で王手を掛け続けている。
+        let keys = [1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 4, 1];
+        let checks = [false, true, false, true, false, true, false, true, false, true, false, true, false];
+        assert_eq!(judge(&keys, &checks, Color::Sente), Some(GameEnding::PerpetualCheck{ checker : Color::Sente }));
+        let checks = [false, false, true, false, true, false, true, false, true, false, true, false, true];
+        assert_eq!(judge(&keys, &checks, Color::Sente), Some(GameEnding::PerpetualCheck{ checker : Color::Gote }));
+    }
+}
@@ -0,0 +1,391 @@
+//! ```
+//! ### 2つの USI エンジンを何局も戦わせて、勝敗表を出す。
+//! cargo run --bin match -- --engine1 <path> --engine2 <path> --games 100 --byoyomi 1000
+//! ```
+//!
+//! # Options.
+//!
+//! * `--engine1 <path>`, `--engine2 <path>` - エンジンの実行ファイル。
+//! * `--option1 <name=value>`, `--option2 <name=value>` - setoption。何回でも書ける。
+//! * `--games <n>` - 対局数。既定は 2。先後は1局ごとに入れ替える。
+//! * `--openings <file>` - 開始局面。sfen の行か `startpos moves ...` の行が並んだファイルか、`.kif` の棋譜。
+//! * `--opening-plies <n>` - 開始局面の指し手を何手目まで使うか。
+//! * `--time <ms>`, `--byoyomi <ms>`, `--inc <ms>` - 持ち時間、秒読み、1手ごとの加算。
+//! * `--margin <ms>` - 時間切れと判定するまでの猶予。既定は 200。
+//! * `--max-ply <n>` - この手数に達したら引き分け。既定は 256。
+//! * `--format <kif|csa>` - 棋譜の形式。既定は kif。
+//! * `--out <dir>` - 棋譜の保存先。既定はカレント・フォルダー。
+//! * `--sprt <elo0,elo1[,alpha,beta]>` - SPRT の結論が出たら打ち切る。alpha と beta の既定は 0.05。
+
+extern crate kifuwarabe_usi;
+
+use kifuwarabe_usi::*;
+use kifuwarabe_usi::engine_output::*;
+use kifuwarabe_usi::engine_process::*;
+use kifuwarabe_usi::position::*;
+use kifuwarabe_usi::record::*;
+use kifuwarabe_usi::stats::*;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+/// コマンドラインで指定された設定。
+struct Settings{
+    engines : [String;2],
+    options : [Vec<(String, String)>;2],
+    games : u32,
+    openings : Option<String>,
+    opening_plies : Option<usize>,
+    time : u64,
+    byoyomi : u64,
+    inc : u64,
+    margin : u64,
+    max_ply : usize,
+    csa : bool,
+    out : String,
+    sprt : Option<Sprt>,
+}
+
+fn usage(message:&str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("usage: match --engine1 <path> --engine2 <path> [--games <n>] [--openings <file>] [--byoyomi <ms>] ...");
+    process::exit(2);
+}
+
+fn parse_args() -> Settings {
+    let mut settings = Settings{
+        engines : [String::new(), String::new()],
+        options : [Vec::new(), Vec::new()],
+        games : 2,
+        openings : None,
+        opening_plies : None,
+        time : 0,
+        byoyomi : 0,
+        inc : 0,
+        margin : 200,
+        max_ply : 256,
+        csa : false,
+        out : ".".to_string(),
+        sprt : None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(key) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage(&format!("{} needs a value.", key)));
+        let number = |x:&str| x.parse::<u64>().unwrap_or_else(|_| usage(&format!("{} needs a number.", key)));
+        match key.as_str() {
+            "--engine1" => { settings.engines[0] = value; },
+            "--engine2" => { settings.engines[1] = value; },
+            "--option1" | "--option2" => {
+                let index = if key=="--option1" {0} else {1};
+                let mut pair = value.splitn(2, '=');
+                let name = pair.next().unwrap_or("").to_string();
+                let value = pair.next().unwrap_or_else(|| usage("options are written as name=value.")).to_string();
+                settings.options[index].push((name, value));
+            },
+            "--games" => { settings.games = number(&value) as u32; },
+            "--openings" => { settings.openings = Some(value); },
+            "--opening-plies" => { settings.opening_plies = Some(number(&value) as usize); },
+            "--time" => { settings.time = number(&value); },
+            "--byoyomi" => { settings.byoyomi = number(&value); },
+            "--inc" => { settings.inc = number(&value); },
+            "--margin" => { settings.margin = number(&value); },
+            "--max-ply" => { settings.max_ply = number(&value) as usize; },
+            "--format" => {
+                settings.csa = match value.as_str() {
+                    "csa" => true,
+                    "kif" => false,
+                    _ => usage("format is kif or csa."),
+                };
+            },
+            "--out" => { settings.out = value; },
+            "--sprt" => {
+                let numbers : Vec<f64> = value.split(',').map(|x| x.trim().parse().unwrap_or_else(|_| usage("--sprt needs numbers."))).collect();
+                settings.sprt = match numbers.len() {
+                    2 => Some(Sprt::new(numbers[0], numbers[1], 0.05, 0.05)),
+                    4 => Some(Sprt::new(numbers[0], numbers[1], numbers[2], numbers[3])),
+                    _ => usage("--sprt is elo0,elo1 or elo0,elo1,alpha,beta."),
+                };
+            },
+            _ => usage(&format!("unknown option {}.", key)),
+        }
+    }
+    if settings.engines[0].is_empty() || settings.engines[1].is_empty() {
+        usage("--engine1 and --engine2 are required.");
+    }
+    if settings.time==0 && settings.byoyomi==0 && settings.inc==0 {
+        usage("set at least one of --time, --byoyomi and --inc.");
+    }
+    settings
+}
+
+/// 開始局面を読み込む。指定がなければ平手初期局面1つ。
+fn load_openings(settings:&Settings) -> Vec<(Position, Vec<UsiMovement>)> {
+    let path = match settings.openings {
+        Some(ref path) => path,
+        None => { return vec![(Position::startpos(), Vec::new())]; },
+    };
+    let text = fs::read_to_string(path).unwrap_or_else(|e| usage(&format!("cannot read {}: {}", path, e)));
+
+    let mut openings = Vec::new();
+    if path.to_lowercase().ends_with(".kif") {
+        match parse_kif(&text) {
+            Some(opening) => { openings.push(opening); },
+            None => usage(&format!("cannot read {} as a KIF record.", path)),
+        }
+    } else {
+        for line in text.lines() {
+            let mut line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+            if line.starts_with("position ") { line = &line["position ".len()..]; }
+            let line = if line.starts_with("startpos") || line.starts_with("sfen ") {
+                line.to_string()
+            } else {
+                format!("sfen {}", line)
+            };
+            let mut starts = 0;
+            openings.push(parse_position(&line, &mut starts, line.len()));
+        }
+    }
+    if let Some(plies) = settings.opening_plies {
+        for opening in &mut openings { opening.1.truncate(plies); }
+    }
+    if openings.is_empty() { usage(&format!("{} has no positions.", path)); }
+    openings
+}
+
+/// エンジンを起動して、オプションを設定する。
+fn start_engine(settings:&Settings, index:usize) -> io::Result<UsiEngineProcess> {
+    let timeout = Duration::from_secs(30);
+    let mut engine = UsiEngineProcess::spawn(&settings.engines[index], timeout)?;
+    engine.set_option("USI_Ponder", "false")?;
+    for (name, value) in &settings.options[index] {
+        engine.set_option(name, value)?;
+    }
+    Ok(engine)
+}
+
+/// 探索を止めさせて、bestmove を読み捨てる。答えなければ偽。
+fn drain_search(engine:&mut UsiEngineProcess) -> bool {
+    if engine.stop().is_err() { return false; }
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now()<deadline {
+        match engine.recv_event(Some(deadline - Instant::now())) {
+            Ok(EngineEvent::BestMove(_)) => { return true; },
+            Ok(_) => {},
+            Err(_) => { return false; },
+        }
+    }
+    false
+}
+
+/// 1局指す。`players[color]` はその手番を持つエンジンの番号。
+/// 応答しなくなったエンジンは `broken` に印を付ける。
+fn play_game(
+    settings:&Settings,
+    engines:&mut [UsiEngineProcess],
+    players:[usize;2],
+    opening:&(Position, Vec<UsiMovement>),
+    broken:&mut [bool;2],
+) -> GameRecord {
+    let names = [
+        engines[players[0]].id().name.clone(),
+        engines[players[1]].id().name.clone(),
+    ];
+    let start = opening.0;
+    let start_sfen = if start.to_sfen()==Position::startpos().to_sfen() { "startpos".to_string() } else { start.to_sfen() };
+
+    let mut position = start;
+    let mut moves : Vec<RecordedMove> = Vec::new();
+    for movement in &opening.1 {
+        moves.push(RecordedMove{ movement : *movement, millis : 0 });
+        position.do_move(movement);
+    }
+
+    let mut remaining = [settings.time; 2];
+    let ending = loop {
+        if settings.max_ply<=moves.len() { break GameEnding::MaxPly; }
+
+        let color = position.turn;
+        let engine = &mut engines[players[color.index()]];
+        let history : Vec<UsiMovement> = moves.iter().map(|x| x.movement).collect();
+        let params = GoParams{
+            btime : remaining[0],
+            wtime : remaining[1],
+            byoyomi : settings.byoyomi,
+            binc : settings.inc,
+            winc : settings.inc,
+            ..GoParams::default()
+        };
+        if engine.position(&start_sfen, &history).and_then(|_| engine.go(&params)).is_err() {
+            broken[players[color.index()]] = true;
+            break GameEnding::IllegalAction;
+        }
+
+        let allowed = Duration::from_millis(remaining[color.index()] + settings.byoyomi + settings.margin);
+        let started = Instant::now();
+        let bestmove = loop {
+            let elapsed = started.elapsed();
+            let rest = if elapsed<allowed { allowed - elapsed } else { Duration::from_millis(0) };
+            match engine.recv_event(Some(rest)) {
+                Ok(EngineEvent::BestMove(bestmove)) => { break Ok(bestmove); },
+                Ok(_) => {},
+                Err(e) => { break Err(e); },
+            }
+        };
+        let millis = started.elapsed().as_millis() as u64;
+
+        let bestmove = match bestmove {
+            Ok(bestmove) => bestmove,
+            Err(ref e) if e.kind()==io::ErrorKind::TimedOut => {
+                if !drain_search(engine) { broken[players[color.index()]] = true; }
+                break GameEnding::TimeUp;
+            },
+            Err(_) => {
+                broken[players[color.index()]] = true;
+                break GameEnding::IllegalAction;
+            },
+        };
+        if allowed.as_millis() as u64 <= millis { break GameEnding::TimeUp; }
+
+        match bestmove {
+            BestMove::Resign => { break GameEnding::Resign; },
+            BestMove::Win => { break GameEnding::Declaration; },
+            BestMove::Move{ best, .. } => {
+                // 王手放置、二歩、打ち歩詰めなどの反則手は、指した側の負け。
+                if !position.legal_moves().contains(&best) { break GameEnding::IllegalAction; }
+                remaining[color.index()] = remaining[color.index()].saturating_sub(millis) + settings.inc;
+                moves.push(RecordedMove{ movement : best, millis });
+                position.do_move(&best);
+            },
+        }
+    };
+
+    let winner = ending.winner(position.turn);
+    for color in Color::all() {
+        let result = match winner {
+            Some(x) if x==color => "win",
+            Some(_) => "lose",
+            None => "draw",
+        };
+        if !broken[players[color.index()]] { let _ = engines[players[color.index()]].game_over(result); }
+    }
+
+    GameRecord{ names, start, moves, ending }
+}
+
+/// 棋譜を `--format` の形式で保存する。失敗するのは、フォルダーやファイルを作れないときか書けないとき。
+fn save_record(settings:&Settings, number:u32, record:&GameRecord) -> io::Result<()> {
+    let dir = Path::new(&settings.out);
+    fs::create_dir_all(dir)?;
+    if settings.csa {
+        write_csa(&mut File::create(dir.join(format!("game{:04}.csa", number)))?, record)
+    } else {
+        write_kif(&mut File::create(dir.join(format!("game{:04}.kif", number)))?, record)
+    }
+}
+
+fn main() {
+    let settings = parse_args();
+    let openings = load_openings(&settings);
+
+    let mut engines = Vec::new();
+    for index in 0..2 {
+        engines.push(start_engine(&settings, index).unwrap_or_else(|e| usage(&format!("cannot start {}: {}", settings.engines[index], e))));
+    }
+    let names = [engines[0].id().name.clone(), engines[1].id().name.clone()];
+
+    // エンジン1から見た結果。ペアの1局目は、2局目が終わるまで取っておく。
+    let mut stats = MatchStats::new();
+    let mut first_of_pair = None;
+    for number in 0..settings.games {
+        let mut broken = [false;2];
+        for (index, engine) in engines.iter_mut().enumerate() {
+            if engine.is_ready(Duration::from_secs(60)).and_then(|_| engine.new_game()).is_err() {
+                broken[index] = true;
+            }
+        }
+        for index in 0..2 {
+            if broken[index] {
+                engines[index] = start_engine(&settings, index).unwrap_or_else(|e| usage(&format!("cannot restart {}: {}", settings.engines[index], e)));
+                engines[index].is_ready(Duration::from_secs(60)).and_then(|_| engines[index].new_game()).unwrap_or_else(|e| usage(&format!("{} is not ready: {}", settings.engines[index], e)));
+                broken[index] = false;
+            }
+        }
+
+        // 同じ開始局面を、先後を入れ替えて2局ずつ。
+        let opening = &openings[(number / 2) as usize % openings.len()];
+        let players = if number%2==0 { [0, 1] } else { [1, 0] };
+        let record = play_game(&settings, &mut engines, players, opening, &mut broken);
+
+        // `is_multiple_of` は新しいコンパイラにしか無い。
+        #[allow(clippy::manual_is_multiple_of)]
+        let last_turn = if record.moves.len() % 2 == 0 { opening.0.turn } else { opening.0.turn.opponent() };
+        let result = match record.ending.winner(last_turn) {
+            Some(winner) if players[winner.index()]==0 => GameResult::Win,
+            Some(_) => GameResult::Loss,
+            None => GameResult::Draw,
+        };
+        match first_of_pair.take() {
+            Some(first) => { stats.add_pair(first, result); },
+            None if number + 1==settings.games => { stats.add_game(result); },
+            None => { first_of_pair = Some(result); },
+        }
+        println!("game {}: {} vs {} {:?} after {} plies, {}",
+            number + 1, record.names[0], record.names[1], record.ending, record.moves.len(),
+            match result {
+                GameResult::Win => "engine1 wins",
+                GameResult::Draw => "draw",
+                GameResult::Loss => "engine2 wins",
+            });
+        if let Err(e) = save_record(&settings, number + 1, &record) {
+            eprintln!("game {}: cannot save the record: {}", number + 1, e);
+        }
+
+        for index in 0..2 {
+            if broken[index] {
+                engines[index] = start_engine(&settings, index).unwrap_or_else(|e| usage(&format!("cannot restart {}: {}", settings.engines[index], e)));
+            }
+        }
+
+        if let Some(sprt) = settings.sprt {
+            if first_of_pair.is_none() {
+                let decision = sprt.decision(&stats);
+                if decision!=SprtDecision::Continue {
+                    println!("SPRT: {:?} after {} games.", decision, stats.games());
+                    break;
+                }
+            }
+        }
+    }
+
+    // ペアの片割れが残っていれば、1局として数える。
+    if let Some(first) = first_of_pair { stats.add_game(first); }
+
+    let [wins, draws, losses] = stats.wdl;
+    println!();
+    println!("{:<20} {:>6} {:>6} {:>6} {:>7}", "", "win", "draw", "loss", "score");
+    // 1局も指していなければ、得点は五分として出す。
+    let total = (wins + draws + losses) as f64;
+    let percent = |won:u64| if total==0.0 { 50.0 } else { (won as f64 + draws as f64 * 0.5) / total * 100.0 };
+    println!("{:<20} {:>6} {:>6} {:>6} {:>6.1}%", names[0], wins, draws, losses, percent(wins));
+    println!("{:<20} {:>6} {:>6} {:>6} {:>6.1}%", names[1], losses, draws, wins, percent(losses));
+    let elo = stats.elo(0.95);
+    println!("Elo difference: {:+.1} +/- {:.1} (95%), LOS: {:.1}%", elo.elo, elo.error(), stats.los() * 100.0);
+    if 0<stats.pairs() {
+        println!("Pentanomial [0, 0.5, 1, 1.5, 2]: {:?}", stats.pentanomial);
+    }
+    if let Some(sprt) = settings.sprt {
+        let (lower, upper) = sprt.bounds();
+        println!("SPRT elo0={} elo1={} alpha={} beta={}: LLR {:.2} ({:.2}, {:.2}) {:?}",
+            sprt.elo0, sprt.elo1, sprt.alpha, sprt.beta, sprt.llr(&stats), lower, upper, sprt.decision(&stats));
+    }
+
+    for engine in engines {
+        let _ = engine.quit(Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,318 @@
+//! 詰めた指し手☆（＾～＾）
+//!
+//! `UsiMovement` は置換表や定跡や指し手の並びに入れるには大きいので、16bit と 32bit に詰める。
+//! 並びはやねうら王の `Move16` と `Move` に合わせてあるので、その形式の定跡や棋譜をそのまま読み書きできる。
+//!
+//! * 0～6bit - 移動先の升。`Square::index` の 0～80。
+//! * 7～13bit - 移動元の升。打なら打った駒の種類の `piece_code` の番号。
+//! * 14bit - 打なら 1。
+//! * 15bit - 成るなら 1。
+//! * 16～31bit - `Move32` だけ。動かした後の駒（成るなら成った駒）の `piece_code` の番号。
+//!
+//! 移動元と移動先が同じ升の手は無いので、そういう値を「手が無い」「パス」「投了」「入玉宣言」に使う。
+use std::fmt;
+
+use piece_code::{code_to_piece, code_to_piece_type, piece_to_code, piece_type_to_code};
+use position::Position;
+use square::Square;
+use {FILE0, Piece, PieceType, UsiMovement};
+
+const DROP_FLAG : u16 = 1 << 14;
+const PROMOTION_FLAG : u16 = 1 << 15;
+
+/// 筋と段を 0～80 に。
+fn square_index(file:i8, rank:i8) -> u16 {
+    Square::new(file, rank).expect("square out of board").index() as u16
+}
+
+/// 0～80 を筋と段に。7bit の欄には 81～127 も入るが、盤の外なので None。
+fn index_square(index:u16) -> Option<(i8, i8)> {
+    Square::from_index(index as usize).map(|x| (x.file(), x.rank()))
+}
+
+/// USI の升を 0～80 に。
+fn parse_square(text:&str) -> Option<u16> {
+    Square::from_usi(text).map(|x| x.index() as u16)
+}
+
+/// 16bit の指し手。盤面が無くても USI の文字列と行き来できる。
+#[derive(Copy,Clone,Debug,Default,PartialEq,Eq,Hash)]
+pub struct Move16(pub u16);
+impl Move16{
+    /// 手が無い。置換表の空きなど。
+    pub const NONE : Move16 = Move16(0);
+    /// パス。null move。
+    pub const NULL : Move16 = Move16(1 << 7 | 1);
+    /// 投了。
+    pub const RESIGN : Move16 = Move16(2 << 7 | 2);
+    /// 入玉宣言勝ち。
+    pub const WIN : Move16 = Move16(3 << 7 | 3);
+
+    /// 盤上の駒を動かす手。
+    pub fn new_move(source_file:i8, source_rank:i8, destination_file:i8, destination_rank:i8, promotion:bool) -> Move16 {
+        let bits = square_index(destination_file, destination_rank) | square_index(source_file, source_rank) << 7;
+        Move16(if promotion { bits | PROMOTION_FLAG } else { bits })
+    }
+
+    /// 持ち駒を打つ手。
+    pub fn new_drop(pt:PieceType, destination_file:i8, destination_rank:i8) -> Move16 {
+        Move16(square_index(destination_file, destination_rank) | u16::from(piece_type_to_code(pt)) << 7 | DROP_FLAG)
+    }
+
+    /// `UsiMovement` から。移動先が0筋の手は投了にする。
+    pub fn from_usi_movement(movement:&UsiMovement) -> Move16 {
+        if movement.destination_file==FILE0 { return Move16::RESIGN; }
+        match movement.drop {
+            PieceType::Space => Move16::new_move(movement.source_file, movement.source_rank, movement.destination_file, movement.destination_rank, movement.promotion),
+            pt => Move16::new_drop(pt, movement.destination_file, movement.destination_rank),
+        }
+    }
+
+    /// USI の文字列から。`resign`、`win`、`null`、`none` も読む。読めなければ None。
+    pub fn from_usi(text:&str) -> Option<Move16> {
+        match text {
+            "none" => { return Some(Move16::NONE); },
+            "null" => { return Some(Move16::NULL); },
+            "resign" => { return Some(Move16::RESIGN); },
+            "win" => { return Some(Move16::WIN); },
+            _ => {},
+        }
+        let bytes = text.as_bytes();
+        if 4<=bytes.len() && bytes[1]==b'*' {
+            let pt = match bytes[0] {
+                b'R' => PieceType::R,
+                b'B' => PieceType::B,
+                b'G' => PieceType::G,
+                b'S' => PieceType::S,
+                b'N' => PieceType::N,
+                b'L' => PieceType::L,
+                b'P' => PieceType::P,
+                _ => { return None; },
+            };
+            if bytes.len()!=4 { return None; }
+            let destination = parse_square(&text[2..])?;
+            return Some(Move16(destination | u16::from(piece_type_to_code(pt)) << 7 | DROP_FLAG));
+        }
+        let promotion = match bytes.len() {
+            4 => false,
+            5 if bytes[4]==b'+' => true,
+            _ => { return None; },
+        };
+        let source = parse_square(text.get(0..2)?)?;
+        let destination = parse_square(text.get(2..4)?)?;
+        if source==destination { return None; }
+        let bits = destination | source << 7;
+        Some(Move16(if promotion { bits | PROMOTION_FLAG } else { bits }))
+    }
+
+    /// 手が無い、パス、投了、入玉宣言なら真。
+    pub fn is_special(self) -> bool {
+        !self.is_drop() && self.source_index()==self.destination_index()
+    }
+
+    pub fn is_drop(self) -> bool {
+        self.0 & DROP_FLAG!=0
+    }
+
+    pub fn is_promotion(self) -> bool {
+        self.0 & PROMOTION_FLAG!=0
+    }
+
+    fn source_index(self) -> u16 {
+        self.0 >> 7 & 0x7f
+    }
+
+    fn destination_index(self) -> u16 {
+        self.0 & 0x7f
+    }
+
+    /// 移動元の筋と段。打か、升の番号が盤の外なら None。
+    pub fn source(self) -> Option<(i8, i8)> {
+        if self.is_drop() { None } else { index_square(self.source_index()) }
+    }
+
+    /// 移動先の筋と段。升の番号が盤の外なら None。
+    pub fn destination(self) -> Option<(i8, i8)> {
+        index_square(self.destination_index())
+    }
+
+    /// 打った駒の種類。打でなければ `PieceType::Space`。
+    pub fn dropped_piece_type(self) -> PieceType {
+        if !self.is_drop() { return PieceType::Space; }
+        match code_to_piece_type(self.source_index() as u8) {
+            Some(PieceType::K) | None => PieceType::Space,
+            Some(pt) => pt,
+        }
+    }
+
+    /// `UsiMovement` にする。投了は移動先を0筋にする。手が無い、パス、入玉宣言は `UsiMovement` で表せないので None。
+    ///
+    /// 壊れた定跡や置換表から読んだ値で、升の番号が盤の外だったり、打った駒が持ち駒にできない駒だったり、
+    /// 打つ手に成りが付いていたりすれば、手が無いものとして None。
+    pub fn to_usi_movement(self) -> Option<UsiMovement> {
+        if self==Move16::RESIGN {
+            return Some(UsiMovement{ destination_file : FILE0, ..UsiMovement::default() });
+        }
+        if self.is_special() { return None; }
+        let (destination_file, destination_rank) = self.destination()?;
+        let (source_file, source_rank) = if self.is_drop() {
+            if self.dropped_piece_type()==PieceType::Space || self.is_promotion() { return None; }
+            (0, 0)
+        } else {
+            self.source()?
+        };
+        Some(UsiMovement{
+            source_file,
+            source_rank,
+            drop : self.dropped_piece_type(),
+            destination_file,
+            destination_rank,
+            promotion : self.is_promotion(),
+        })
+    }
+}
+impl fmt::Display for Move16{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Move16::NONE => { return write!(f, "none"); },
+            Move16::NULL => { return write!(f, "null"); },
+            Move16::RESIGN => { return write!(f, "resign"); },
+            Move16::WIN => { return write!(f, "win"); },
+            _ => {},
+        }
+        // 盤の外の升などは、手が無いのと同じに書く。
+        match self.to_usi_movement() {
+            Some(movement) => write!(f, "{}", movement),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+/// 32bit の指し手。`Move16` の上に、動かした後の駒を持つ。
+#[derive(Copy,Clone,Debug,Default,PartialEq,Eq,Hash)]
+pub struct Move32(pub u32);
+impl Move32{
+    pub const NONE : Move32 = Move32(Move16::NONE.0 as u32);
+    pub const NULL : Move32 = Move32(Move16::NULL.0 as u32);
+    pub const RESIGN : Move32 = Move32(Move16::RESIGN.0 as u32);
+    pub const WIN : Move32 = Move32(Move16::WIN.0 as u32);
+
+    /// `movement` に、動かした後の駒 `moved` を付ける。
+    pub fn new(movement:Move16, moved:Piece) -> Move32 {
+        Move32(u32::from(movement.0) | u32::from(piece_to_code(moved)) << 16)
+    }
+
+    /// `position` で `movement` を指したときの `Move32`。合法手かどうかは調べない。
+    pub fn from_usi_movement(position:&Position, movement:&UsiMovement) -> Move32 {
+        let move16 = Move16::from_usi_movement(movement);
+        if move16.is_special() { return Move32(u32::from(move16.0)); }
+        let moved = match movement.drop {
+            PieceType::Space => {
+                let pc = position.piece(movement.source_file, movement.source_rank);
+                if movement.promotion { Piece::from(pc.color(), pc.piece_type().promote()) } else { pc }
+            },
+            pt => Piece::from(position.turn, pt),
+        };
+        Move32::new(move16, moved)
+    }
+
+    /// 下の 16bit。
+    pub fn to_move16(self) -> Move16 {
+        Move16(self.0 as u16)
+    }
+
+    /// 動かした後の駒。特別な手なら空マス。
+    pub fn moved_piece(self) -> Piece {
+        code_to_piece((self.0 >> 16) as u8).unwrap_or(Piece::Space)
+    }
+
+    /// `Move16::to_usi_movement` と同じ。
+    pub fn to_usi_movement(self) -> Option<UsiMovement> {
+        self.to_move16().to_usi_movement()
+    }
+}
+impl From<Move32> for Move16{
+    fn from(movement:Move32) -> Move16 {
+        movement.to_move16()
+    }
+}
+impl fmt::Display for Move32{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_move16())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaneuraou_layout() {
+        // 7g は (7-1)*9+(7-1) = 60、7f は 59、5e は 40。歩の番号は 1。
+        assert_eq!(Move16::from_usi("7g7f"), Some(Move16(59 | 60 << 7)));
+        assert_eq!(Move16::from_usi("8h2b+"), Some(Move16((9 + 1) | (63 + 7) << 7 | PROMOTION_FLAG)));
+        assert_eq!(Move16::from_usi("P*5e"), Some(Move16(40 | 1 << 7 | DROP_FLAG)));
+    }
+
+    #[test]
+    fn usi_round_trip() {
+        for text in ["7g7f", "8h2b+", "P*5e", "R*1a", "none", "null", "resign", "win"].iter() {
+            assert_eq!(Move16::from_usi(text).unwrap().to_string(), *text);
+        }
+        for text in ["", "7g7g", "K*5e", "P*5e+", "7g7f=", "0a1b", "7g7"].iter() {
+            assert_eq!(Move16::from_usi(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn usi_movement_round_trip() {
+        let mut position = Position::from_sfen("lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b Pp 1");
+        for _ in 0..2 {
+            for movement in position.legal_moves().iter() {
+                let move16 = Move16::from_usi_movement(movement);
+                assert_eq!(move16.to_usi_movement(), Some(*movement));
+                let move32 = Move32::from_usi_movement(&position, movement);
+                assert_eq!(move32.to_move16(), move16);
+                assert_eq!(move32.to_usi_movement(), Some(*movement));
+            }
+            let first = position.legal_moves()[0];
+            position.do_move(&first);
+        }
+    }
+
+    #[test]
+    fn moved_piece() {
+        let position = Position::startpos();
+        let mut starts = 0;
+        let (_, movement) = ::parse_movement("8h2b+", &mut starts, 5);
+        assert_eq!(Move32::from_usi_movement(&position, &movement).moved_piece(), Piece::PB0);
+        assert_eq!(Move32::RESIGN.moved_piece(), Piece::Space);
+    }
+
+    #[test]
+    fn special_moves() {
+        assert_eq!(Move16::NONE.to_usi_movement(), None);
+        assert_eq!(Move16::NULL.to_usi_movement(), None);
+        assert_eq!(Move16::WIN.to_usi_movement(), None);
+        assert_eq!(Move16::RESIGN.to_usi_movement().map(|x| x.destination_file), Some(FILE0));
+        assert_eq!(Move16::from_usi_movement(&UsiMovement{ destination_file : FILE0, ..UsiMovement::default() }), Move16::RESIGN);
+    }
+
+    #[test]
+    fn out_of_range_values() {
+        // 移動先、移動元が 81～127。
+        for &bits in [81, 127, 40 | 81 << 7, 40 | 127 << 7, 127 | 127 << 7 | PROMOTION_FLAG].iter() {
+            let movement = Move16(bits);
+            assert_eq!(movement.to_usi_movement(), None, "{:#x}", bits);
+            assert_eq!(movement.to_string(), "none");
+        }
+        assert_eq!(Move16(81 | 40 << 7).destination(), None);
+        assert_eq!(Move16(40 | 81 << 7).source(), None);
+        // 玉や、番号の無い駒を打つ手。成りの付いた打。
+        for &bits in [40 | 8 << 7 | DROP_FLAG, 40 | 100 << 7 | DROP_FLAG, 40 | 1 << 7 | DROP_FLAG | PROMOTION_FLAG].iter() {
+            assert_eq!(Move16(bits).to_usi_movement(), None, "{:#x}", bits);
+            assert_eq!(Move16(bits).to_string(), "none");
+        }
+        assert_eq!(Move32(u32::from(Move16(127).0) | 0xffff << 16).moved_piece(), Piece::Space);
+    }
+}
@@ -0,0 +1,85 @@
+//! 入玉宣言☆（＾～＾） CSA の `Declaration:Jishogi 1.1`（27点法）で、宣言した側が勝ちか判定する。
+//!
+//! 手番の側が宣言して、次の全部を満たせば勝ち。
+//!
+//! * 玉が敵陣（前から3段）にいる。
+//! * 玉に王手が掛かっていない。
+//! * 敵陣にいる、玉以外の自分の駒が 10 枚以上。
+//! * 敵陣にいる玉以外の自分の駒と持ち駒を、飛角（竜馬）5点、それ以外1点で数えて、先手 28点、後手 27点以上。
+//!
+//! 持ち時間が残っているかどうかは、呼ぶ側で調べる。
+use movegen::is_promotion_zone;
+use position::Position;
+use {FILE0, FILE10, RANK1, RANK10};
+use {Color, PieceType};
+
+/// 宣言の点数。飛角と竜馬は5点、それ以外は1点。
+fn declaration_points(pt:PieceType) -> u32 {
+    match pt.demote() {
+        PieceType::R | PieceType::B => 5,
+        _ => 1,
+    }
+}
+
+impl Position{
+    /// 手番の側が入玉宣言して勝てるなら真。
+    pub fn can_declare_win(&self) -> bool {
+        let color = self.turn;
+        let king = match self.king_square(color) {
+            Some(x) => x,
+            None => { return false; },
+        };
+        if !is_promotion_zone(color, king.1) || self.in_check(color) { return false; }
+
+        let mut pieces = 0;
+        let mut points = 0;
+        for file in FILE0+1..FILE10 {
+            for rank in (RANK1..RANK10).filter(|x| is_promotion_zone(color, *x)) {
+                let pc = self.piece(file, rank);
+                if pc.is_space() || pc.color()!=color || pc.piece_type()==PieceType::K { continue; }
+                pieces += 1;
+                points += declaration_points(pc.piece_type());
+            }
+        }
+        for (pt, count) in self.hand[color.index()].iter() {
+            points += declaration_points(pt) * count as u32;
+        }
+        let required = if color==Color::Sente { 28 } else { 27 };
+        10<=pieces && required<=points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sente_needs_28_points() {
+        // 飛角4枚で 20点、金銀8枚で 8点。
+        assert!(Position::from_sfen("RB1K1BR2/GGGG5/SSSS5/9/9/9/9/9/4k4 b - 1").can_declare_win());
+        // 銀が1枚少なくて 27点。持ち駒の歩で 28点。
+        assert!(!Position::from_sfen("RB1K1BR2/GGGG5/SSS6/9/9/9/9/9/4k4 b - 1").can_declare_win());
+        assert!(Position::from_sfen("RB1K1BR2/GGGG5/SSS6/9/9/9/9/9/4k4 b P 1").can_declare_win());
+    }
+
+    #[test]
+    fn gote_needs_27_points() {
+        assert!(Position::from_sfen("4K4/9/9/9/9/9/6sss/5gggg/2rb1k1br w - 1").can_declare_win());
+        assert!(!Position::from_sfen("4K4/9/9/9/9/9/7ss/5gggg/2rb1k1br w - 1").can_declare_win());
+    }
+
+    #[test]
+    fn needs_ten_pieces_in_the_camp() {
+        // 点数は足りるが、敵陣の駒が9枚。
+        assert!(!Position::from_sfen("RB1K1BR2/GGGG5/S8/9/9/9/9/9/4k4 b 3SP 1").can_declare_win());
+    }
+
+    #[test]
+    fn king_must_be_in_the_camp_and_not_in_check() {
+        assert!(!Position::from_sfen("RB3BR2/GGGG5/SSSS5/3K5/9/9/9/9/4k4 b - 1").can_declare_win());
+        // ６一の玉の隣、５一に後手の飛車。
+        assert!(!Position::from_sfen("RB1Kr1BR1/GGGG5/SSSS5/9/9/9/9/9/4k4 b - 1").can_declare_win());
+        // 手番でない側の宣言は数えない。
+        assert!(!Position::from_sfen("RB1K1BR2/GGGG5/SSSS5/9/9/9/9/9/4k4 w - 1").can_declare_win());
+    }
+}
@@ -0,0 +1,327 @@
+//! 局面図☆（＾～＾） 盤を文字で描く。
+//!
+//! * `to_ascii` - sfen の駒の文字で描いた盤。ログ向け。
+//! * `to_unicode` - ☗☖ と漢字で描いた盤。
+//! * `to_bod` - KIF の局面図（BOD）。`parse_bod` で読み戻せる。
+//!
+//! BOD は次のような形。後手の駒には `v` を付け、空マスは `・`。
+//!
+//! ```text
+//! 後手の持駒：なし
+//!   ９ ８ ７ ６ ５ ４ ３ ２ １
+//! +---------------------------+
+//! |v香v桂v銀v金v玉v金v銀v桂v香|一
+//! | ・v飛 ・ ・ ・ ・ ・v角 ・|二
+//! …
+//! |香 桂 銀 金 玉 金 銀 桂 香|九
+//! +---------------------------+
+//! 先手の持駒：なし
+//! 先手番
+//! ```
+use hand::Hand;
+use position::Position;
+use record::{num_to_kanji, num_to_zenkaku};
+use validation::PIECE_TYPE_TOTALS;
+use {FILE0, FILE10, RANK1, RANK10};
+use {Color, Piece, PieceType};
+use {file_rank_to_cell, num_to_lower_case};
+
+/// BOD の駒名。どれも1文字。
+pub fn bod_piece_name(pt:PieceType) -> &'static str {
+    use PieceType::*;
+    match pt {
+        K => "玉",
+        R => "飛",
+        B => "角",
+        G => "金",
+        S => "銀",
+        N => "桂",
+        L => "香",
+        P => "歩",
+        PR => "龍",
+        PB => "馬",
+        PS => "全",
+        PN => "圭",
+        PL => "杏",
+        PP => "と",
+        Space | Num => "・",
+    }
+}
+
+/// BOD の駒名から駒種類。`王` と `竜` も読む。
+fn parse_bod_piece_name(c:char) -> Option<PieceType> {
+    use PieceType::*;
+    match c {
+        '玉' | '王' => Some(K),
+        '飛' => Some(R),
+        '角' => Some(B),
+        '金' => Some(G),
+        '銀' => Some(S),
+        '桂' => Some(N),
+        '香' => Some(L),
+        '歩' => Some(P),
+        '龍' | '竜' => Some(PR),
+        '馬' => Some(PB),
+        '全' => Some(PS),
+        '圭' => Some(PN),
+        '杏' => Some(PL),
+        'と' => Some(PP),
+        _ => None,
+    }
+}
+
+/// 1～18 の漢数字。`十八` のように書く。
+pub fn count_to_kanji(count:i8) -> String {
+    match count {
+        1..=9 => num_to_kanji(count).to_string(),
+        10 => "十".to_string(),
+        _ => format!("十{}", num_to_kanji(count - 10)),
+    }
+}
+
+/// `count_to_kanji` の逆。
+fn parse_kanji_count(text:&str) -> Option<i8> {
+    let digit = |c:char| "一二三四五六七八九".chars().position(|x| x==c).map(|x| x as i8 + 1);
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('十'), None, None) => Some(10),
+        (Some('十'), Some(c), None) => Some(10 + digit(c)?),
+        (Some(c), None, None) => digit(c),
+        _ => None,
+    }
+}
+
+/// 持ち駒を `飛　角　金二　歩十八` のように。無ければ `なし`。
+pub fn hand_to_kanji(hand:Hand) -> String {
+    if hand.is_empty() { return "なし".to_string(); }
+    let mut text = String::new();
+    for (pt, count) in hand.iter() {
+        if !text.is_empty() { text.push('　'); }
+        text.push_str(bod_piece_name(pt));
+        if 1<count { text.push_str(&count_to_kanji(count)); }
+    }
+    text
+}
+
+/// `hand_to_kanji` の逆。空白区切り。`なし` や空なら持ち駒なし。
+fn parse_hand_kanji(text:&str) -> Option<Hand> {
+    let mut hand = Hand::EMPTY;
+    for word in text.split([' ', '　']).filter(|x| !x.is_empty() && *x!="なし") {
+        let mut chars = word.chars();
+        let pt = parse_bod_piece_name(chars.next()?)?;
+        let rest = chars.as_str();
+        let count = if rest.is_empty() { 1 } else { parse_kanji_count(rest)? };
+        if pt==PieceType::K { return None; }
+        let &(_, max) = PIECE_TYPE_TOTALS.iter().find(|x| x.0==pt)?;
+        if max<hand.count(pt) + count { return None; }
+        hand.set(pt, hand.count(pt) + count);
+    }
+    Some(hand)
+}
+
+/// sfen の文字で描いた盤。後手の持ち駒、盤、先手の持ち駒、手番の順。
+///
+/// ```text
+///   9  8  7  6  5  4  3  2  1
+/// +---------------------------+
+/// | l  n  s  g  k  g  s  n  l | a
+/// | .  r  .  .  .  .  .  b  . | b
+/// ```
+pub fn to_ascii(position:&Position) -> String {
+    let mut text = format!("gote hand: {}\n", hand_or_dash(position.hand[Color::Gote.index()].to_sfen(Color::Gote)));
+    for file in (FILE0+1..FILE10).rev() { text.push_str(&format!("  {}", file)); }
+    text.push('\n');
+    text.push_str("+---------------------------+\n");
+    for rank in RANK1..RANK10 {
+        text.push('|');
+        for file in (FILE0+1..FILE10).rev() {
+            let pc = position.piece(file, rank);
+            text.push_str(&format!("{:>2} ", if pc.is_space() { "." } else { pc.to_sfen() }));
+        }
+        text.push_str(&format!("| {}\n", num_to_lower_case(rank)));
+    }
+    text.push_str("+---------------------------+\n");
+    text.push_str(&format!("sente hand: {}\n", hand_or_dash(position.hand[Color::Sente.index()].to_sfen(Color::Sente))));
+    text.push_str(&format!("turn: {}, ply: {}\n", position.turn.to_usi(), position.ply));
+    text
+}
+
+fn hand_or_dash(text:String) -> String {
+    if text.is_empty() { "-".to_string() } else { text }
+}
+
+/// ☗☖ と漢字で描いた盤。駒は `☖香`、空マスは `　・`。
+pub fn to_unicode(position:&Position) -> String {
+    let mut text = format!("☖持駒：{}\n", hand_to_kanji(position.hand[Color::Gote.index()]));
+    for file in (FILE0+1..FILE10).rev() {
+        text.push('　');
+        text.push_str(num_to_zenkaku(file));
+    }
+    text.push('\n');
+    for rank in RANK1..RANK10 {
+        for file in (FILE0+1..FILE10).rev() {
+            let pc = position.piece(file, rank);
+            if pc.is_space() {
+                text.push_str("　・");
+            } else {
+                text.push(if pc.color()==Color::Sente { '☗' } else { '☖' });
+                text.push_str(bod_piece_name(pc.piece_type()));
+            }
+        }
+        text.push_str(num_to_kanji(rank));
+        text.push('\n');
+    }
+    text.push_str(&format!("☗持駒：{}\n", hand_to_kanji(position.hand[Color::Sente.index()])));
+    text.push_str(&format!("{}番\n", if position.turn==Color::Sente { "☗" } else { "☖" }));
+    text
+}
+
+/// KIF の局面図（BOD）。2手目以降の局面なら、指した手数を `手数＝` の行に書く。
+pub fn to_bod(position:&Position) -> String {
+    let mut text = format!("後手の持駒：{}\n", hand_to_kanji(position.hand[Color::Gote.index()]));
+    text.push_str("  ９ ８ ７ ６ ５ ４ ３ ２ １\n");
+    text.push_str("+---------------------------+\n");
+    for rank in RANK1..RANK10 {
+        text.push('|');
+        for file in (FILE0+1..FILE10).rev() {
+            let pc = position.piece(file, rank);
+            text.push(if !pc.is_space() && pc.color()==Color::Gote { 'v' } else { ' ' });
+            text.push_str(bod_piece_name(pc.piece_type()));
+        }
+        text.push('|');
+        text.push_str(num_to_kanji(rank));
+        text.push('\n');
+    }
+    text.push_str("+---------------------------+\n");
+    text.push_str(&format!("先手の持駒：{}\n", hand_to_kanji(position.hand[Color::Sente.index()])));
+    if 1<position.ply { text.push_str(&format!("手数＝{}\n", position.ply - 1)); }
+    text.push_str(if position.turn==Color::Sente { "先手番\n" } else { "後手番\n" });
+    text
+}
+
+/// 盤の1段。`|v香v桂 ・…|一` の、縦線の間の 9 マス。
+fn parse_bod_rank(line:&str) -> Option<[Piece; 9]> {
+    let mut pieces = [Piece::Space; 9];
+    let mut chars = line.strip_prefix('|')?.chars();
+    for piece in pieces.iter_mut() {
+        let color = match chars.next()? {
+            'v' => Color::Gote,
+            ' ' | '　' | '^' => Color::Sente,
+            _ => { return None; },
+        };
+        let c = chars.next()?;
+        if c!='・' { *piece = Piece::from(color, parse_bod_piece_name(c)?); }
+    }
+    if chars.next()!=Some('|') { return None; }
+    Some(pieces)
+}
+
+/// 局面図（BOD）を読み取る。KIF の棋譜の中に書かれていてもよく、前後の行は読み飛ばす。
+///
+/// 駒落ちの `上手の持駒`、`下手の持駒`、`上手番`、`下手番` も読む。
+/// 手番の行が無ければ先手番。盤の段が 9 段揃わなければ None。
+pub fn parse_bod(text:&str) -> Option<Position> {
+    let mut position = Position::startpos();
+    position.board = [Piece::Space; 100];
+    let mut rank = RANK1;
+    for line in text.lines() {
+        let line = line.trim_end();
+        let hand_line = |prefix:&str| line.strip_prefix(prefix)
+            .and_then(|x| x.strip_prefix("の持駒"))
+            .map(|x| x.trim_start_matches(['：', ':']));
+        if let Some(rest) = hand_line("後手").or_else(|| hand_line("上手")) {
+            position.hand[Color::Gote.index()] = parse_hand_kanji(rest)?;
+        } else if let Some(rest) = hand_line("先手").or_else(|| hand_line("下手")) {
+            position.hand[Color::Sente.index()] = parse_hand_kanji(rest)?;
+        } else if line.starts_with('|') && rank<RANK10 {
+            for (i, pc) in parse_bod_rank(line)?.iter().enumerate() {
+                position.board[file_rank_to_cell(9 - i as i8, rank)] = *pc;
+            }
+            rank += 1;
+        } else if let Some(rest) = line.strip_prefix("手数＝").or_else(|| line.strip_prefix("手数=")) {
+            let digits : String = rest.chars().take_while(|x| x.is_ascii_digit()).collect();
+            position.ply = digits.parse::<u32>().ok()? + 1;
+        } else if line=="後手番" || line=="上手番" {
+            position.turn = Color::Gote;
+        } else if line=="先手番" || line=="下手番" {
+            position.turn = Color::Sente;
+        }
+    }
+    if rank<RANK10 { return None; }
+    Some(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(sfen:&str) {
+        let position = Position::from_sfen(sfen);
+        let bod = to_bod(&position);
+        assert_eq!(parse_bod(&bod).map(|x| x.to_sfen()), Some(position.to_sfen()), "{}", bod);
+    }
+
+    #[test]
+    fn bod_round_trip() {
+        round_trip("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1");
+        // 後手番、成り駒、2手目以降。
+        round_trip("ln1g1g1nl/1r1sk2+B1/p1pppp1pp/6p2/1p7/2P6/PP1PPPPPP/7R1/LNSGKGSNL w Bs 12");
+        // 10枚以上の持ち駒。
+        round_trip("4k4/9/9/9/9/9/9/9/4K4 b 2G10Prbgsnl8p 1");
+    }
+
+    #[test]
+    fn bod_layout() {
+        let bod = to_bod(&Position::startpos());
+        let lines : Vec<&str> = bod.lines().collect();
+        assert_eq!(lines[0], "後手の持駒：なし");
+        assert_eq!(lines[3], "|v香v桂v銀v金v玉v金v銀v桂v香|一");
+        assert_eq!(lines[4], "| ・v飛 ・ ・ ・ ・ ・v角 ・|二");
+        assert_eq!(lines[14], "先手番");
+        assert!(!bod.contains("手数"));
+    }
+
+    #[test]
+    fn parse_handicap_bod() {
+        // 駒落ちの上手、下手。`王` と `竜` も読む。前後の行は読み飛ばす。
+        let text = "開始日時：2024/01/01\n\
+            上手の持駒：金二\n\
+            \x20 ９ ８ ７ ６ ５ ４ ３ ２ １\n\
+            +---------------------------+\n\
+            | ・ ・ ・ ・v王 ・ ・ ・ ・|一\n\
+            | ・ ・ ・ ・ ・ ・ ・ ・ ・|二\n\
+            | ・ ・ ・ ・ ・ ・ ・ ・ ・|三\n\
+            | ・ ・ ・ ・ ・ ・ ・ ・ ・|四\n\
+            | ・ ・ ・ ・ ・ ・ ・ ・ ・|五\n\
+            | ・ ・ ・ ・ ・ ・ ・ ・ ・|六\n\
+            | ・ ・ ・ ・ ・ ・ ・ ・ ・|七\n\
+            | ・ 竜 ・ ・ ・ ・ ・ ・ ・|八\n\
+            | ・ ・ ・ ・ 玉 ・ ・ ・ ・|九\n\
+            +---------------------------+\n\
+            下手の持駒：歩十八\n\
+            上手番\n\
+            手数＝1  ▲７六歩  まで\n";
+        let position = parse_bod(text).unwrap();
+        assert_eq!(position.to_sfen(), "4k4/9/9/9/9/9/9/1+R7/4K4 w 18P2g 2");
+    }
+
+    #[test]
+    fn rejects_broken_bod() {
+        let bod = to_bod(&Position::startpos());
+        // 段が足りない。
+        let short : String = bod.lines().filter(|x| !x.ends_with('九')).map(|x| format!("{}\n", x)).collect();
+        assert!(parse_bod(&short).is_none());
+        // 読めない駒。
+        assert!(parse_bod(&bod.replace("v角", "v象")).is_none());
+        // 持ち駒が多すぎる。
+        assert!(parse_bod(&bod.replace("先手の持駒：なし", "先手の持駒：飛三")).is_none());
+    }
+
+    #[test]
+    fn ascii_header_matches_columns() {
+        let ascii = to_ascii(&Position::startpos());
+        let lines : Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines[1], "  9  8  7  6  5  4  3  2  1");
+        assert_eq!(lines[3], "| l  n  s  g  k  g  s  n  l | a");
+    }
+}
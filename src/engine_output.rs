@@ -0,0 +1,448 @@
+//! エンジンが GUI へ送る行の字句解析☆（＾～＾）
+//!
+//! `parse_movement` の逆向きで、`bestmove`、`info`、`option`、`id`、`checkmate` を読む。
+//! どの関数も `starts` の位置にキーワードがある1行を受け取る。読めなければ None。
+use std::fmt;
+use std::str::FromStr;
+
+use parse_movement;
+use UsiMovement;
+
+/// `bestmove` の中身。
+#[derive(Copy,Clone,Debug)]
+pub enum BestMove{
+    /// 指し手。`ponder` があれば、相手の予想手。
+    Move{ best : UsiMovement, ponder : Option<UsiMovement> },
+    /// `bestmove resign`。
+    Resign,
+    /// `bestmove win`。入玉宣言勝ち。
+    Win,
+}
+impl fmt::Display for BestMove{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BestMove::Move{ best, ponder : Some(ponder) } => { write!(f,"bestmove {} ponder {}", best, ponder) },
+            BestMove::Move{ best, ponder : None } => { write!(f,"bestmove {}", best) },
+            BestMove::Resign => { write!(f,"bestmove resign") },
+            BestMove::Win => { write!(f,"bestmove win") },
+        }
+    }
+}
+
+/// `score` の値。
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum Score{
+    /// `score cp <x>`。
+    Cp(i32),
+    /// `score mate <x>`。負なら詰まされる手数。
+    Mate(i32),
+    /// `score mate +`。手数は分からないが詰ます。
+    MatePlus,
+    /// `score mate -`。手数は分からないが詰まされる。
+    MateMinus,
+}
+impl fmt::Display for Score{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Score::Cp(x) => { write!(f,"cp {}", x) },
+            Score::Mate(x) => { write!(f,"mate {}", x) },
+            Score::MatePlus => { write!(f,"mate +") },
+            Score::MateMinus => { write!(f,"mate -") },
+        }
+    }
+}
+
+/// `score` の後ろに付く境界。
+#[derive(Copy,Clone,Debug,Default,PartialEq)]
+pub enum ScoreBound{
+    /// 何も付かない。
+    #[default]
+    Exact,
+    /// `lowerbound`。
+    Lower,
+    /// `upperbound`。
+    Upper,
+}
+
+/// `info` 1行分。書かれていない項目は None か空。
+#[derive(Clone,Debug,Default)]
+pub struct Info{
+    pub depth : Option<u32>,
+    pub seldepth : Option<u32>,
+    /// ミリ秒。
+    pub time : Option<u64>,
+    pub nodes : Option<u64>,
+    pub nps : Option<u64>,
+    /// 千分率。
+    pub hashfull : Option<u32>,
+    pub multipv : Option<u32>,
+    pub currmove : Option<UsiMovement>,
+    pub score : Option<Score>,
+    pub bound : ScoreBound,
+    pub pv : Vec<UsiMovement>,
+    /// `string` から行末まで。
+    pub string : Option<String>,
+}
+impl fmt::Display for Info{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f,"info")?;
+        if let Some(x) = self.depth { write!(f," depth {}", x)?; }
+        if let Some(x) = self.seldepth { write!(f," seldepth {}", x)?; }
+        if let Some(x) = self.time { write!(f," time {}", x)?; }
+        if let Some(x) = self.nodes { write!(f," nodes {}", x)?; }
+        if let Some(x) = self.nps { write!(f," nps {}", x)?; }
+        if let Some(x) = self.hashfull { write!(f," hashfull {}", x)?; }
+        if let Some(x) = self.multipv { write!(f," multipv {}", x)?; }
+        if let Some(x) = self.currmove { write!(f," currmove {}", x)?; }
+        if let Some(x) = self.score {
+            write!(f," score {}", x)?;
+            match self.bound {
+                ScoreBound::Exact => {},
+                ScoreBound::Lower => { write!(f," lowerbound")?; },
+                ScoreBound::Upper => { write!(f," upperbound")?; },
+            }
+        }
+        if !self.pv.is_empty() {
+            write!(f," pv")?;
+            for movement in &self.pv { write!(f," {}", movement)?; }
+        }
+        // string は行末まで読まれるので、必ず最後。
+        if let Some(ref x) = self.string { write!(f," string {}", x)?; }
+        Ok(())
+    }
+}
+
+/// `checkmate` の中身。
+#[derive(Clone,Debug)]
+pub enum Checkmate{
+    /// 詰み手順。
+    Mate(Vec<UsiMovement>),
+    /// `checkmate nomate`。
+    NoMate,
+    /// `checkmate timeout`。
+    Timeout,
+    /// `checkmate notimplemented`。
+    NotImplemented,
+}
+impl fmt::Display for Checkmate{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Checkmate::Mate(ref moves) => {
+                write!(f,"checkmate")?;
+                for movement in moves { write!(f," {}", movement)?; }
+                Ok(())
+            },
+            Checkmate::NoMate => { write!(f,"checkmate nomate") },
+            Checkmate::Timeout => { write!(f,"checkmate timeout") },
+            Checkmate::NotImplemented => { write!(f,"checkmate notimplemented") },
+        }
+    }
+}
+
+/// `id name` と `id author` の中身。
+#[derive(Clone,Debug,Default)]
+pub struct EngineId{
+    pub name : String,
+    pub author : String,
+}
+
+/// `option` の型と既定値。既定値が書かれていなければ None。
+#[derive(Clone,Debug,PartialEq)]
+pub enum UsiOptionType{
+    Check{ default : Option<bool> },
+    Spin{ default : Option<i64>, min : Option<i64>, max : Option<i64> },
+    Combo{ default : Option<String>, vars : Vec<String> },
+    Button,
+    String{ default : Option<String> },
+    Filename{ default : Option<String> },
+}
+
+/// `option name <id> type <t> ...` の1行分。
+#[derive(Clone,Debug,PartialEq)]
+pub struct UsiOption{
+    pub name : String,
+    pub option_type : UsiOptionType,
+}
+
+/// 空白を読み飛ばしてから、次の空白の手前までを1語として返す。
+pub fn next_token<'a>(line:&'a str, starts:&mut usize) -> Option<&'a str> {
+    let bytes = line.as_bytes();
+    while *starts<bytes.len() && bytes[*starts]==b' ' { *starts+=1; }
+    let begin = *starts;
+    while *starts<bytes.len() && bytes[*starts]!=b' ' { *starts+=1; }
+    if begin==*starts { None } else { Some(&line[begin..*starts]) }
+}
+
+/// 1語が USI の指し手の形をしていれば読み取る。`parse_movement` と違って、形が崩れていてもパニックしない。
+pub fn parse_movement_token(token:&str) -> Option<UsiMovement> {
+    let bytes = token.as_bytes();
+    let is_file = |x:u8| (b'1'..=b'9').contains(&x);
+    let is_rank = |x:u8| (b'a'..=b'i').contains(&x);
+    let well_formed = match bytes.len() {
+        4 if b"RBGSNLP".contains(&bytes[0]) => {
+            bytes[1]==b'*' && is_file(bytes[2]) && is_rank(bytes[3])
+        },
+        4 | 5 => {
+            is_file(bytes[0]) && is_rank(bytes[1]) && is_file(bytes[2]) && is_rank(bytes[3])
+                && (bytes.len()==4 || bytes[4]==b'+')
+        },
+        _ => false,
+    };
+    if !well_formed { return None; }
+
+    let mut starts = 0;
+    let (_, movement) = parse_movement(token, &mut starts, token.len());
+    Some(movement)
+}
+
+/// `bestmove 7g7f ponder 3c3d`、`bestmove resign`、`bestmove win` を読み取る。
+pub fn parse_bestmove(line:&str, starts:&mut usize) -> Option<BestMove> {
+    if next_token(line, starts)!=Some("bestmove") { return None; }
+    let best = match next_token(line, starts)? {
+        "resign" => { return Some(BestMove::Resign); },
+        "win" => { return Some(BestMove::Win); },
+        token => parse_movement_token(token)?,
+    };
+    let ponder = match next_token(line, starts) {
+        Some("ponder") => next_token(line, starts).and_then(parse_movement_token),
+        _ => None,
+    };
+    Some(BestMove::Move{ best, ponder })
+}
+
+/// `info ...` を読み取る。知らない項目は読み飛ばす。
+pub fn parse_info(line:&str, starts:&mut usize) -> Option<Info> {
+    if next_token(line, starts)!=Some("info") { return None; }
+
+    let mut info = Info::default();
+    // pv の途中で知らない語が来たら、pv が終わったとみなしてその語を読み直す。
+    let mut pending = None;
+    loop {
+        let token = match pending.take() {
+            Some(token) => token,
+            None => match next_token(line, starts) { Some(token) => token, None => break },
+        };
+        match token {
+            "depth" => { info.depth = next_number(line, starts); },
+            "seldepth" => { info.seldepth = next_number(line, starts); },
+            "time" => { info.time = next_number(line, starts); },
+            "nodes" => { info.nodes = next_number(line, starts); },
+            "nps" => { info.nps = next_number(line, starts); },
+            "hashfull" => { info.hashfull = next_number(line, starts); },
+            "multipv" => { info.multipv = next_number(line, starts); },
+            "currmove" => { info.currmove = next_token(line, starts).and_then(parse_movement_token); },
+            "score" => {
+                info.score = match next_token(line, starts) {
+                    Some("cp") => next_number(line, starts).map(Score::Cp),
+                    Some("mate") => match next_token(line, starts) {
+                        Some("+") => Some(Score::MatePlus),
+                        Some("-") => Some(Score::MateMinus),
+                        Some(x) => x.parse().ok().map(Score::Mate),
+                        None => None,
+                    },
+                    _ => None,
+                };
+            },
+            "lowerbound" => { info.bound = ScoreBound::Lower; },
+            "upperbound" => { info.bound = ScoreBound::Upper; },
+            "pv" => {
+                while let Some(token) = next_token(line, starts) {
+                    match parse_movement_token(token) {
+                        Some(movement) => { info.pv.push(movement); },
+                        None => { pending = Some(token); break; },
+                    }
+                }
+            },
+            "string" => {
+                info.string = Some(line[*starts..].trim_start().to_string());
+                *starts = line.len();
+            },
+            _ => {},
+        }
+    }
+    Some(info)
+}
+
+fn next_number<T:FromStr>(line:&str, starts:&mut usize) -> Option<T> {
+    next_token(line, starts).and_then(|x| x.parse().ok())
+}
+
+/// `checkmate <moves>|nomate|timeout|notimplemented` を読み取る。
+pub fn parse_checkmate(line:&str, starts:&mut usize) -> Option<Checkmate> {
+    if next_token(line, starts)!=Some("checkmate") { return None; }
+    let mut moves = Vec::new();
+    while let Some(token) = next_token(line, starts) {
+        match token {
+            "nomate" => { return Some(Checkmate::NoMate); },
+            "timeout" => { return Some(Checkmate::Timeout); },
+            "notimplemented" => { return Some(Checkmate::NotImplemented); },
+            _ => { moves.push(parse_movement_token(token)?); },
+        }
+    }
+    if moves.is_empty() { None } else { Some(Checkmate::Mate(moves)) }
+}
+
+/// `id name ...` か `id author ...` なら読み取って真を返す。名前は空白を含んでもいい。
+pub fn parse_id(line:&str, starts:&mut usize, id:&mut EngineId) -> bool {
+    let mut cursor = *starts;
+    if next_token(line, &mut cursor)!=Some("id") { return false; }
+    let field = match next_token(line, &mut cursor) {
+        Some("name") => &mut id.name,
+        Some("author") => &mut id.author,
+        _ => { return false; },
+    };
+    *field = line[cursor..].trim().to_string();
+    *starts = line.len();
+    true
+}
+
+/// `option name <id> type <t> [default <x>] [min <x>] [max <x>] [var <x>]*` を読み取る。
+///
+/// 文字列の既定値 `<empty>` は空文字列として読む。
+pub fn parse_option(line:&str, starts:&mut usize) -> Option<UsiOption> {
+    if next_token(line, starts)!=Some("option") { return None; }
+
+    let mut name = None;
+    let mut type_name = None;
+    let mut default = None;
+    let mut min = None;
+    let mut max = None;
+    let mut vars = Vec::new();
+    while let Some(token) = next_token(line, starts) {
+        match token {
+            "name" => { name = next_token(line, starts); },
+            "type" => { type_name = next_token(line, starts); },
+            "default" => { default = next_token(line, starts); },
+            "min" => { min = next_number(line, starts); },
+            "max" => { max = next_number(line, starts); },
+            "var" => { if let Some(var) = next_token(line, starts) { vars.push(var.to_string()); } },
+            _ => {},
+        }
+    }
+
+    let text = |x:&str| if x=="<empty>" { String::new() } else { x.to_string() };
+    let option_type = match type_name? {
+        "check" => UsiOptionType::Check{ default : default.and_then(|x| x.parse().ok()) },
+        "spin" => UsiOptionType::Spin{ default : default.and_then(|x| x.parse().ok()), min, max },
+        "combo" => UsiOptionType::Combo{ default : default.map(text), vars },
+        "button" => UsiOptionType::Button,
+        "string" => UsiOptionType::String{ default : default.map(text) },
+        "filename" => UsiOptionType::Filename{ default : default.map(text) },
+        _ => { return None; },
+    };
+    Some(UsiOption{
+        name : name?.to_string(),
+        option_type,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(line:&[UsiMovement]) -> Vec<String> {
+        line.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn bestmove() {
+        match parse_bestmove("bestmove 7g7f ponder 3c3d", &mut 0) {
+            Some(BestMove::Move{ best, ponder : Some(ponder) }) => {
+                assert_eq!(best.to_string(), "7g7f");
+                assert_eq!(ponder.to_string(), "3c3d");
+            },
+            other => panic!("{:?}", other),
+        }
+        match parse_bestmove("bestmove P*5e", &mut 0) {
+            Some(BestMove::Move{ best, ponder : None }) => { assert_eq!(best.to_string(), "P*5e"); },
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(parse_bestmove("bestmove resign", &mut 0), Some(BestMove::Resign)));
+        assert!(matches!(parse_bestmove("bestmove win", &mut 0), Some(BestMove::Win)));
+        // 読めない予想手は捨てて、指し手だけ読む。
+        assert!(matches!(parse_bestmove("bestmove 2g2f+ ponder ???", &mut 0), Some(BestMove::Move{ ponder : None, .. })));
+        assert!(parse_bestmove("bestmove", &mut 0).is_none());
+        assert!(parse_bestmove("bestmove 0z0z", &mut 0).is_none());
+        assert!(parse_bestmove("info depth 1", &mut 0).is_none());
+    }
+
+    #[test]
+    fn info_score_and_bound() {
+        let info = parse_info("info depth 10 seldepth 12 time 35 nodes 1000 nps 28571 hashfull 12 multipv 2 score cp -35 lowerbound pv 7g7f 3c3d", &mut 0).unwrap();
+        assert_eq!((info.depth, info.seldepth, info.time, info.nodes), (Some(10), Some(12), Some(35), Some(1000)));
+        assert_eq!((info.nps, info.hashfull, info.multipv), (Some(28571), Some(12), Some(2)));
+        assert_eq!(info.score, Some(Score::Cp(-35)));
+        assert_eq!(info.bound, ScoreBound::Lower);
+        assert_eq!(moves(&info.pv), ["7g7f", "3c3d"]);
+
+        let info = parse_info("info score mate 5 upperbound", &mut 0).unwrap();
+        assert_eq!(info.score, Some(Score::Mate(5)));
+        assert_eq!(info.bound, ScoreBound::Upper);
+        assert_eq!(parse_info("info score mate -3", &mut 0).unwrap().score, Some(Score::Mate(-3)));
+        assert_eq!(parse_info("info score mate +", &mut 0).unwrap().score, Some(Score::MatePlus));
+        assert_eq!(parse_info("info score mate -", &mut 0).unwrap().score, Some(Score::MateMinus));
+        let info = parse_info("info score cp 100", &mut 0).unwrap();
+        assert_eq!(info.bound, ScoreBound::Exact);
+        assert_eq!(info.to_string(), "info score cp 100");
+    }
+
+    #[test]
+    fn info_pv_ends_at_the_next_keyword() {
+        // pv は行末まで続くこともあれば、知らない語で終わることもある。
+        let info = parse_info("info depth 3 pv 7g7f 3c3d 2g2f", &mut 0).unwrap();
+        assert_eq!(moves(&info.pv), ["7g7f", "3c3d", "2g2f"]);
+        let info = parse_info("info pv 7g7f 3c3d depth 5 currmove 2g2f", &mut 0).unwrap();
+        assert_eq!(moves(&info.pv), ["7g7f", "3c3d"]);
+        assert_eq!(info.depth, Some(5));
+        assert_eq!(info.currmove.map(|x| x.to_string()), Some("2g2f".to_string()));
+    }
+
+    #[test]
+    fn info_string_swallows_the_rest() {
+        let info = parse_info("info depth 3 string hello  depth 9 pv 7g7f", &mut 0).unwrap();
+        assert_eq!(info.depth, Some(3));
+        assert_eq!(info.string, Some("hello  depth 9 pv 7g7f".to_string()));
+        assert!(info.pv.is_empty());
+        assert_eq!(info.to_string(), "info depth 3 string hello  depth 9 pv 7g7f");
+        assert!(parse_info("bestmove 7g7f", &mut 0).is_none());
+    }
+
+    #[test]
+    fn checkmate() {
+        assert!(matches!(parse_checkmate("checkmate nomate", &mut 0), Some(Checkmate::NoMate)));
+        assert!(matches!(parse_checkmate("checkmate notimplemented", &mut 0), Some(Checkmate::NotImplemented)));
+        assert!(matches!(parse_checkmate("checkmate timeout", &mut 0), Some(Checkmate::Timeout)));
+        match parse_checkmate("checkmate R*2a 1a1b 2a2b+", &mut 0) {
+            Some(Checkmate::Mate(ref line)) => { assert_eq!(moves(line), ["R*2a", "1a1b", "2a2b+"]); },
+            other => panic!("{:?}", other),
+        }
+        assert!(parse_checkmate("checkmate", &mut 0).is_none());
+        assert!(parse_checkmate("checkmate 7g7f what", &mut 0).is_none());
+    }
+
+    #[test]
+    fn option_combo_with_vars() {
+        let option = parse_option("option name Style type combo default Normal var Solid var Normal var Risky", &mut 0).unwrap();
+        assert_eq!(option, UsiOption{
+            name : "Style".to_string(),
+            option_type : UsiOptionType::Combo{
+                default : Some("Normal".to_string()),
+                vars : vec!["Solid".to_string(), "Normal".to_string(), "Risky".to_string()],
+            },
+        });
+    }
+
+    #[test]
+    fn option_types() {
+        let parse = |line:&str| parse_option(line, &mut 0).map(|x| x.option_type);
+        assert_eq!(parse("option name USI_Ponder type check default true"), Some(UsiOptionType::Check{ default : Some(true) }));
+        assert_eq!(parse("option name USI_Hash type spin default 256 min 1 max 1024"),
+            Some(UsiOptionType::Spin{ default : Some(256), min : Some(1), max : Some(1024) }));
+        assert_eq!(parse("option name Clear type button"), Some(UsiOptionType::Button));
+        assert_eq!(parse("option name Book type string default <empty>"), Some(UsiOptionType::String{ default : Some(String::new()) }));
+        assert_eq!(parse("option name Eval type filename default eval.bin"), Some(UsiOptionType::Filename{ default : Some("eval.bin".to_string()) }));
+        assert_eq!(parse("option name Strange type colour"), None);
+        assert_eq!(parse("option name NoType"), None);
+        assert_eq!(parse("option type check"), None);
+    }
+}
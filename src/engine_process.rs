@@ -0,0 +1,237 @@
+//! GUI側から思考エンジンを動かすやつ☆（＾～＾）
+//!
+//! エンジンの実行ファイルを子プロセスとして起動して、標準入出力で USI をしゃべる。
+//! 動作確認には `cargo build --example mock_engine` で作るモック・エンジンを使う。
+use std::ffi::OsStr;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use engine_output::*;
+use GoParams;
+use UsiMovement;
+
+/// エンジンから届いた1行。読めなかった行は `Other` に入れる。
+#[derive(Clone,Debug)]
+pub enum EngineEvent{
+    Info(Info),
+    BestMove(BestMove),
+    Checkmate(Checkmate),
+    /// それ以外の行。
+    Other(String),
+}
+impl EngineEvent{
+    /// 1行を先頭のキーワードで振り分けて字句解析する。
+    pub fn parse(line:&str) -> EngineEvent{
+        let parsed = match line.split(' ').next() {
+            Some("info") => parse_info(line, &mut 0).map(EngineEvent::Info),
+            Some("bestmove") => parse_bestmove(line, &mut 0).map(EngineEvent::BestMove),
+            Some("checkmate") => parse_checkmate(line, &mut 0).map(EngineEvent::Checkmate),
+            _ => None,
+        };
+        parsed.unwrap_or_else(|| EngineEvent::Other(line.to_string()))
+    }
+}
+
+/// 子プロセスとして動いている思考エンジン。
+///
+/// エンジンの標準出力は別スレッドで1行ずつ読んでおいて、`recv_event` で受け取る。
+/// 捨てるときに `quit` を送り、終わらなければ殺す。
+pub struct UsiEngineProcess{
+    child : Child,
+    stdin : ChildStdin,
+    lines : Receiver<String>,
+    id : EngineId,
+    options : Vec<UsiOption>,
+}
+impl UsiEngineProcess{
+    /// エンジンを起動して `usi` を送り、`usiok` が来るまでに `id` と `option` を読み取る。
+    ///
+    /// 評価関数ファイルなどを相対パスで探すエンジンのために、実行ファイルのあるフォルダーで起動する。
+    pub fn spawn<S:AsRef<OsStr>>(program:S, timeout:Duration) -> io::Result<UsiEngineProcess> {
+        let path = Path::new(program.as_ref());
+        let mut command = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => {
+                // 相対パスは起動フォルダーを変えると見失うので、先に絶対パスにしておく。
+                let path = path.canonicalize()?;
+                let mut command = Command::new(&path);
+                if let Some(dir) = path.parent() { command.current_dir(dir); }
+                command
+            },
+            _ => Command::new(path),
+        };
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line { Ok(x) => x, Err(_) => break };
+                if sender.send(line.trim_end().to_string()).is_err() { break; }
+            }
+        });
+
+        let mut engine = UsiEngineProcess{
+            child,
+            stdin,
+            lines,
+            id : EngineId::default(),
+            options : Vec::new(),
+        };
+
+        engine.send("usi")?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = engine.recv_line_until(deadline, "usiok")?;
+            if line=="usiok" { break; }
+            if parse_id(&line, &mut 0, &mut engine.id) { continue; }
+            if let Some(option) = parse_option(&line, &mut 0) { engine.options.push(option); }
+        }
+        Ok(engine)
+    }
+
+    /// `id name` と `id author` の中身。
+    pub fn id(&self) -> &EngineId {
+        &self.id
+    }
+
+    /// `usiok` までに宣言されたオプション。
+    pub fn options(&self) -> &[UsiOption] {
+        &self.options
+    }
+
+    /// 1行送る。
+    pub fn send(&mut self, command:&str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// `setoption name <id> value <x>` を送る。
+    pub fn set_option(&mut self, name:&str, value:&str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    /// `isready` を送り、`readyok` が来るまで待つ。それまでに来た行は捨てる。
+    pub fn is_ready(&mut self, timeout:Duration) -> io::Result<()> {
+        self.send("isready")?;
+        let deadline = Instant::now() + timeout;
+        while self.recv_line_until(deadline, "readyok")?!="readyok" {}
+        Ok(())
+    }
+
+    /// `usinewgame` を送る。
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send("usinewgame")
+    }
+
+    /// `position` を送る。`sfen` に "startpos" を渡せば平手初期局面。
+    pub fn position(&mut self, sfen:&str, moves:&[UsiMovement]) -> io::Result<()> {
+        let mut command = if sfen=="startpos" {
+            "position startpos".to_string()
+        } else {
+            format!("position sfen {}", sfen)
+        };
+        if !moves.is_empty() {
+            command.push_str(" moves");
+            for movement in moves {
+                command.push_str(&format!(" {}", movement));
+            }
+        }
+        self.send(&command)
+    }
+
+    /// `go ...` を送る。結果は `recv_event` で受け取る。
+    pub fn go(&mut self, params:&GoParams) -> io::Result<()> {
+        self.send(&format!("go {}", params))
+    }
+
+    /// `stop` を送る。
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.send("stop")
+    }
+
+    /// `ponderhit` を送る。
+    pub fn ponderhit(&mut self) -> io::Result<()> {
+        self.send("ponderhit")
+    }
+
+    /// `gameover win|lose|draw` を送る。
+    pub fn game_over(&mut self, result:&str) -> io::Result<()> {
+        self.send(&format!("gameover {}", result))
+    }
+
+    /// エンジンから1行受け取る。None なら来るまで待つ。
+    ///
+    /// 時間切れなら `TimedOut`、エンジンが終わっていれば `UnexpectedEof` のエラー。
+    pub fn recv_event(&self, timeout:Option<Duration>) -> io::Result<EngineEvent> {
+        let line = match timeout {
+            Some(timeout) => self.lines.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => io::Error::new(io::ErrorKind::TimedOut, "engine did not answer in time"),
+                RecvTimeoutError::Disconnected => engine_exited(),
+            })?,
+            None => self.lines.recv().map_err(|_| engine_exited())?,
+        };
+        Ok(EngineEvent::parse(&line))
+    }
+
+    /// エンジンが終わるまで、届いた行を順に返し続ける。
+    pub fn events(&self) -> EngineEvents<'_> {
+        EngineEvents{ lines : &self.lines }
+    }
+
+    /// `quit` を送って、終わるのを待つ。
+    pub fn quit(mut self, timeout:Duration) -> io::Result<()> {
+        self.send("quit")?;
+        self.wait_or_kill(timeout)
+    }
+
+    fn recv_line_until(&self, deadline:Instant, expected:&str) -> io::Result<String> {
+        let now = Instant::now();
+        let rest = if now<deadline { deadline - now } else { Duration::from_millis(0) };
+        self.lines.recv_timeout(rest).map_err(|e| match e {
+            RecvTimeoutError::Timeout => io::Error::new(io::ErrorKind::TimedOut, format!("engine did not answer {} in time", expected)),
+            RecvTimeoutError::Disconnected => engine_exited(),
+        })
+    }
+
+    fn wait_or_kill(&mut self, timeout:Duration) -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now()<deadline {
+            if self.child.try_wait()?.is_some() { return Ok(()); }
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.child.kill()?;
+        self.child.wait().map(|_| ())
+    }
+}
+impl Drop for UsiEngineProcess{
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.send("quit");
+            let _ = self.wait_or_kill(Duration::from_millis(500));
+        }
+    }
+}
+
+/// `UsiEngineProcess::events` の返す繰り返し。
+pub struct EngineEvents<'a>{
+    lines : &'a Receiver<String>,
+}
+impl<'a> Iterator for EngineEvents<'a>{
+    type Item = EngineEvent;
+    fn next(&mut self) -> Option<EngineEvent> {
+        self.lines.recv().ok().map(|line| EngineEvent::parse(&line))
+    }
+}
+
+fn engine_exited() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "engine exited")
+}
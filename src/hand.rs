@@ -0,0 +1,217 @@
+//! 持ち駒☆（＾～＾） 片方の側の持ち駒を 32bit に詰めたもの。
+//!
+//! 並びはやねうら王の `Hand` と同じ。駒種類ごとの枚数の欄の上に、1bit ずつ空きを置いてある。
+//!
+//! | 駒 | 歩 | 香 | 桂 | 銀 | 角 | 飛 | 金 |
+//! |----|----|----|----|----|----|----|----|
+//! | bit | 0～4 | 8～10 | 12～14 | 16～18 | 20～21 | 24～25 | 28～30 |
+//!
+//! 引き算して空きの bit が立たなければ、どの駒も引く方以上にある。
+//! これで持ち駒の優劣を1回で比べられる。詰め探索の証明や千日手の判定に使う。
+use std::fmt;
+
+use {Color, PieceType};
+
+/// 持ち駒にできる駒種類。sfen に書く順。
+pub const HAND_PIECE_TYPES : [PieceType; 7] = [
+    PieceType::R, PieceType::B, PieceType::G, PieceType::S, PieceType::N, PieceType::L, PieceType::P,
+];
+
+/// 駒種類ごとの (欄の位置, 欄の幅)。
+fn field(pt:PieceType) -> (u32, u32) {
+    use PieceType::*;
+    match pt {
+        P => (0, 5),
+        L => (8, 3),
+        N => (12, 3),
+        S => (16, 3),
+        B => (20, 2),
+        R => (24, 2),
+        G => (28, 3),
+        _ => panic!("{} is not hand piece.", pt),
+    }
+}
+
+/// 各欄のすぐ上の空きの bit。
+const BORROW_MASK : u32 = 1 << 5 | 1 << 11 | 1 << 15 | 1 << 19 | 1 << 22 | 1 << 26 | 1 << 31;
+
+/// 片方の側の持ち駒。
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Hand(u32);
+impl Hand{
+    /// 持ち駒なし。
+    pub const EMPTY : Hand = Hand(0);
+
+    /// 詰めた 32bit から。欄の外の bit が立っていれば None。
+    pub fn from_packed(packed:u32) -> Option<Hand> {
+        let mut hand = Hand::EMPTY;
+        for &pt in HAND_PIECE_TYPES.iter() {
+            let (shift, width) = field(pt);
+            hand.0 |= packed & (((1 << width) - 1) << shift);
+        }
+        if hand.0==packed { Some(hand) } else { None }
+    }
+
+    /// 詰めた 32bit。置換表やハッシュに使う。
+    pub fn packed(self) -> u32 {
+        self.0
+    }
+
+    /// 枚数。成り駒は成る前の駒の枚数。
+    pub fn count(self, pt:PieceType) -> i8 {
+        let (shift, width) = field(pt.demote());
+        ((self.0 >> shift) & ((1 << width) - 1)) as i8
+    }
+
+    /// 枚数を変える。欄に入らない枚数なら panic。
+    pub fn set(&mut self, pt:PieceType, count:i8) {
+        let (shift, width) = field(pt.demote());
+        assert!(0<=count && (count as u32)<1 << width, "{} {} is out of hand.", pt, count);
+        self.0 = (self.0 & !(((1 << width) - 1) << shift)) | ((count as u32) << shift);
+    }
+
+    /// 1枚足す。取った駒は成る前の駒として足す。
+    pub fn add(&mut self, pt:PieceType) {
+        let count = self.count(pt);
+        self.set(pt, count + 1);
+    }
+
+    /// 1枚減らす。無ければ panic。
+    pub fn remove(&mut self, pt:PieceType) {
+        let count = self.count(pt);
+        assert!(0<count, "no {} in hand.", pt);
+        self.set(pt, count - 1);
+    }
+
+    /// 1枚も無ければ真。
+    pub fn is_empty(self) -> bool {
+        self.0==0
+    }
+
+    /// どの駒種類も `other` 以上の枚数があれば真。
+    pub fn is_superior_or_equal(self, other:Hand) -> bool {
+        (self.0.wrapping_sub(other.0) & BORROW_MASK)==0
+    }
+
+    /// 持っている駒種類と枚数を、sfen に書く順で。
+    pub fn iter(self) -> impl Iterator<Item=(PieceType, i8)> {
+        HAND_PIECE_TYPES.iter().map(move |&pt| (pt, self.count(pt))).filter(|x| 0<x.1)
+    }
+
+    /// `color` の側の持ち駒としての sfen。飛角金銀桂香歩の順で、2枚以上なら枚数を前に付ける。
+    /// 持ち駒が無ければ空文字列。
+    pub fn to_sfen(self, color:Color) -> String {
+        let mut text = String::new();
+        for (pt, count) in self.iter() {
+            if 1<count { text.push_str(&count.to_string()); }
+            let c = pt.to_sfen_char();
+            text.push(if color==Color::Sente { c } else { c.to_ascii_lowercase() });
+        }
+        text
+    }
+}
+impl fmt::Debug for Hand{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hand({})", self.to_sfen(Color::Sente))
+    }
+}
+
+/// 両方の持ち駒の sfen。先手、後手の順。無ければ `-`。
+pub fn hands_to_sfen(hands:&[Hand; 2]) -> String {
+    let text = format!("{}{}", hands[0].to_sfen(Color::Sente), hands[1].to_sfen(Color::Gote));
+    if text.is_empty() { "-".to_string() } else { text }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 全部の駒を最大枚数持った手。
+    fn full() -> Hand {
+        let mut hand = Hand::EMPTY;
+        for &(pt, count) in [(PieceType::R, 2), (PieceType::B, 2), (PieceType::G, 4), (PieceType::S, 4),
+            (PieceType::N, 4), (PieceType::L, 4), (PieceType::P, 18)].iter() {
+            hand.set(pt, count);
+        }
+        hand
+    }
+
+    #[test]
+    fn yaneuraou_layout() {
+        let mut hand = Hand::EMPTY;
+        hand.set(PieceType::P, 18);
+        hand.set(PieceType::L, 1);
+        hand.set(PieceType::G, 4);
+        assert_eq!(hand.packed(), 18 | 1 << 8 | 4 << 28);
+    }
+
+    #[test]
+    fn pack_and_unpack() {
+        let hand = full();
+        assert_eq!(Hand::from_packed(hand.packed()), Some(hand));
+        for (pt, count) in hand.iter() {
+            assert_eq!(Hand::from_packed(hand.packed()).unwrap().count(pt), count);
+        }
+        // 空きの bit が立っていれば読まない。
+        for &bit in [5, 6, 11, 15, 19, 22, 23, 26, 27, 31].iter() {
+            assert_eq!(Hand::from_packed(1 << bit), None, "bit {}", bit);
+        }
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut hand = Hand::EMPTY;
+        // 成り駒は成る前の駒として足す。
+        hand.add(PieceType::PR);
+        hand.add(PieceType::P);
+        hand.add(PieceType::PP);
+        assert_eq!(hand.count(PieceType::R), 1);
+        assert_eq!(hand.count(PieceType::P), 2);
+        hand.remove(PieceType::P);
+        assert_eq!(hand.count(PieceType::P), 1);
+        assert_eq!(hand.to_sfen(Color::Sente), "RP");
+        assert_eq!(hand.to_sfen(Color::Gote), "rp");
+        hand.remove(PieceType::R);
+        hand.remove(PieceType::P);
+        assert!(hand.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn remove_missing_piece() {
+        let mut hand = Hand::EMPTY;
+        hand.remove(PieceType::G);
+    }
+
+    #[test]
+    fn superiority() {
+        let mut small = Hand::EMPTY;
+        small.set(PieceType::G, 1);
+        small.set(PieceType::P, 3);
+        let mut large = small;
+        large.add(PieceType::S);
+        assert!(large.is_superior_or_equal(small));
+        assert!(!small.is_superior_or_equal(large));
+        assert!(small.is_superior_or_equal(small));
+        assert!(small.is_superior_or_equal(Hand::EMPTY));
+        assert!(full().is_superior_or_equal(large));
+
+        // 歩は多いが金が少ない。どちらも優越しない。
+        let mut other = Hand::EMPTY;
+        other.set(PieceType::P, 18);
+        assert!(!other.is_superior_or_equal(small));
+        assert!(!small.is_superior_or_equal(other));
+    }
+
+    #[test]
+    fn sfen() {
+        let mut hand = Hand::EMPTY;
+        hand.set(PieceType::P, 10);
+        hand.set(PieceType::B, 1);
+        hand.set(PieceType::S, 2);
+        assert_eq!(hand.to_sfen(Color::Sente), "B2S10P");
+        assert_eq!(hands_to_sfen(&[hand, Hand::EMPTY]), "B2S10P");
+        assert_eq!(hands_to_sfen(&[Hand::EMPTY, hand]), "b2s10p");
+        assert_eq!(hands_to_sfen(&[Hand::EMPTY; 2]), "-");
+    }
+}
@@ -0,0 +1,248 @@
+//! 手合割☆（＾～＾） 平手と駒落ちの開始局面。
+//!
+//! 駒落ちは上手（後手）の駒を落として、上手から指す。左右は上手から見て。
+use position::Position;
+
+/// 手合割。
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum Handicap{
+    /// 平手。
+    Hirate,
+    /// 香落ち。1一の香を落とす。
+    Kyo,
+    /// 右香落ち。9一の香を落とす。
+    MigiKyo,
+    /// 角落ち。
+    Kaku,
+    /// 飛車落ち。
+    Hisha,
+    /// 飛香落ち。
+    HiKyo,
+    /// 二枚落ち。
+    Nimai,
+    /// 三枚落ち。
+    Sanmai,
+    /// 四枚落ち。
+    Yonmai,
+    /// 五枚落ち。四枚落ちから8一の桂も落とす。
+    Gomai,
+    /// 左五枚落ち。四枚落ちから2一の桂も落とす。
+    HidariGomai,
+    /// 六枚落ち。
+    Rokumai,
+    /// 左七枚落ち。六枚落ちから3一の銀も落とす。
+    HidariNanamai,
+    /// 右七枚落ち。六枚落ちから7一の銀も落とす。
+    MigiNanamai,
+    /// 八枚落ち。
+    Hachimai,
+    /// 十枚落ち。
+    Jumai,
+    /// 歩三兵。上手は玉と、持ち駒の歩3枚だけ。
+    FuSanbei,
+}
+
+/// 全部の手合割。平手が先頭。
+pub const HANDICAP_ARRAY : [Handicap; 17] = [
+    Handicap::Hirate,
+    Handicap::Kyo,
+    Handicap::MigiKyo,
+    Handicap::Kaku,
+    Handicap::Hisha,
+    Handicap::HiKyo,
+    Handicap::Nimai,
+    Handicap::Sanmai,
+    Handicap::Yonmai,
+    Handicap::Gomai,
+    Handicap::HidariGomai,
+    Handicap::Rokumai,
+    Handicap::HidariNanamai,
+    Handicap::MigiNanamai,
+    Handicap::Hachimai,
+    Handicap::Jumai,
+    Handicap::FuSanbei,
+];
+
+impl Handicap{
+    /// 開始局面の sfen。駒落ちは上手（後手）番。
+    pub fn sfen(self) -> &'static str {
+        use self::Handicap::*;
+        match self {
+            Hirate => "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            Kyo => "lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            MigiKyo => "1nsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Kaku => "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Hisha => "lnsgkgsnl/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            HiKyo => "lnsgkgsn1/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Nimai => "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Sanmai => "lnsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Yonmai => "1nsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Gomai => "2sgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            HidariGomai => "1nsgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Rokumai => "2sgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            HidariNanamai => "2sgkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            MigiNanamai => "3gkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Hachimai => "3gkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            Jumai => "4k4/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+            FuSanbei => "4k4/9/9/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w 3p 1",
+        }
+    }
+
+    /// KIF の `手合割：` に書く名前。
+    pub fn kif_name(self) -> &'static str {
+        use self::Handicap::*;
+        match self {
+            Hirate => "平手",
+            Kyo => "香落ち",
+            MigiKyo => "右香落ち",
+            Kaku => "角落ち",
+            Hisha => "飛車落ち",
+            HiKyo => "飛香落ち",
+            Nimai => "二枚落ち",
+            Sanmai => "三枚落ち",
+            Yonmai => "四枚落ち",
+            Gomai => "五枚落ち",
+            HidariGomai => "左五枚落ち",
+            Rokumai => "六枚落ち",
+            HidariNanamai => "左七枚落ち",
+            MigiNanamai => "右七枚落ち",
+            Hachimai => "八枚落ち",
+            Jumai => "十枚落ち",
+            FuSanbei => "歩三兵",
+        }
+    }
+
+    /// CSA の `PI` 行。平手から取り除く駒を並べる。
+    ///
+    /// 歩三兵だけは持ち駒を足す `P-` 行も改行で続ける。
+    pub fn csa(self) -> &'static str {
+        use self::Handicap::*;
+        match self {
+            Hirate => "PI",
+            Kyo => "PI11KY",
+            MigiKyo => "PI91KY",
+            Kaku => "PI22KA",
+            Hisha => "PI82HI",
+            HiKyo => "PI82HI11KY",
+            Nimai => "PI82HI22KA",
+            Sanmai => "PI82HI22KA11KY",
+            Yonmai => "PI82HI22KA11KY91KY",
+            Gomai => "PI82HI22KA11KY91KY81KE",
+            HidariGomai => "PI82HI22KA11KY91KY21KE",
+            Rokumai => "PI82HI22KA11KY91KY21KE81KE",
+            HidariNanamai => "PI82HI22KA11KY91KY21KE81KE31GI",
+            MigiNanamai => "PI82HI22KA11KY91KY21KE81KE71GI",
+            Hachimai => "PI82HI22KA11KY91KY21KE81KE31GI71GI",
+            Jumai => "PI82HI22KA11KY91KY21KE81KE31GI71GI41KI61KI",
+            FuSanbei => "PI82HI22KA11KY91KY21KE81KE31GI71GI41KI61KI13FU23FU33FU43FU53FU63FU73FU83FU93FU\nP-00FU00FU00FU",
+        }
+    }
+
+    /// 開始局面。
+    pub fn position(self) -> Position {
+        Position::from_sfen(self.sfen())
+    }
+
+    /// KIF の手合割の名前から。`香落` のように `ち` が無くても読む。
+    pub fn from_kif_name(name:&str) -> Option<Handicap> {
+        let name = name.trim();
+        HANDICAP_ARRAY.iter().cloned().find(|x| x.kif_name()==name || x.kif_name().trim_end_matches('ち')==name)
+    }
+
+    /// 局面の盤上と持ち駒が、どれかの手合割の開始局面と同じならそれを返す。手番と手数は見ない。
+    pub fn detect(position:&Position) -> Option<Handicap> {
+        let board = position.board_to_sfen();
+        let hand = position.hand_to_sfen();
+        HANDICAP_ARRAY.iter().cloned().find(|x| {
+            let start = x.position();
+            start.board_to_sfen()==board && start.hand_to_sfen()==hand
+        })
+    }
+
+    /// sfen がどの手合割の開始局面か。
+    pub fn detect_sfen(sfen:&str) -> Option<Handicap> {
+        Handicap::detect(&Position::from_sfen(sfen))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use record::csa_piece_name;
+    use {Color, Piece, PieceType, file_rank_to_cell};
+
+    #[test]
+    fn sfen_round_trip() {
+        for &handicap in HANDICAP_ARRAY.iter() {
+            let position = handicap.position();
+            assert_eq!(position.to_sfen(), handicap.sfen(), "{:?}", handicap);
+            // 駒落ちは上手（後手）から指す。
+            let turn = if handicap==Handicap::Hirate { Color::Sente } else { Color::Gote };
+            assert!(position.turn==turn, "{:?}", handicap);
+        }
+    }
+
+    #[test]
+    fn csa_removes_the_same_pieces_as_sfen() {
+        for &handicap in HANDICAP_ARRAY.iter() {
+            let mut lines = handicap.csa().lines();
+            let removed = lines.next().unwrap();
+            assert!(removed.starts_with("PI"), "{:?}", handicap);
+            // 平手から、上手の駒を4文字ずつ取り除く。
+            let mut position = Handicap::Hirate.position();
+            let removed = &removed.as_bytes()[2..];
+            assert_eq!(removed.len() % 4, 0, "{:?}", handicap);
+            for chunk in removed.chunks(4) {
+                let (file, rank) = ((chunk[0] - b'0') as i8, (chunk[1] - b'0') as i8);
+                let pc = position.piece(file, rank);
+                assert!(!pc.is_space() && pc.color()==Color::Gote, "{:?} {}{}", handicap, file, rank);
+                assert_eq!(csa_piece_name(pc.piece_type()).as_bytes(), &chunk[2..], "{:?}", handicap);
+                position.board[file_rank_to_cell(file, rank)] = Piece::Space;
+            }
+            // 歩三兵は上手の持ち駒に歩を足す。
+            for line in lines {
+                assert!(line.starts_with("P-"), "{:?}", handicap);
+                for _ in 0..(line.len() - 2) / 4 { position.hand[Color::Gote.index()].add(PieceType::P); }
+            }
+            assert_eq!(position.board_to_sfen(), handicap.position().board_to_sfen(), "{:?}", handicap);
+            assert_eq!(position.hand_to_sfen(), handicap.position().hand_to_sfen(), "{:?}", handicap);
+        }
+        assert_eq!(Handicap::Hirate.csa(), "PI");
+        assert_eq!(Handicap::Kaku.csa(), "PI22KA");
+        assert!(Handicap::FuSanbei.csa().ends_with("\nP-00FU00FU00FU"));
+    }
+
+    #[test]
+    fn kif_names() {
+        assert_eq!(Handicap::Hirate.kif_name(), "平手");
+        assert_eq!(Handicap::HiKyo.kif_name(), "飛香落ち");
+        assert_eq!(Handicap::FuSanbei.kif_name(), "歩三兵");
+        for &handicap in HANDICAP_ARRAY.iter() {
+            assert_eq!(Handicap::from_kif_name(handicap.kif_name()), Some(handicap));
+        }
+        assert_eq!(Handicap::from_kif_name("香落"), Some(Handicap::Kyo));
+        assert_eq!(Handicap::from_kif_name(" 二枚落ち "), Some(Handicap::Nimai));
+        assert_eq!(Handicap::from_kif_name("その他"), None);
+    }
+
+    #[test]
+    fn detect_round_trip() {
+        for &handicap in HANDICAP_ARRAY.iter() {
+            assert_eq!(Handicap::detect_sfen(handicap.sfen()), Some(handicap));
+            assert_eq!(Handicap::detect(&handicap.position()), Some(handicap));
+        }
+        // 手番と手数は見ない。
+        assert_eq!(Handicap::detect_sfen("lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 5"), Some(Handicap::Kaku));
+    }
+
+    #[test]
+    fn detect_no_match() {
+        // 1手進めた局面と、持ち駒だけ違う局面。
+        let mut position = Handicap::Hirate.position();
+        let movement = position.legal_moves()[0];
+        position.do_move(&movement);
+        assert_eq!(Handicap::detect(&position), None);
+        assert_eq!(Handicap::detect_sfen("4k4/9/9/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w 2p 1"), None);
+        assert_eq!(Handicap::detect_sfen("8k/9/9/9/9/9/9/9/9 b G 1"), None);
+    }
+}
@@ -1,5 +1,31 @@
 /// コンピューター将棋 通信部 USIプロトコル Rustフレームワーク
 use std::fmt;
+use std::str::FromStr;
+
+use hand::Hand;
+
+pub mod compact_move;
+pub mod declaration;
+pub mod diagram;
+pub mod engine_output;
+pub mod engine_process;
+pub mod hand;
+pub mod handicap;
+pub mod mate;
+pub mod movegen;
+pub mod notation;
+pub mod piece_code;
+pub mod position;
+pub mod record;
+pub mod search;
+pub mod see;
+pub mod square;
+pub mod stats;
+pub mod svg;
+pub mod time_manager;
+pub mod transposition_table;
+pub mod tsume;
+pub mod validation;
 
 /// Fileは筋、Rankは段。1～9を使用。
 pub const FILE0: i8 = 0; // 0筋は投了フラグとしても使用。
@@ -34,8 +60,104 @@ pub fn file_rank_to_cell(file:i8, rank:i8)->usize{
 pub const STARTPOS_LN: usize = 57;
 pub const STARTPOS: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL";
 
+/// 手番。駒の持ち主。
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Color{
+    // 先手（☗、sfen の `b`、CSA の `+`）。
+    Sente,
+    // 後手（☖、sfen の `w`、CSA の `-`）。
+    Gote,
+}
+impl Color{
+    /// 先手、後手の順。
+    pub fn all() -> impl Iterator<Item=Color> {
+        [Color::Sente, Color::Gote].iter().cloned()
+    }
+
+    /// 先手なら 0、後手なら 1。配列を引くときに使う。
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// 0 なら先手、1 なら後手。
+    pub fn from_index(index:usize) -> Option<Color> {
+        match index {
+            0 => Some(Color::Sente),
+            1 => Some(Color::Gote),
+            _ => None,
+        }
+    }
+
+    /// 相手。
+    pub fn opponent(self) -> Color {
+        match self {
+            Color::Sente => Color::Gote,
+            Color::Gote => Color::Sente,
+        }
+    }
+
+    /// sfen の手番の `b` か `w` から。
+    pub fn from_usi(c:char) -> Option<Color> {
+        match c {
+            'b' => Some(Color::Sente),
+            'w' => Some(Color::Gote),
+            _ => None,
+        }
+    }
+
+    /// sfen の手番の `b` か `w`。
+    pub fn to_usi(self) -> char {
+        match self {
+            Color::Sente => 'b',
+            Color::Gote => 'w',
+        }
+    }
+
+    /// CSA の `+` か `-` から。
+    pub fn from_csa(c:char) -> Option<Color> {
+        match c {
+            '+' => Some(Color::Sente),
+            '-' => Some(Color::Gote),
+            _ => None,
+        }
+    }
+
+    /// CSA の `+` か `-`。
+    pub fn to_csa(self) -> char {
+        match self {
+            Color::Sente => '+',
+            Color::Gote => '-',
+        }
+    }
+
+    /// 手番の記号から。KIF の ☗☖ のほか、▲△ と、このクレートの駒の表示の ▼ も読む。
+    pub fn from_mark(c:char) -> Option<Color> {
+        match c {
+            '☗' | '▲' | '▼' => Some(Color::Sente),
+            '☖' | '△' | '▽' => Some(Color::Gote),
+            _ => None,
+        }
+    }
+
+    /// KIF の手番の記号 ☗ か ☖。
+    pub fn to_mark(self) -> char {
+        match self {
+            Color::Sente => '☗',
+            Color::Gote => '☖',
+        }
+    }
+}
+impl fmt::Display for Color{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Color::Sente => { write!(f,"先手")},
+            Color::Gote => { write!(f,"後手")},
+        }
+    }
+}
+
 /// 駒種類。先後なしの駒と空白。
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum PieceType{
     // 玉(King)。
     K,
@@ -83,8 +205,8 @@ impl fmt::Display for PieceType{
             N => { write!(f,"桂")},
             L => { write!(f,"香")},
             P => { write!(f,"歩")},
-            PB => { write!(f,"竜")},
-            PR => { write!(f,"馬")},
+            PR => { write!(f,"竜")},
+            PB => { write!(f,"馬")},
             PS => { write!(f,"全")},
             PN => { write!(f,"圭")},
             PL => { write!(f,"杏")},
@@ -95,8 +217,110 @@ impl fmt::Display for PieceType{
     }
 }
 
+/// 先後なしの駒種類を、`Piece` と同じ並びで。空マスは含めない。
+pub const PIECE_TYPE_ARRAY : [PieceType; 14] = [
+    PieceType::K, PieceType::R, PieceType::B, PieceType::G, PieceType::S, PieceType::N, PieceType::L,
+    PieceType::P, PieceType::PR, PieceType::PB, PieceType::PS, PieceType::PN, PieceType::PL, PieceType::PP,
+];
+impl PieceType{
+    /// 玉から順に、空マスを除いた全部の駒種類。
+    pub fn all() -> impl Iterator<Item=PieceType> {
+        PIECE_TYPE_ARRAY.iter().cloned()
+    }
+
+    /// 成れる駒なら真。飛、角、銀、桂、香、歩。
+    pub fn can_promote(self) -> bool {
+        use PieceType::*;
+        matches!(self, R | B | S | N | L | P)
+    }
+
+    /// 成った駒なら真。
+    pub fn is_promoted(self) -> bool {
+        use PieceType::*;
+        matches!(self, PR | PB | PS | PN | PL | PP)
+    }
+
+    /// 成った駒種類。成れない駒はそのまま。
+    pub fn promote(self) -> PieceType {
+        use PieceType::*;
+        match self {
+            R => PR,
+            B => PB,
+            S => PS,
+            N => PN,
+            L => PL,
+            P => PP,
+            _ => self,
+        }
+    }
+
+    /// 成る前の駒種類。持ち駒にするときに使う。
+    pub fn demote(self) -> PieceType {
+        use PieceType::*;
+        match self {
+            PR => R,
+            PB => B,
+            PS => S,
+            PN => N,
+            PL => L,
+            PP => P,
+            _ => self,
+        }
+    }
+
+    /// sfen の先手の1文字から。成りの `+` は付かないので、成っていない駒だけ。
+    pub fn from_sfen_char(c:char) -> Option<PieceType> {
+        use PieceType::*;
+        match c {
+            'K' => Some(K),
+            'R' => Some(R),
+            'B' => Some(B),
+            'G' => Some(G),
+            'S' => Some(S),
+            'N' => Some(N),
+            'L' => Some(L),
+            'P' => Some(P),
+            _ => None,
+        }
+    }
+
+    /// sfen の先手の1文字。成り駒は成る前の駒の文字で、`+` は付けない。空マスは ' '。
+    pub fn to_sfen_char(self) -> char {
+        use PieceType::*;
+        match self.demote() {
+            K => 'K',
+            R => 'R',
+            B => 'B',
+            G => 'G',
+            S => 'S',
+            N => 'N',
+            L => 'L',
+            P => 'P',
+            _ => ' ',
+        }
+    }
+}
+/// sfen の `K` や `+R` を読む。小文字も読む。
+impl FromStr for PieceType{
+    type Err = ParsePieceError;
+    fn from_str(text:&str) -> Result<PieceType, ParsePieceError> {
+        text.parse::<Piece>().map(Piece::piece_type)
+    }
+}
+
+/// `Piece` や `PieceType` として読めなかった文字列。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsePieceError{
+    pub text : String,
+}
+impl fmt::Display for ParsePieceError{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f,"'{}' は駒ではない。", self.text)
+    }
+}
+
 /// 先後付きの駒と空白
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Piece{
     // ▼玉(King)。
     K0,
@@ -197,6 +421,101 @@ impl fmt::Display for Piece{
         }
     }
 }
+/// 先後付きの駒を、先手、後手の順で。空マスは含めない。
+pub const PIECE_ARRAY : [Piece; 28] = [
+    Piece::K0, Piece::R0, Piece::B0, Piece::G0, Piece::S0, Piece::N0, Piece::L0,
+    Piece::P0, Piece::PR0, Piece::PB0, Piece::PS0, Piece::PN0, Piece::PL0, Piece::PP0,
+    Piece::K1, Piece::R1, Piece::B1, Piece::G1, Piece::S1, Piece::N1, Piece::L1,
+    Piece::P1, Piece::PR1, Piece::PB1, Piece::PS1, Piece::PN1, Piece::PL1, Piece::PP1,
+];
+impl Piece{
+    /// 持ち主と駒種類から。空マスの駒種類なら空マス。
+    pub fn from(color:Color, pt:PieceType) -> Piece {
+        match pt {
+            PieceType::Space | PieceType::Num => Piece::Space,
+            _ => PIECE_ARRAY[color.index()*PIECE_TYPE_ARRAY.len() + pt as usize],
+        }
+    }
+
+    /// 先手の玉から順に、空マスを除いた全部の駒。
+    pub fn all() -> impl Iterator<Item=Piece> {
+        PIECE_ARRAY.iter().cloned()
+    }
+
+    /// 空マスなら真。
+    pub fn is_space(self) -> bool {
+        matches!(self, Piece::Space | Piece::Num)
+    }
+
+    /// 駒の持ち主。空マスで呼ばないこと。
+    pub fn color(self) -> Color {
+        debug_assert!(!self.is_space(), "color of space");
+        if (self as usize)<PIECE_TYPE_ARRAY.len() { Color::Sente } else { Color::Gote }
+    }
+
+    /// 先後を外した駒種類。空マスは空マス。
+    pub fn piece_type(self) -> PieceType {
+        if self.is_space() { return PieceType::Space; }
+        PIECE_TYPE_ARRAY[self as usize % PIECE_TYPE_ARRAY.len()]
+    }
+
+    /// 成った駒。成れない駒と空マスはそのまま。
+    pub fn promote(self) -> Piece {
+        if self.is_space() { return self; }
+        Piece::from(self.color(), self.piece_type().promote())
+    }
+
+    /// 成る前の駒。
+    pub fn demote(self) -> Piece {
+        if self.is_space() { return self; }
+        Piece::from(self.color(), self.piece_type().demote())
+    }
+
+    /// sfen の1文字から。大文字が先手、小文字が後手。成りの `+` は付かないので、成っていない駒だけ。
+    pub fn from_sfen_char(c:char) -> Option<Piece> {
+        let pt = PieceType::from_sfen_char(c.to_ascii_uppercase())?;
+        Some(Piece::from(if c.is_ascii_uppercase() { Color::Sente } else { Color::Gote }, pt))
+    }
+
+    /// sfen の1文字。成り駒は成る前の駒の文字で、`+` は付けない。空マスは ' '。
+    pub fn to_sfen_char(self) -> char {
+        if self.is_space() { return ' '; }
+        let c = self.piece_type().to_sfen_char();
+        if self.color()==Color::Sente { c } else { c.to_ascii_lowercase() }
+    }
+
+    /// sfen の表記。成り駒は `+` 付きの2文字。空マスは空文字列。
+    pub fn to_sfen(self) -> &'static str {
+        use Piece::*;
+        match self {
+            K0 => "K", R0 => "R", B0 => "B", G0 => "G", S0 => "S", N0 => "N", L0 => "L", P0 => "P",
+            PR0 => "+R", PB0 => "+B", PS0 => "+S", PN0 => "+N", PL0 => "+L", PP0 => "+P",
+            K1 => "k", R1 => "r", B1 => "b", G1 => "g", S1 => "s", N1 => "n", L1 => "l", P1 => "p",
+            PR1 => "+r", PB1 => "+b", PS1 => "+s", PN1 => "+n", PL1 => "+l", PP1 => "+p",
+            Space | Num => "",
+        }
+    }
+}
+/// sfen の `K` や `+p` を読む。
+impl FromStr for Piece{
+    type Err = ParsePieceError;
+    fn from_str(text:&str) -> Result<Piece, ParsePieceError> {
+        let error = || ParsePieceError{ text : text.to_string() };
+        let (promoted, letter) = match text.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let mut chars = letter.chars();
+        let pc = match (chars.next(), chars.next()) {
+            (Some(c), None) => Piece::from_sfen_char(c).ok_or_else(error)?,
+            _ => { return Err(error()); },
+        };
+        if !promoted { return Ok(pc); }
+        if !pc.piece_type().can_promote() { return Err(error()); }
+        Ok(pc.promote())
+    }
+}
+
 /// 持ち駒
 pub const HAND_PIECE_ARRAY_LN : usize = 14;
 pub const HAND_PIECE_ARRAY : [Piece; HAND_PIECE_ARRAY_LN] = [
@@ -247,7 +566,7 @@ pub fn hand_piece_to_num(pc: Piece) -> usize {
 /// * `destination_file` - 移動先の筋。
 /// * `destination_rank` - 移動先の段。
 /// * `promotion` - 移動後に成るなら真。
-#[derive(Copy,Clone)]
+#[derive(Copy,Clone,PartialEq,Eq,Hash)]
 pub struct UsiMovement{
     pub source_file : i8,
     pub source_rank : i8,
@@ -256,8 +575,8 @@ pub struct UsiMovement{
     pub destination_rank : i8,
     pub promotion : bool,
 }
-impl UsiMovement{
-    pub fn default()->UsiMovement{
+impl Default for UsiMovement{
+    fn default()->UsiMovement{
         UsiMovement{
             source_file : 0,
             source_rank : 0,
@@ -318,6 +637,72 @@ impl fmt::Debug for UsiMovement{
     }
 }
 
+/// go コマンドの引数。時間はミリ秒。
+///
+/// # Members.
+///
+/// * `ponder` - 先読みなら真。
+/// * `btime` - 先手の残り時間。
+/// * `wtime` - 後手の残り時間。
+/// * `byoyomi` - 秒読み。
+/// * `binc` - 先手の1手ごとの加算時間。
+/// * `winc` - 後手の1手ごとの加算時間。
+/// * `infinite` - stop が来るまで考えるなら真。
+#[derive(Copy,Clone,Debug,Default)]
+pub struct GoParams{
+    pub ponder : bool,
+    pub btime : u64,
+    pub wtime : u64,
+    pub byoyomi : u64,
+    pub binc : u64,
+    pub winc : u64,
+    pub infinite : bool,
+}
+impl fmt::Display for GoParams{
+    /// `go` の後ろに続く部分を書き出す。
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        if self.infinite { return write!(f,"infinite"); }
+        if self.ponder { write!(f,"ponder ")?; }
+        write!(f, "btime {} wtime {}", self.btime, self.wtime)?;
+        if 0<self.binc || 0<self.winc {
+            write!(f, " binc {} winc {}", self.binc, self.winc)
+        } else {
+            write!(f, " byoyomi {}", self.byoyomi)
+        }
+    }
+}
+
+/// go コマンドの `go` より後ろを字句解析。知らない語は読み飛ばす。
+///
+/// # Examples input.
+///
+/// * `btime 60000 wtime 50000 byoyomi 10000`
+/// * `ponder btime 60000 wtime 50000 binc 1000 winc 1000`
+/// * `infinite`
+pub fn parse_go(line:&str, starts:&mut usize, len:usize) -> GoParams {
+    let mut params = GoParams::default();
+    let mut tokens = line[*starts..len].split_whitespace();
+    while let Some(token) = tokens.next() {
+        match token {
+            "ponder" => { params.ponder = true; },
+            "infinite" => { params.infinite = true; },
+            "btime" | "wtime" | "byoyomi" | "binc" | "winc" => {
+                let value = tokens.next().and_then(|x| x.parse().ok()).unwrap_or(0);
+                match token {
+                    "btime" => { params.btime = value; },
+                    "wtime" => { params.wtime = value; },
+                    "byoyomi" => { params.byoyomi = value; },
+                    "binc" => { params.binc = value; },
+                    _ => { params.winc = value; },
+                }
+            },
+            _ => {},
+        }
+    }
+    *starts = len;
+    params
+}
+
 
 /// 開始地点から文字列が一致すれば、カーソルを進めて真を返す。
 pub fn starts_with_and_forward(
@@ -405,41 +790,21 @@ pub fn parse_board(line:&str, starts:&mut usize, len:usize) -> [Piece;100] {
                     ban[file_rank_to_cell(file,rank)] = Space; file-=1;
                 }
             },
-            "K" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::K0; file-=1; },
-            "R" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::R0; file-=1; },
-            "B" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::B0; file-=1; },
-            "G" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::G0; file-=1; },
-            "S" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::S0; file-=1; },
-            "N" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::N0; file-=1; },
-            "L" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::L0; file-=1; },
-            "P" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::P0; file-=1; },
-            "k" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::K1; file-=1; },
-            "r" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::R1; file-=1; },
-            "b" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::B1; file-=1; },
-            "g" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::G1; file-=1; },
-            "s" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::S1; file-=1; },
-            "n" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::N1; file-=1; },
-            "l" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::L1; file-=1; },
-            "p" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::P1; file-=1; },
             "+" => {
                 *starts+=1;
-                match &line[*starts..(*starts+1)]{
-                    "R" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PR0; file-=1; },
-                    "B" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PB0; file-=1; },
-                    "S" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PS0; file-=1; },
-                    "N" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PN0; file-=1; },
-                    "L" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PL0; file-=1; },
-                    "P" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PP0; file-=1; },
-                    "r" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PR1; file-=1; },
-                    "b" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PB1; file-=1; },
-                    "s" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PS1; file-=1; },
-                    "n" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PN1; file-=1; },
-                    "l" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PL1; file-=1; },
-                    "p" => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = Piece::PP1; file-=1; },
-                    _ => { panic!(format!("盤部(0) '{}' だった。", &line[*starts..(*starts+1)]));},
-                }                    
+                // 成れる駒の文字が続くはず。
+                let pc = line[*starts..].chars().next().and_then(Piece::from_sfen_char);
+                match pc {
+                    Some(pc) if pc.piece_type().can_promote() => {
+                        *starts+=1;  ban[file_rank_to_cell(file,rank)] = pc.promote(); file-=1;
+                    },
+                    _ => { panic!("盤部(0) '{}' だった。", &line[*starts..(*starts+1)]);},
+                }
+            },
+            letter => match letter.chars().next().and_then(Piece::from_sfen_char) {
+                Some(pc) => { *starts+=1;  ban[file_rank_to_cell(file,rank)] = pc; file-=1; },
+                None => {break 'ban;}, // 盤部正常終了
             },
-            _ => {break 'ban;}, // 盤部正常終了
         }
     }
 
@@ -447,72 +812,38 @@ pub fn parse_board(line:&str, starts:&mut usize, len:usize) -> [Piece;100] {
     ban
 }
 
-/// 持ち駒を字句解析
-pub fn parse_hand_piece(line:&str, starts:&mut usize, len:usize) -> [i8; HAND_PIECE_ARRAY_LN]{
-    // 持ち駒数。増減させたいので、u8 ではなく i8。
-    let mut hand_count_arr = [0i8; HAND_PIECE_ARRAY_LN];
+/// 持ち駒を字句解析。先手、後手の順。
+///
+/// # Examples input.
+///
+/// * `-`
+/// * `2Pb`
+/// * `R2G10p`
+pub fn parse_hand_piece(line:&str, starts:&mut usize, len:usize) -> [Hand; 2]{
+    let mut hands = [Hand::EMPTY; 2];
 
     // 持ち駒の読取
     if starts_with_and_forward(line, starts, "-") {
-        // 持ち駒なし。        
-    } else {
-        'mg:loop{
-            if 0<(len-*starts){
-                // 持ち駒の枚数。
-                let mut count = 1;
-                match &line[*starts..(*starts+1)]{
-                    "1"=>{
-                        // 1枚のときは数字は付かないので、10～18 と確定☆
-                        match &line[*starts..(*starts+1)]{
-                            "0"=>{count=10; *starts+=2;},
-                            "1"=>{count=11; *starts+=2;},
-                            "2"=>{count=12; *starts+=2;},
-                            "3"=>{count=13; *starts+=2;},
-                            "4"=>{count=14; *starts+=2;},
-                            "5"=>{count=15; *starts+=2;},
-                            "6"=>{count=16; *starts+=2;},
-                            "7"=>{count=17; *starts+=2;},
-                            "8"=>{count=18; *starts+=2;},
-                            _ => { panic!("[{}] is not hand piece.", &line[*starts..(*starts+2)]);},
-                        }
-                    },
-                    "2"=>{count=2; *starts+=1;},
-                    "3"=>{count=3; *starts+=1;},
-                    "4"=>{count=4; *starts+=1;},
-                    "5"=>{count=5; *starts+=1;},
-                    "6"=>{count=6; *starts+=1;},
-                    "7"=>{count=7; *starts+=1;},
-                    "8"=>{count=8; *starts+=1;},
-                    "9"=>{count=9; *starts+=1;},
-                    _ => {},// 駒の名前なら次へ (エラーの場合は取りこぼす)
-                }
-
-                use Piece::*;
-                let piece : Piece;
-                match &line[*starts..(*starts+1)]{
-                    "R"=>{ piece=R0; *starts+=1; },
-                    "B"=>{ piece=B0; *starts+=1; },
-                    "G"=>{ piece=G0; *starts+=1; },
-                    "S"=>{ piece=S0; *starts+=1; },
-                    "N"=>{ piece=N0; *starts+=1; },
-                    "L"=>{ piece=L0; *starts+=1; },
-                    "P"=>{ piece=P0; *starts+=1; },
-                    "r"=>{ piece=R1; *starts+=1; },
-                    "b"=>{ piece=B1; *starts+=1; },
-                    "g"=>{ piece=G1; *starts+=1; },
-                    "s"=>{ piece=S1; *starts+=1; },
-                    "n"=>{ piece=N1; *starts+=1; },
-                    "l"=>{ piece=L1; *starts+=1; },
-                    "p"=>{ piece=P1; *starts+=1; },
-                    _ => { break 'mg; }, // 持駒部 正常終了
-                }
+        // 持ち駒なし。
+        return hands;
+    }
+    while *starts<len {
+        // 持ち駒の枚数。1枚のときは数字が付かない。歩は 10～18 の2桁もある。
+        let digits = line[*starts..len].bytes().take_while(|x| x.is_ascii_digit()).count();
+        let count = if digits==0 { 1 } else { line[*starts..(*starts+digits)].parse().unwrap_or(0) };
 
-                hand_count_arr[hand_piece_to_num(piece)] = count;
-            }//if
-        }//loop
-    }//else
+        let piece = match line[(*starts+digits)..len].chars().next().and_then(Piece::from_sfen_char) {
+            Some(piece) if piece.piece_type()!=PieceType::K => piece,
+            _ => {
+                if 0<digits { panic!("[{}] is not hand piece.", &line[*starts..len]); }
+                break; // 持駒部 正常終了
+            },
+        };
+        *starts += digits + 1;
+        hands[piece.color().index()].set(piece.piece_type(), count);
+    }
 
-    hand_count_arr
+    hands
 }
 
 /// 指し手文字列から、打った駒種類を抽出します。
@@ -571,7 +902,7 @@ pub fn parse_movement(
                 "7" => 7,
                 "8" => 8,
                 "9" => 9,
-                _ => {panic!(format!("(1) '{}' だった。", &line[*starts..(*starts+1)]));},
+                _ => {panic!("(1) '{}' だった。", &line[*starts..(*starts+1)]);},
             };
             *starts+=1;
 
@@ -585,7 +916,7 @@ pub fn parse_movement(
                 "g" => 7,
                 "h" => 8,
                 "i" => 9,
-                _ => {panic!(format!("(2) '{}' だった。", &line[*starts..(*starts+1)]));},
+                _ => {panic!("(2) '{}' だった。", &line[*starts..(*starts+1)]);},
             };
             *starts+=1;
         },
@@ -602,7 +933,7 @@ pub fn parse_movement(
         "7" => 7,
         "8" => 8,
         "9" => 9,
-        _ => {panic!(format!("(3) '{}' だった。", &line[*starts..(*starts+1)]));},
+        _ => {panic!("(3) '{}' だった。", &line[*starts..(*starts+1)]);},
     };
     *starts+=1;
     
@@ -617,7 +948,7 @@ pub fn parse_movement(
         "g" => 7,
         "h" => 8,
         "i" => 9,
-        _ => {panic!(format!("(4) '{}' だった。", &line[*starts..(*starts+1)]));},
+        _ => {panic!("(4) '{}' だった。", &line[*starts..(*starts+1)]);},
     };
     *starts+=1;
     
@@ -627,9 +958,70 @@ pub fn parse_movement(
     }
 
     // 続きにスペース「 」が１つあれば読み飛ばす
-    if starts_with_and_forward(line, starts, " ") {
-    }
+    starts_with_and_forward(line, starts, " ");
 
     // 残りは「筋の数字」、「段のアルファベット」のはず。成り
     (true, result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sfen_round_trip_of_every_piece() {
+        assert_eq!(Piece::all().count(), 28);
+        for pc in Piece::all() {
+            assert_eq!(pc.to_sfen().parse::<Piece>(), Ok(pc));
+            assert_eq!(pc.to_sfen().parse::<PieceType>(), Ok(pc.piece_type()));
+            assert_eq!(Piece::from(pc.color(), pc.piece_type()), pc);
+            // 1文字の表記は成りを落とす。
+            assert_eq!(Piece::from_sfen_char(pc.to_sfen_char()), Some(pc.demote()));
+        }
+        assert_eq!("+r".parse::<Piece>(), Ok(Piece::PR1));
+        assert_eq!("k".parse::<PieceType>(), Ok(PieceType::K));
+        assert_eq!(Piece::Space.to_sfen(), "");
+        assert_eq!(Piece::Space.to_sfen_char(), ' ');
+    }
+
+    #[test]
+    fn promote_and_demote() {
+        for pt in PieceType::all() {
+            if pt.can_promote() {
+                assert!(pt.promote().is_promoted());
+                assert_eq!(pt.promote().demote(), pt);
+            } else {
+                // 玉、金、成り駒は成っても変わらない。
+                assert_eq!(pt.promote(), pt);
+            }
+            assert!(!pt.demote().is_promoted());
+        }
+        for &pt in [PieceType::K, PieceType::G, PieceType::Space].iter() {
+            assert_eq!(pt.promote(), pt);
+            assert_eq!(pt.demote(), pt);
+        }
+        assert_eq!(Piece::G1.promote(), Piece::G1);
+        assert_eq!(Piece::K0.demote(), Piece::K0);
+        assert_eq!(Piece::S1.promote(), Piece::PS1);
+        assert_eq!(Piece::PP0.demote(), Piece::P0);
+        assert_eq!(Piece::Space.promote(), Piece::Space);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for &text in ["", "+", "X", "KK", "+K", "+g", "++P", "P+", "歩"].iter() {
+            assert_eq!(text.parse::<Piece>(), Err(ParsePieceError{ text : text.to_string() }), "{}", text);
+            assert!(text.parse::<PieceType>().is_err(), "{}", text);
+        }
+        assert_eq!("+K".parse::<Piece>().unwrap_err().to_string(), "'+K' は駒ではない。");
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(PieceType::PR.to_string(), "竜");
+        assert_eq!(PieceType::PB.to_string(), "馬");
+        assert_eq!(Piece::PR0.to_string(), "▼竜");
+        assert_eq!(Piece::PB1.to_string(), "△馬");
+        assert_eq!(Piece::P0.to_string(), "▼歩");
+    }
+}
@@ -0,0 +1,601 @@
+//! 詰将棋の探索☆（＾～＾） df-pn（証明数と反証数の深さ優先探索）。
+//!
+//! 手番の側が攻め方。攻め方は王手だけを指し、玉方は全部の合法手で逃げる。
+//! 置換表は盤上と手番で引き、持ち駒の優劣で証明と反証を使い回す。
+//! 千日手になる手順は攻め方の失敗とみなす。その反証は、繰り返した局面が
+//! 今の手順の上にあるときだけ置換表から使う（経路依存の問題への対処）。
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use engine_output::Checkmate;
+use movegen::can_drop;
+use position::{Position, can_slide, is_on_board, step_directions};
+use hand::{HAND_PIECE_TYPES, Hand};
+use {Color, Piece, PieceType, UsiMovement};
+use file_rank_to_cell;
+
+/// 証明数、反証数の無限大。
+const INFINITE : u32 = u32::MAX / 2;
+/// これより深い手順は詰まないものとして扱う。
+const MAX_DEPTH : u32 = 255;
+
+/// 探索を打ち切る条件。None なら制限なし。
+///
+/// # Members.
+///
+/// * `nodes` - 探索する局面数。
+/// * `time` - 思考時間。
+#[derive(Copy,Clone,Debug,Default)]
+pub struct MateLimits{
+    pub nodes : Option<u64>,
+    pub time : Option<Duration>,
+}
+
+/// 置換表の1項目。同じ盤上で持ち駒が違えば別の項目。
+///
+/// 証明と、それ以外（反証か調べかけ）は同じ持ち駒でも別の項目にする。
+///
+/// * `length` - 証明したとき、何手以内に詰むか。
+/// * `remaining` - 反証したとき、残り何手まで調べたか。
+/// * `repetition` - 千日手で反証したなら、繰り返した局面のハッシュ。
+struct Entry{
+    hand : [Hand; 2],
+    pn : u32,
+    dn : u32,
+    length : u32,
+    remaining : u32,
+    repetition : Vec<u64>,
+}
+
+/// 局面を引いた結果。
+#[derive(Clone,Default)]
+struct Lookup{
+    pn : u32,
+    dn : u32,
+    length : u32,
+    repetition : Vec<u64>,
+}
+
+/// 詰み手順を取り出すときの覚え書き。
+enum MainLine{
+    /// 最短の詰み手順。
+    Found(Vec<UsiMovement>),
+    /// この手数以内では詰まない。
+    Longer(u32),
+}
+
+struct Solver{
+    /// 攻め方。
+    attacker : Color,
+    table : HashMap<u64, Vec<Entry>>,
+    /// 今の手順の上にある局面の `key`。
+    path : HashSet<u64>,
+    nodes : u64,
+    limits : MateLimits,
+    started : Instant,
+    aborted : bool,
+    /// この深さで打ち切る。手順を短くするときは浅くする。
+    depth_limit : u32,
+}
+impl Solver{
+    fn new(attacker:Color, limits:MateLimits) -> Solver {
+        Solver{
+            attacker,
+            table : HashMap::new(),
+            path : HashSet::new(),
+            nodes : 0,
+            limits,
+            started : Instant::now(),
+            aborted : false,
+            depth_limit : MAX_DEPTH,
+        }
+    }
+
+    /// 探索局面数か時間を使い切っていれば真。
+    fn is_over(&mut self) -> bool {
+        if self.aborted { return true; }
+        if let Some(nodes) = self.limits.nodes {
+            if nodes<=self.nodes { self.aborted = true; }
+        }
+        if let Some(time) = self.limits.time {
+            if time<=self.started.elapsed() { self.aborted = true; }
+        }
+        self.aborted
+    }
+
+    /// 持ち駒 `a` が `b` 以上に攻め方に都合がいいなら真。攻め方の持ち駒が多く、玉方の持ち駒が少ない。
+    fn is_superior(&self, a:&[Hand; 2], b:&[Hand; 2]) -> bool {
+        let attacker = self.attacker.index();
+        let defender = self.attacker.opponent().index();
+        a[attacker].is_superior_or_equal(b[attacker]) && b[defender].is_superior_or_equal(a[defender])
+    }
+
+    fn lookup(&self, position:&Position, depth:u32) -> Lookup {
+        self.lookup_keyed(position, position.key(), position.board_key(), depth)
+    }
+
+    /// `key` と `board_key` を計算済みの局面を引く。残りの手数より長い証明と、浅く調べただけの反証は使わない。
+    fn lookup_keyed(&self, position:&Position, key:u64, board_key:u64, depth:u32) -> Lookup {
+        let remaining = self.depth_limit.saturating_sub(depth);
+        if self.path.contains(&key) {
+            return Lookup{ pn : INFINITE, dn : 0, length : 0, repetition : vec![key] };
+        }
+        let mut found = Lookup{ pn : 1, dn : 1, ..Lookup::default() };
+        if let Some(entries) = self.table.get(&board_key) {
+            for entry in entries.iter() {
+                if entry.pn==0 && entry.length<=remaining && self.is_superior(&position.hand, &entry.hand) {
+                    return Lookup{ pn : 0, dn : INFINITE, length : entry.length, repetition : Vec::new() };
+                }
+                if entry.dn==0 && self.is_superior(&entry.hand, &position.hand) && remaining<=entry.remaining
+                    && entry.repetition.iter().all(|x| self.path.contains(x)) {
+                    return Lookup{ pn : INFINITE, dn : 0, length : 0, repetition : entry.repetition.clone() };
+                }
+                if entry.hand==position.hand && entry.pn!=0 && entry.dn!=0 {
+                    found.pn = entry.pn;
+                    found.dn = entry.dn;
+                }
+            }
+        }
+        found
+    }
+
+    /// 証明は短い方を残す。
+    fn store(&mut self, position:&Position, found:Lookup, remaining:u32) {
+        let entries = self.table.entry(position.board_key()).or_default();
+        let proven = found.pn==0;
+        let entry = Entry{ hand : position.hand, pn : found.pn, dn : found.dn, length : found.length, remaining, repetition : found.repetition };
+        match entries.iter_mut().find(|x| x.hand==position.hand && (x.pn==0)==proven) {
+            Some(x) => { if !proven || entry.length<x.length { *x = entry; } },
+            None => { entries.push(entry); },
+        }
+    }
+
+    fn proof(length:u32) -> Lookup {
+        Lookup{ pn : 0, dn : INFINITE, length, repetition : Vec::new() }
+    }
+
+    fn disproof() -> Lookup {
+        Lookup{ pn : INFINITE, dn : 0, ..Lookup::default() }
+    }
+
+    /// 同じ盤上、同じ持ち駒の項目があれば真。
+    fn has_entry(&self, position:&Position, board_key:u64) -> bool {
+        self.table.get(&board_key).is_some_and(|x| x.iter().any(|entry| entry.hand==position.hand))
+    }
+
+    /// 初めて見る局面の証明数と反証数を、手の数で見積もって置く。受けが無ければ詰み、王手が1手詰めなら証明済み。
+    fn initialize(&mut self, position:&Position) {
+        let found = if position.turn==self.attacker {
+            let checks = position.check_moves();
+            let mate1 = checks.iter().any(|x| {
+                let mut next = *position;
+                next.do_move(x);
+                next.evasions().is_empty()
+            });
+            if mate1 {
+                Solver::proof(1)
+            } else if checks.is_empty() {
+                Solver::disproof()
+            } else {
+                Lookup{ pn : 1, dn : checks.len() as u32, ..Lookup::default() }
+            }
+        } else {
+            let evasions = position.evasions();
+            if evasions.is_empty() {
+                Solver::proof(0)
+            } else {
+                Lookup{ pn : evasions.len() as u32, dn : 1, ..Lookup::default() }
+            }
+        };
+        self.store(position, found, u32::MAX);
+    }
+
+    /// 攻め方の手番なら王手、玉方の手番なら王手の受け。
+    fn children(&self, position:&Position) -> Vec<UsiMovement> {
+        if position.turn==self.attacker { position.check_moves() } else { position.evasions() }
+    }
+
+    /// 証明数か反証数が閾値に届くまで調べる。
+    fn search(&mut self, position:&Position, threshold_pn:u32, threshold_dn:u32, depth:u32) {
+        self.nodes += 1;
+        let or_node = position.turn==self.attacker;
+        let remaining = self.depth_limit.saturating_sub(depth);
+        if remaining==0 {
+            // 深さの打ち切り。玉方がもう詰んでいるときだけ証明。
+            let found = if or_node || position.has_legal_move() { Solver::disproof() } else { Solver::proof(0) };
+            self.store(position, found, 0);
+            return;
+        }
+        let moves = self.children(position);
+        if moves.is_empty() {
+            // 王手が無ければ不詰、逃げる手が無ければ詰み。
+            let found = if or_node { Solver::disproof() } else { Solver::proof(0) };
+            self.store(position, found, u32::MAX);
+            return;
+        }
+        let key = position.key();
+        let children : Vec<(Position, u64, u64)> = moves.iter().map(|x| {
+            let mut next = *position;
+            next.do_move(x);
+            (next, next.key(), next.board_key())
+        }).collect();
+        for x in children.iter() {
+            if self.has_entry(&x.0, x.2) { continue; }
+            let found = self.lookup_keyed(&x.0, x.1, x.2, depth + 1);
+            if found.pn!=0 && found.dn!=0 { self.initialize(&x.0); }
+        }
+        self.path.insert(key);
+        loop {
+            let found : Vec<Lookup> = children.iter().map(|x| self.lookup_keyed(&x.0, x.1, x.2, depth + 1)).collect();
+            // 攻め方の局面は証明数の最小と反証数の和、玉方の局面はその逆。
+            let (mut min, mut sum) = (INFINITE, 0u32);
+            let (mut best, mut second) = (0, INFINITE);
+            for (i, x) in found.iter().enumerate() {
+                let (a, b) = if or_node { (x.pn, x.dn) } else { (x.dn, x.pn) };
+                sum = sum.saturating_add(b).min(INFINITE);
+                if a<min {
+                    second = min;
+                    min = a;
+                    best = i;
+                } else if a<second {
+                    second = a;
+                }
+            }
+            let (pn, dn) = if or_node { (min, sum) } else { (sum, min) };
+            let mut entry = Lookup{ pn, dn, ..Lookup::default() };
+            if pn==0 {
+                // 攻め方の局面は一番短い子の、玉方の局面は一番長い子の手数に1手足す。
+                let lengths = found.iter().filter(|x| x.pn==0).map(|x| x.length);
+                entry.length = 1 + if or_node { lengths.min() } else { lengths.max() }.unwrap_or(0);
+            }
+            if dn==0 {
+                // 攻め方の局面は全部の子の、玉方の局面は一番ましな子の千日手を引き継ぐ。自分自身への繰り返しは外す。
+                let disproved = found.iter().filter(|x| x.dn==0);
+                let repetition : Vec<u64> = if or_node {
+                    disproved.flat_map(|x| x.repetition.iter().cloned()).collect()
+                } else {
+                    disproved.min_by_key(|x| x.repetition.len()).map(|x| x.repetition.clone()).unwrap_or_default()
+                };
+                for r in repetition {
+                    if r!=key && !entry.repetition.contains(&r) { entry.repetition.push(r); }
+                }
+            }
+            self.store(position, entry, remaining);
+            if threshold_pn<=pn || threshold_dn<=dn || self.is_over() { break; }
+
+            // 一番有望な子を、2番目に追い越されるまで調べる。
+            let child = &found[best];
+            let (child_pn, child_dn) = if or_node {
+                (threshold_pn.min(second.saturating_add(1)), (threshold_dn - dn).saturating_add(child.dn).min(INFINITE))
+            } else {
+                ((threshold_pn - pn).saturating_add(child.pn).min(INFINITE), threshold_dn.min(second.saturating_add(1)))
+            };
+            self.search(&children[best].0, child_pn, child_dn, depth + 1);
+        }
+        self.path.remove(&key);
+    }
+
+    /// 子が `bound` 手以内に詰むかを調べる。
+    fn ensure(&mut self, position:&Position, depth:u32, bound:u32) -> Lookup {
+        let depth_limit = self.depth_limit;
+        self.depth_limit = depth + bound;
+        let mut found = self.lookup(position, depth);
+        if found.pn!=0 && found.dn!=0 && !self.is_over() {
+            self.search(position, INFINITE, INFINITE, depth);
+            found = self.lookup(position, depth);
+        }
+        self.depth_limit = depth_limit;
+        found
+    }
+
+    /// `bound` 手以内で最短の詰み手順を取り出す。攻め方は最短、玉方は最長を選ぶ。
+    ///
+    /// 攻め方は、何手以内で詰むかを1手、3手、5手と短い方から確かめ、その手数で詰む王手を選ぶ。
+    fn main_line(&mut self, position:&Position, depth:u32, bound:u32, memo:&mut HashMap<u64, MainLine>) -> Option<Vec<UsiMovement>> {
+        let key = position.key();
+        match memo.get(&key) {
+            Some(MainLine::Found(line)) => { return if line.len() as u32<=bound { Some(line.clone()) } else { None }; },
+            Some(&MainLine::Longer(x)) if bound<=x => { return None; },
+            _ => {},
+        }
+        if self.path.contains(&key) || MAX_DEPTH<=depth { return None; }
+        let or_node = position.turn==self.attacker;
+        let moves = self.children(position);
+        if moves.is_empty() {
+            return if or_node { None } else { Some(Vec::new()) };
+        }
+        if bound==0 { return None; }
+
+        let plies = if or_node {
+            match (1..=bound).step_by(2).find(|&x| self.ensure(position, depth, x).pn==0) {
+                Some(x) => x,
+                None => {
+                    memo.insert(key, MainLine::Longer(bound));
+                    return None;
+                },
+            }
+        } else {
+            bound
+        };
+        self.path.insert(key);
+        let mut best : Option<Vec<UsiMovement>> = if or_node { None } else { Some(Vec::new()) };
+        for movement in moves.iter() {
+            let mut next = *position;
+            next.do_move(movement);
+            if or_node && self.ensure(&next, depth + 1, plies - 1).pn!=0 { continue; }
+            match self.main_line(&next, depth + 1, plies - 1, memo) {
+                Some(mut line) => {
+                    line.insert(0, *movement);
+                    if or_node {
+                        best = Some(line);
+                        break;
+                    }
+                    if best.as_ref().is_some_and(|x| x.len()<line.len()) { best = Some(line); }
+                },
+                None => {
+                    if !or_node {
+                        best = None;
+                        break;
+                    }
+                },
+            }
+        }
+        self.path.remove(&key);
+        memo.insert(key, match best {
+            Some(ref line) => MainLine::Found(line.clone()),
+            None => MainLine::Longer(bound),
+        });
+        best
+    }
+}
+
+/// 手番の側が玉方を詰ませる最短手順を探す。
+///
+/// 玉方の持ち駒はそのまま使う。詰将棋として解くなら `Position::with_defender_hand_filled` を通してから渡す。
+/// 詰みを見つけた後も、残りの探索量で、もっと短い手数で詰まないか調べる。
+pub fn solve_mate(position:&Position, limits:&MateLimits) -> Checkmate {
+    solve_mate_within(position, MAX_DEPTH, limits)
+}
+
+/// `plies` 手以内の詰みだけを探す。それより長い詰みしか無ければ `Checkmate::NoMate`。
+pub fn solve_mate_within(position:&Position, plies:u32, limits:&MateLimits) -> Checkmate {
+    // 玉方がもう王手されているのは反則の局面。
+    if position.in_check(position.turn.opponent()) { return Checkmate::NoMate; }
+    let mut solver = Solver::new(position.turn, *limits);
+    solver.depth_limit = plies.min(MAX_DEPTH);
+    solver.search(position, INFINITE, INFINITE, 0);
+    let found = solver.lookup(position, 0);
+    if found.dn==0 { return Checkmate::NoMate; }
+    if found.pn!=0 { return Checkmate::Timeout; }
+    match solver.main_line(position, 0, found.length, &mut HashMap::new()) {
+        Some(line) => Checkmate::Mate(line),
+        None => Checkmate::Timeout,
+    }
+}
+
+/// `from` の駒を `to` へ動かすと、玉 `king` が相手の走り駒に素通しになるなら真。
+///
+/// `to` が玉と走り駒の間か走り駒のマスなら、筋を塞いだままなので外れない。
+fn is_pinned(position:&Position, king:(i8, i8), from:(i8, i8), to:(i8, i8)) -> bool {
+    let (df, dr) = (from.0 - king.0, from.1 - king.1);
+    if !(df==0 || dr==0 || df.abs()==dr.abs()) { return false; }
+    let (sf, sr) = (df.signum(), dr.signum());
+    let color = position.piece(king.0, king.1).color();
+    let mut passed = false;
+    let (mut f, mut r) = (king.0 + sf, king.1 + sr);
+    while is_on_board(f, r) {
+        if (f, r)==to { return false; }
+        if (f, r)==from {
+            passed = true;
+        } else {
+            let pc = position.piece(f, r);
+            if !pc.is_space() { return passed && pc.color()!=color && can_slide(pc, -sf, -sr); }
+        }
+        f += sf;
+        r += sr;
+    }
+    false
+}
+
+/// 王手を掛けられた手番の側に受けが無ければ真。指し手は作らず、利きだけで調べる。
+///
+/// * 玉の逃げ道 - 玉を盤から外して、周りのマスに相手の利きがあるか。走り駒の王手の先へは逃げられない。
+/// * 両王手 - 逃げ道が無ければ詰み。
+/// * 王手している駒を取る手、走り駒との間に動かす手 - その駒がピンされていなければ受かる。
+/// * 合駒を打つ手 - 間のマスに打てる持ち駒があれば受かる。
+fn is_mated(position:&Position) -> bool {
+    let defender = position.turn;
+    let attacker = defender.opponent();
+    let king = match position.king_square(defender) {
+        Some(x) => x,
+        None => { return false; },
+    };
+    let mut without_king = *position;
+    without_king.board[file_rank_to_cell(king.0, king.1)] = Piece::Space;
+    for &(df, dr) in step_directions(PieceType::K).iter() {
+        let to = (king.0 + df, king.1 + dr);
+        if !is_on_board(to.0, to.1) { continue; }
+        let target = position.piece(to.0, to.1);
+        if (target.is_space() || target.color()==attacker) && !without_king.is_attacked(to.0, to.1, attacker) {
+            return false;
+        }
+    }
+
+    let checkers = position.attackers(king.0, king.1, attacker);
+    if checkers.len()!=1 { return !checkers.is_empty(); }
+    let checker = checkers[0];
+    let reachable = |to:(i8, i8)| position.attackers(to.0, to.1, defender).into_iter()
+        .any(|from| from!=king && !is_pinned(position, king, from, to));
+    if reachable(checker) { return false; }
+    let (df, dr) = (checker.0 - king.0, checker.1 - king.1);
+    if (df==0 || dr==0 || df.abs()==dr.abs()) && (1<df.abs() || 1<dr.abs()) {
+        let (sf, sr) = (df.signum(), dr.signum());
+        let (mut f, mut r) = (king.0 + sf, king.1 + sr);
+        while (f, r)!=checker {
+            if reachable((f, r)) || HAND_PIECE_TYPES.iter().any(|&pt| can_drop(position, defender, pt, f, r)) {
+                return false;
+            }
+            f += sf;
+            r += sr;
+        }
+    }
+    true
+}
+
+/// 1手詰めの手。王手の中から、指した後に受けが無い手を利きで探す。玉方の指し手は作らない。
+pub fn find_mate1(position:&Position) -> Option<UsiMovement> {
+    if position.in_check(position.turn.opponent()) { return None; }
+    position.check_moves().into_iter().find(|x| {
+        let mut next = *position;
+        next.do_move(x);
+        is_mated(&next)
+    })
+}
+
+/// 3手以内で詰む初手。どの受けにも1手詰めがある王手を探す。
+///
+/// 1手詰めが先に見つかれば受けは作らない。受けは玉の逃げる手から調べ、
+/// 1手詰めの無い受けが1つ見つかった王手は、残りの受けを調べずに捨てる。
+pub fn find_mate3(position:&Position) -> Option<UsiMovement> {
+    if let Some(movement) = find_mate1(position) { return Some(movement); }
+    if position.in_check(position.turn.opponent()) { return None; }
+    position.check_moves().into_iter().find(|movement| {
+        let mut next = *position;
+        next.do_move(movement);
+        next.evasions().iter().all(|evasion| {
+            let mut after = next;
+            after.do_move(evasion);
+            find_mate1(&after).is_some()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> MateLimits {
+        MateLimits{ nodes : Some(100_000), time : None }
+    }
+
+    /// 玉方の持ち駒を残り駒全部にして解く。
+    fn solve(sfen:&str, limits:&MateLimits) -> Checkmate {
+        solve_mate(&Position::from_sfen(sfen).with_defender_hand_filled(), limits)
+    }
+
+    /// 手順を指し終えると詰んでいて、長さが `plies` 手。
+    fn assert_mate(sfen:&str, plies:usize) {
+        let line = match solve(sfen, &limits()) {
+            Checkmate::Mate(line) => line,
+            other => panic!("{}: {}", sfen, other),
+        };
+        assert_eq!(line.len(), plies, "{}", sfen);
+        let mut position = Position::from_sfen(sfen).with_defender_hand_filled();
+        for movement in line.iter() {
+            assert!(position.legal_moves().contains(movement), "{} {}", sfen, movement);
+            position.do_move(movement);
+        }
+        assert!(position.is_checkmated(), "{}", sfen);
+    }
+
+    #[test]
+    fn mate_in_1() {
+        assert_mate("8k/9/8P/9/9/9/9/9/9 b G 1", 1);
+        assert!(find_mate1(&Position::from_sfen("8k/9/8P/9/9/9/9/9/9 b G 1")).is_some());
+    }
+
+    #[test]
+    fn mate_in_3() {
+        assert_mate("8k/6G2/9/9/9/9/9/9/9 b R 1", 3);
+    }
+
+    #[test]
+    fn mate_in_5() {
+        assert_mate("7gk/8l/9/5+R2L/9/9/9/9/9 b SN 1", 5);
+    }
+
+    #[test]
+    fn no_mate() {
+        assert!(matches!(solve("8k/9/9/9/9/9/9/9/9 b G 1", &limits()), Checkmate::NoMate));
+    }
+
+    #[test]
+    fn pawn_drop_mate_is_refuted() {
+        // ▲１二歩打は詰みになるので打ち歩詰めの反則。他に詰ませる手は無い。
+        let sfen = "8k/9/6NG1/9/9/9/9/9/9 b P 1";
+        let position = Position::from_sfen(sfen);
+        let drop = position.pseudo_legal_moves().into_iter().find(|x| x.to_string()=="P*1b").unwrap();
+        assert!(!position.legal_moves().contains(&drop));
+        assert_eq!(find_mate1(&position), None);
+        assert!(matches!(solve(sfen, &limits()), Checkmate::NoMate));
+    }
+
+    #[test]
+    fn node_limit_gives_up() {
+        let limits = MateLimits{ nodes : Some(1), time : None };
+        assert!(matches!(solve("7gk/8l/9/5+R2L/9/9/9/9/9 b SN 1", &limits), Checkmate::Timeout));
+    }
+
+    #[test]
+    fn time_limit_gives_up() {
+        let limits = MateLimits{ nodes : None, time : Some(Duration::from_secs(0)) };
+        assert!(matches!(solve("7gk/8l/9/5+R2L/9/9/9/9/9 b SN 1", &limits), Checkmate::Timeout));
+    }
+
+    #[test]
+    fn mated_matches_evasions() {
+        // 利きで調べた詰みと、受けを作って調べた詰みが、どの王手の後でも一致する。
+        let sfens = [
+            "8k/9/6NG1/9/9/9/9/9/9 b P 1",
+            "7gk/8l/9/5+R2L/9/9/9/9/9 b SN 1",
+            "9/8k/9/8G/7R1/9/9/9/9 b - 1",
+            "4k4/9/4P4/9/4R4/9/9/9/4K4 b G2Pr 1",
+            "7nl/7k1/6ppp/9/9/9/9/9/8K b RBG2S 1",
+            "ln1g3nl/1r2k1g2/p1ppsp1pp/1s4p2/1p2P4/2P2PP2/PPSP3PP/2G1G2R1/LN1K3NL b Bb 1",
+        ];
+        let mut mates = 0;
+        for sfen in sfens.iter() {
+            for &filled in [false, true].iter() {
+                let mut position = Position::from_sfen(sfen);
+                if filled { position = position.with_defender_hand_filled(); }
+                for ply in 0..40 {
+                    for movement in position.check_moves().iter() {
+                        let mut next = position;
+                        next.do_move(movement);
+                        let expected = next.evasions().is_empty();
+                        assert_eq!(is_mated(&next), expected, "{} {}", position.to_sfen(), movement);
+                        if expected { mates += 1; }
+                    }
+                    let moves = position.legal_moves();
+                    if moves.is_empty() { break; }
+                    position.do_move(&moves[(ply * 7 + 3) % moves.len()]);
+                }
+            }
+        }
+        assert!(0<mates);
+    }
+
+    #[test]
+    fn short_mates_are_cheaper_than_df_pn() {
+        // 同じ問題を何度も解いた時間で比べる。1手詰めと3手詰めは、df-pn を深さで打ち切るより速い。
+        let problems = [
+            ("8k/9/8P/9/9/9/9/9/9 b G 1", 1),
+            ("9/8k/9/8G/7R1/9/9/9/9 b - 1", 3),
+            ("8k/6G2/9/9/9/9/9/9/9 b R 1", 3),
+        ];
+        let limits = limits();
+        for &(sfen, plies) in problems.iter() {
+            let position = Position::from_sfen(sfen).with_defender_hand_filled();
+            let find = if plies==1 { find_mate1 } else { find_mate3 };
+            assert!(find(&position).is_some(), "{}", sfen);
+            assert!(matches!(solve_mate_within(&position, plies, &limits), Checkmate::Mate(_)), "{}", sfen);
+            let started = Instant::now();
+            for _ in 0..20 { find(&position); }
+            let found = started.elapsed();
+            let started = Instant::now();
+            for _ in 0..20 { solve_mate_within(&position, plies, &limits); }
+            let solved = started.elapsed();
+            assert!(found * 2<solved, "{} {:?} {:?}", sfen, found, solved);
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! 指し手生成☆（＾～＾） 盤上の駒の移動と、持ち駒の打。
+//!
+//! 王手放置、二歩、行き所のない駒、打ち歩詰めを除いたものが合法手。
+use hand::HAND_PIECE_TYPES;
+use position::{Position, can_slide, can_step, is_on_board, slide_directions, step_directions};
+use Piece;
+use {FILE0, FILE10, RANK1, RANK10};
+use {Color, PieceType, UsiMovement};
+
+/// 自分から見て前から何段目か。
+fn rank_from_front(color:Color, rank:i8) -> i8 {
+    if color==Color::Sente { rank } else { RANK10 - rank }
+}
+/// 敵陣（前から3段）なら真。
+pub fn is_promotion_zone(color:Color, rank:i8) -> bool {
+    rank_from_front(color, rank)<=3
+}
+/// そこへ行くと動けなくなる駒なら真。
+fn is_dead_end(color:Color, pt:PieceType, rank:i8) -> bool {
+    match pt {
+        PieceType::P | PieceType::L => rank_from_front(color, rank)<=1,
+        PieceType::N => rank_from_front(color, rank)<=2,
+        _ => false,
+    }
+}
+
+/// 盤上の駒の移動を、成と不成の両方について足す。
+fn push_board_move(moves:&mut Vec<UsiMovement>, color:Color, pt:PieceType, from:(i8, i8), to:(i8, i8)) {
+    let movement = UsiMovement{
+        source_file : from.0,
+        source_rank : from.1,
+        drop : PieceType::Space,
+        destination_file : to.0,
+        destination_rank : to.1,
+        promotion : false,
+    };
+    if pt.can_promote() && (is_promotion_zone(color, from.1) || is_promotion_zone(color, to.1)) {
+        moves.push(UsiMovement{ promotion : true, ..movement });
+    }
+    if !is_dead_end(color, pt, to.1) {
+        moves.push(movement);
+    }
+}
+
+/// `from` にいる `pc` が `to` に利いていれば真。`vacated` のマスは空いているとみなす。
+fn attacks_from(position:&Position, pc:Piece, from:(i8, i8), to:(i8, i8), vacated:(i8, i8)) -> bool {
+    let (df, dr) = (to.0 - from.0, to.1 - from.1);
+    if can_step(pc, df, dr) { return true; }
+    if !(df==0 || dr==0 || df.abs()==dr.abs()) { return false; }
+    let (sf, sr) = (df.signum(), dr.signum());
+    if !can_slide(pc, sf, sr) { return false; }
+    let (mut f, mut r) = (from.0 + sf, from.1 + sr);
+    while (f, r)!=to {
+        if (f, r)!=vacated && !position.piece(f, r).is_space() { return false; }
+        f += sf;
+        r += sr;
+    }
+    true
+}
+
+/// 持ち駒をそのマスに打てるなら真。二歩と行き所のない駒を除く。
+pub fn can_drop(position:&Position, color:Color, pt:PieceType, file:i8, rank:i8) -> bool {
+    if position.hand_count(color, pt)==0 || !position.piece(file, rank).is_space() || is_dead_end(color, pt, rank) {
+        return false;
+    }
+    let pawn = Piece::from(color, PieceType::P);
+    !(pt==PieceType::P && (RANK1..RANK10).any(|x| position.piece(file, x)==pawn))
+}
+
+/// 持ち駒を打つ手を足す。
+fn push_drop(moves:&mut Vec<UsiMovement>, pt:PieceType, to:(i8, i8)) {
+    moves.push(UsiMovement{
+        source_file : 0,
+        source_rank : 0,
+        drop : pt,
+        destination_file : to.0,
+        destination_rank : to.1,
+        promotion : false,
+    });
+}
+
+/// `from` にいる手番の側の駒の、王手放置も含めた移動。
+fn push_piece_moves(position:&Position, moves:&mut Vec<UsiMovement>, from:(i8, i8)) {
+    let color = position.turn;
+    let sign = if color==Color::Sente { 1 } else { -1 };
+    let pt = position.piece(from.0, from.1).piece_type();
+    for &(df, dr) in step_directions(pt).iter() {
+        let to = (from.0 + df * sign, from.1 + dr * sign);
+        if !is_on_board(to.0, to.1) { continue; }
+        let target = position.piece(to.0, to.1);
+        if target.is_space() || target.color()!=color {
+            push_board_move(moves, color, pt, from, to);
+        }
+    }
+    for &(df, dr) in slide_directions(pt).iter() {
+        let mut to = (from.0 + df * sign, from.1 + dr * sign);
+        while is_on_board(to.0, to.1) {
+            let target = position.piece(to.0, to.1);
+            if !target.is_space() && target.color()==color { break; }
+            push_board_move(moves, color, pt, from, to);
+            if !target.is_space() { break; }
+            to = (to.0 + df * sign, to.1 + dr * sign);
+        }
+    }
+}
+
+/// 玉から見た8方向。
+const KING_DIRECTIONS : [(i8, i8); 8] = [(0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0), (0, 1), (-1, 1), (1, 1)];
+
+/// 手番の側の駒が来ると `king` に王手が掛かりうるマス。
+/// 玉から8方向に最初の駒のマスまでと、桂馬が王手を掛ける2マス。
+fn checking_squares(position:&Position, king:(i8, i8)) -> Vec<(i8, i8)> {
+    let mut squares = Vec::new();
+    for &(df, dr) in KING_DIRECTIONS.iter() {
+        let (mut f, mut r) = (king.0 + df, king.1 + dr);
+        while is_on_board(f, r) {
+            squares.push((f, r));
+            if !position.piece(f, r).is_space() { break; }
+            f += df;
+            r += dr;
+        }
+    }
+    let dr = if position.turn==Color::Sente { 2 } else { -2 };
+    for df in [-1, 1].iter() {
+        let to = (king.0 + df, king.1 + dr);
+        if is_on_board(to.0, to.1) { squares.push(to); }
+    }
+    squares
+}
+
+impl Position{
+    /// 王手放置も含めた指し手。二歩と行き所のない駒は除く。
+    pub fn pseudo_legal_moves(&self) -> Vec<UsiMovement> {
+        let mut moves = Vec::new();
+        let color = self.turn;
+
+        for file in FILE0+1..FILE10 {
+            for rank in RANK1..RANK10 {
+                let pc = self.piece(file, rank);
+                if pc.is_space() || pc.color()!=color { continue; }
+                push_piece_moves(self, &mut moves, (file, rank));
+            }
+        }
+
+        for &pt in HAND_PIECE_TYPES.iter() {
+            if self.hand_count(color, pt)==0 { continue; }
+            for file in FILE0+1..FILE10 {
+                for rank in RANK1..RANK10 {
+                    if can_drop(self, color, pt, file, rank) { push_drop(&mut moves, pt, (file, rank)); }
+                }
+            }
+        }
+        moves
+    }
+
+    /// `pseudo_legal_moves` の手が合法なら真。自玉に王手が残る手と、打ち歩詰めが反則。
+    pub fn is_legal_pseudo(&self, movement:&UsiMovement) -> bool {
+        let mut next = *self;
+        next.do_move(movement);
+        if next.in_check(self.turn) { return false; }
+        if movement.drop==PieceType::P && next.in_check(next.turn) && !next.has_legal_move() {
+            return false;
+        }
+        true
+    }
+
+    /// 合法手。
+    pub fn legal_moves(&self) -> Vec<UsiMovement> {
+        self.pseudo_legal_moves().into_iter().filter(|x| self.is_legal_pseudo(x)).collect()
+    }
+
+    /// 合法手が1つでもあれば真。
+    pub fn has_legal_move(&self) -> bool {
+        self.pseudo_legal_moves().iter().any(|x| self.is_legal_pseudo(x))
+    }
+
+    /// 指すと相手玉に王手が掛かるなら真。駒の利きだけで調べ、局面は進めない。
+    pub fn gives_check(&self, movement:&UsiMovement) -> bool {
+        let king = match self.king_square(self.turn.opponent()) {
+            Some(x) => x,
+            None => { return false; },
+        };
+        let to = (movement.destination_file, movement.destination_rank);
+        if movement.drop!=PieceType::Space {
+            return attacks_from(self, Piece::from(self.turn, movement.drop), to, king, (0, 0));
+        }
+        let from = (movement.source_file, movement.source_rank);
+        let mut pc = self.piece(from.0, from.1);
+        if movement.promotion { pc = Piece::from(self.turn, pc.piece_type().promote()); }
+        if attacks_from(self, pc, to, king, from) { return true; }
+
+        // 空き王手。玉から動かす駒を通った先に、玉へ走る自分の駒がいるか。
+        let (df, dr) = (from.0 - king.0, from.1 - king.1);
+        if !(df==0 || dr==0 || df.abs()==dr.abs()) { return false; }
+        let (sf, sr) = (df.signum(), dr.signum());
+        let (tf, tr) = (to.0 - king.0, to.1 - king.1);
+        if (tf==0 || tr==0 || tf.abs()==tr.abs()) && (tf.signum(), tr.signum())==(sf, sr) { return false; }
+        let (mut f, mut r) = (king.0 + sf, king.1 + sr);
+        while is_on_board(f, r) {
+            let pc = self.piece(f, r);
+            if (f, r)!=from && !pc.is_space() {
+                return pc.color()==self.turn && can_slide(pc, -sf, -sr);
+            }
+            f += sf;
+            r += sr;
+        }
+        false
+    }
+
+    /// 相手玉に王手を掛ける合法手。全部の指し手は作らず、相手玉の周りから候補を作る。
+    ///
+    /// * 直接の王手 - 玉に利きうるマスへ動く手と、そこへ打つ手。
+    /// * 空き王手 - 玉と自分の走り駒の間にいる、自分の駒の手。
+    pub fn check_moves(&self) -> Vec<UsiMovement> {
+        let color = self.turn;
+        let king = match self.king_square(color.opponent()) {
+            Some(x) => x,
+            None => { return Vec::new(); },
+        };
+        let mut candidates = Vec::new();
+        for to in checking_squares(self, king) {
+            let target = self.piece(to.0, to.1);
+            if !target.is_space() && target.color()==color { continue; }
+            for from in self.attackers(to.0, to.1, color) {
+                push_board_move(&mut candidates, color, self.piece(from.0, from.1).piece_type(), from, to);
+            }
+            for &pt in HAND_PIECE_TYPES.iter() {
+                if can_drop(self, color, pt, to.0, to.1) { push_drop(&mut candidates, pt, to); }
+            }
+        }
+        for &(df, dr) in KING_DIRECTIONS.iter() {
+            // 玉から見て最初の駒が自分の駒で、その先に玉へ走る自分の駒がいれば、最初の駒が動くと空き王手。
+            let mut pieces = Vec::new();
+            let (mut f, mut r) = (king.0 + df, king.1 + dr);
+            while is_on_board(f, r) && pieces.len()<2 {
+                if !self.piece(f, r).is_space() { pieces.push((f, r)); }
+                f += df;
+                r += dr;
+            }
+            if let [front, back] = pieces[..] {
+                let (pc, slider) = (self.piece(front.0, front.1), self.piece(back.0, back.1));
+                if pc.color()==color && slider.color()==color && can_slide(slider, -df, -dr) {
+                    push_piece_moves(self, &mut candidates, front);
+                }
+            }
+        }
+
+        let mut moves : Vec<UsiMovement> = Vec::new();
+        for movement in candidates {
+            if !moves.contains(&movement) && self.gives_check(&movement) && self.is_legal_pseudo(&movement) {
+                moves.push(movement);
+            }
+        }
+        moves
+    }
+
+    /// 王手を掛けられている側の合法手。玉が逃げる手と、王手している駒を取る手、合駒だけを作る。
+    pub fn evasions(&self) -> Vec<UsiMovement> {
+        let color = self.turn;
+        let king = match self.king_square(color) {
+            Some(x) => x,
+            None => { return Vec::new(); },
+        };
+        let mut moves = Vec::new();
+        for &(df, dr) in step_directions(PieceType::K).iter() {
+            let to = (king.0 + df, king.1 + dr);
+            if !is_on_board(to.0, to.1) { continue; }
+            let target = self.piece(to.0, to.1);
+            if target.is_space() || target.color()!=color {
+                push_board_move(&mut moves, color, PieceType::K, king, to);
+            }
+        }
+
+        let checkers = self.attackers(king.0, king.1, color.opponent());
+        if checkers.len()==1 {
+            let checker = checkers[0];
+            // 王手している駒のマスと、走り駒なら玉との間のマス。
+            let mut targets = vec![checker];
+            let (df, dr) = (checker.0 - king.0, checker.1 - king.1);
+            if (df==0 || dr==0 || df.abs()==dr.abs()) && (1<df.abs() || 1<dr.abs()) {
+                let (sf, sr) = (df.signum(), dr.signum());
+                let (mut f, mut r) = (king.0 + sf, king.1 + sr);
+                while (f, r)!=checker {
+                    targets.push((f, r));
+                    f += sf;
+                    r += sr;
+                }
+            }
+            for (i, &to) in targets.iter().enumerate() {
+                for from in self.attackers(to.0, to.1, color) {
+                    if from==king { continue; }
+                    push_board_move(&mut moves, color, self.piece(from.0, from.1).piece_type(), from, to);
+                }
+                if i==0 { continue; }
+                for &pt in HAND_PIECE_TYPES.iter() {
+                    if can_drop(self, color, pt, to.0, to.1) { push_drop(&mut moves, pt, to); }
+                }
+            }
+        }
+        moves.into_iter().filter(|x| self.is_legal_pseudo(x)).collect()
+    }
+
+    /// 手番の側が詰んでいれば真。
+    pub fn is_checkmated(&self) -> bool {
+        self.in_check(self.turn) && self.evasions().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use position::Position;
+    use UsiMovement;
+
+    /// 全部の指し手から王手だけを選んだもの。
+    fn brute_force_checks(position:&Position) -> Vec<UsiMovement> {
+        position.pseudo_legal_moves().into_iter().filter(|x| position.gives_check(x) && position.is_legal_pseudo(x)).collect()
+    }
+
+    fn assert_same_checks(position:&Position) {
+        let mut expected : Vec<String> = brute_force_checks(position).iter().map(|x| x.to_string()).collect();
+        let mut actual : Vec<String> = position.check_moves().iter().map(|x| x.to_string()).collect();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected, "sfen {}", position.to_sfen());
+    }
+
+    const SFENS : [&str; 7] = [
+        "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+        // 歩が動くと飛車の空き王手。
+        "4k4/9/4P4/9/4R4/9/9/9/4K4 b G2Pr 1",
+        // 桂が跳ぶと角の空き王手。
+        "8k/9/6N2/9/4B4/9/9/9/K8 b GSNL 1",
+        "k8/9/9/9/4b4/9/2n6/9/4K4 w rgs3p 1",
+        "7nl/7k1/6ppp/9/9/9/9/9/8K b RBG2S 1",
+        // 成って王手、不成では王手にならない手。
+        "4k4/9/3S1S3/2B6/9/9/9/9/4K4 b L 1",
+        "ln1g3nl/1r2k1g2/p1ppsp1pp/1s4p2/1p2P4/2P2PP2/PPSP3PP/2G1G2R1/LN1K3NL b Bb 1",
+    ];
+
+    #[test]
+    fn check_moves_match_brute_force() {
+        for sfen in SFENS.iter() {
+            assert_same_checks(&Position::from_sfen(sfen));
+        }
+    }
+
+    #[test]
+    fn check_moves_match_brute_force_along_games() {
+        for sfen in SFENS.iter() {
+            let mut position = Position::from_sfen(sfen);
+            for ply in 0..60 {
+                assert_same_checks(&position);
+                let checks = position.check_moves();
+                let moves = if ply % 3==0 && !checks.is_empty() { checks } else { position.legal_moves() };
+                if moves.is_empty() { break; }
+                position.do_move(&moves[(ply * 7 + 3) % moves.len()]);
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! 指し手の日本語表記☆（＾～＾） `▲７六歩`、`△同銀`、`▲２二角成`、`▲５五角打`。
+//!
+//! 動かした駒の名前を書くので、指す前の局面が要る。
+//! 同じ升へ動ける同じ駒が他にもあれば、日本将棋連盟の棋譜の表記方法にならって
+//! `右`、`左`、`直`（位置）と `上`、`引`、`寄`（動作）を付けて区別する。
+//! 打つ手に `打` を付けるのは、盤上の同じ駒もそこへ動けるときだけ。
+use position::Position;
+use record::{kif_piece_name, num_to_kanji, num_to_zenkaku};
+use {Color, PieceType, UsiMovement};
+
+/// 指す側の印。
+pub fn turn_mark(color:Color) -> &'static str {
+    match color {
+        Color::Sente => "▲",
+        Color::Gote => "△",
+    }
+}
+
+/// 指す側から見た (筋, 段)。右が小さく、前が小さい。
+fn relative(color:Color, file:i8, rank:i8) -> (i8, i8) {
+    match color {
+        Color::Sente => (file, rank),
+        Color::Gote => (10 - file, 10 - rank),
+    }
+}
+
+/// 移動元から移動先への動作。前へ出れば `上`、下がれば `引`、横なら `寄`。
+fn action(color:Color, source:(i8, i8), destination:(i8, i8)) -> char {
+    let from = relative(color, source.0, source.1).1;
+    let to = relative(color, destination.0, destination.1).1;
+    if to<from { '上' } else if from<to { '引' } else { '寄' }
+}
+
+/// 同じ升へ動ける同じ駒が他にもあるときの、区別の語。`sources` は他の駒の移動元。
+fn modifier(color:Color, pt:PieceType, source:(i8, i8), destination:(i8, i8), sources:&[(i8, i8)]) -> String {
+    if sources.is_empty() { return String::new(); }
+
+    // 動作だけで区別できればそれを書く。
+    let mine = action(color, source, destination);
+    let same_action : Vec<(i8, i8)> = sources.iter().cloned().filter(|x| action(color, *x, destination)==mine).collect();
+    if same_action.is_empty() { return mine.to_string(); }
+
+    // まっすぐ前へ出るなら `直`。竜と馬には使わない。
+    let long = matches!(pt, PieceType::R | PieceType::B | PieceType::PR | PieceType::PB);
+    if !long && mine=='上' && source.0==destination.0 { return "直".to_string(); }
+
+    // 位置。同じ動作の駒の中で一番右か一番左か。
+    let file = |x:(i8, i8)| relative(color, x.0, x.1).0;
+    let position = if same_action.iter().all(|x| file(source)<file(*x)) {
+        '右'
+    } else if same_action.iter().all(|x| file(*x)<file(source)) {
+        '左'
+    } else {
+        return String::new();
+    };
+    // 動作の違う駒まで含めて位置で区別できれば、動作は書かない。
+    let by_position = sources.iter().all(|x| if position=='右' { file(source)<file(*x) } else { file(*x)<file(source) });
+    if by_position { position.to_string() } else { format!("{}{}", position, mine) }
+}
+
+/// 日本語表記の1手。`position` は指す前の局面。
+///
+/// `previous` は1つ前の指し手。移動先が同じなら `同` と書く。
+pub fn japanese_move(position:&Position, movement:&UsiMovement, previous:Option<&UsiMovement>) -> String {
+    let destination = (movement.destination_file, movement.destination_rank);
+    let mut text = turn_mark(position.turn).to_string();
+    match previous {
+        Some(previous) if (previous.destination_file, previous.destination_rank)==destination => {
+            text.push('同');
+        },
+        _ => {
+            text.push_str(num_to_zenkaku(destination.0));
+            text.push_str(num_to_kanji(destination.1));
+        },
+    }
+
+    let moves = position.legal_moves();
+    // 同じ升へ動ける、同じ駒種類の盤上の駒。成と不成の2手は1つにまとめる。
+    let others = |pt:PieceType, source:(i8, i8)| {
+        let mut sources : Vec<(i8, i8)> = moves.iter()
+            .filter(|x| x.drop==PieceType::Space && (x.destination_file, x.destination_rank)==destination)
+            .map(|x| (x.source_file, x.source_rank))
+            .filter(|x| *x!=source && position.piece(x.0, x.1).piece_type()==pt)
+            .collect();
+        sources.sort_unstable();
+        sources.dedup();
+        sources
+    };
+
+    match movement.drop {
+        PieceType::Space => {
+            let source = (movement.source_file, movement.source_rank);
+            let pt = position.piece(source.0, source.1).piece_type();
+            text.push_str(kif_piece_name(pt));
+            text.push_str(&modifier(position.turn, pt, source, destination, &others(pt, source)));
+            if movement.promotion {
+                text.push('成');
+            } else if moves.iter().any(|x| x.promotion && (x.source_file, x.source_rank)==source && (x.destination_file, x.destination_rank)==destination) {
+                text.push_str("不成");
+            }
+        },
+        pt => {
+            text.push_str(kif_piece_name(pt));
+            if !others(pt, (0, 0)).is_empty() { text.push('打'); }
+        },
+    }
+    text
+}
+
+/// 読み筋を日本語表記でつなげる。`▲７六歩△３四歩▲２六歩`。
+///
+/// `previous` は `position` に至った指し手。1手目の `同` に使う。
+pub fn japanese_moves(position:&Position, moves:&[UsiMovement], previous:Option<&UsiMovement>) -> String {
+    let mut position = *position;
+    let mut previous = previous.cloned();
+    let mut text = String::new();
+    for movement in moves.iter() {
+        text.push_str(&japanese_move(&position, movement, previous.as_ref()));
+        position.do_move(movement);
+        previous = Some(*movement);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine_output::parse_movement_token;
+
+    /// sfen の局面で、USI の1手を日本語にする。
+    fn japanese(sfen:&str, movement:&str) -> String {
+        japanese_move(&Position::from_sfen(sfen), &parse_movement_token(movement).unwrap(), None)
+    }
+
+    #[test]
+    fn same_square_and_promotion() {
+        let moves : Vec<UsiMovement> = ["7g7f", "3c3d", "8h2b+", "3a2b"].iter().map(|x| parse_movement_token(x).unwrap()).collect();
+        assert_eq!(japanese_moves(&Position::startpos(), &moves, None), "▲７六歩△３四歩▲２二角成△同銀");
+        // 1手目の `同` は、局面に至った手で決まる。
+        let mut position = Position::startpos();
+        position.do_move(&moves[0]);
+        assert_eq!(japanese_move(&position, &parse_movement_token("7c7d").unwrap(), None), "△７四歩");
+        assert_eq!(japanese_move(&position, &parse_movement_token("7c7d").unwrap(), Some(&parse_movement_token("7e7d").unwrap())), "△同歩");
+    }
+
+    #[test]
+    fn drop_only_when_a_board_piece_could_move_there() {
+        // ８八の角も５五へ行ける。
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/1B7/4K4 b B 1", "B*5e"), "▲５五角打");
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/1B7/4K4 b B 1", "8h5e"), "▲５五角");
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/9/4K4 b B 1", "B*5e"), "▲５五角");
+    }
+
+    #[test]
+    fn gold_pairs() {
+        // ６九と４九の金が５八へ。
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/9/3G1G2K b - 1", "4i5h"), "▲５八金右");
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/9/3G1G2K b - 1", "6i5h"), "▲５八金左");
+        // ５九と４九の金が５八へ。まっすぐ上がるのは `直`。
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/9/4GG2K b - 1", "5i5h"), "▲５八金直");
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/9/4GG2K b - 1", "4i5h"), "▲５八金右");
+        // ６八と４九の金が５八へ。動作で区別できる。
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/3G5/5G2K b - 1", "6h5h"), "▲５八金寄");
+        assert_eq!(japanese("4k4/9/9/9/9/9/9/3G5/5G2K b - 1", "4i5h"), "▲５八金上");
+        // 後手の左右は後手から見る。
+        assert_eq!(japanese("k2g1g3/9/9/9/9/9/9/9/4K4 w - 1", "4a5b"), "△５二金左");
+        assert_eq!(japanese("k2g1g3/9/9/9/9/9/9/9/4K4 w - 1", "6a5b"), "△５二金右");
+    }
+
+    #[test]
+    fn dragon_pairs() {
+        // ９一と１一の竜が５一へ。
+        assert_eq!(japanese("+R7+R/9/9/9/4k4/9/9/9/4K4 b - 1", "1a5a"), "▲５一龍右");
+        assert_eq!(japanese("+R7+R/9/9/9/4k4/9/9/9/4K4 b - 1", "9a5a"), "▲５一龍左");
+        // ５四と４三の竜が５二へ。竜には `直` を使わない。
+        assert_eq!(japanese("9/9/5+R3/4+R4/8k/9/9/9/K8 b - 1", "5d5b"), "▲５二龍左");
+        assert_eq!(japanese("9/9/5+R3/4+R4/8k/9/9/9/K8 b - 1", "4c5b"), "▲５二龍右");
+    }
+}
@@ -0,0 +1,108 @@
+//! ほかのエンジンの駒の番号☆（＾～＾）
+//!
+//! やねうら王と Apery は、駒種類を次の番号で数え、後手の駒はこれに 16 を足す。空マスは 0。
+//! 定跡や教師局面や `Move32` を読み書きするときに使う。
+//!
+//! | 歩 | 香 | 桂 | 銀 | 角 | 飛 | 金 | 玉 | と | 杏 | 圭 | 全 | 馬 | 竜 |
+//! |----|----|----|----|----|----|----|----|----|----|----|----|----|----|
+//! |  1 |  2 |  3 |  4 |  5 |  6 |  7 |  8 |  9 | 10 | 11 | 12 | 13 | 14 |
+use {Color, Piece, PieceType};
+
+/// 後手の駒に足す数。
+pub const WHITE_OFFSET : u8 = 16;
+
+/// 番号から駒種類。15 は使わない。
+pub const CODE_TO_PIECE_TYPE : [PieceType; 16] = [
+    PieceType::Space,
+    PieceType::P,
+    PieceType::L,
+    PieceType::N,
+    PieceType::S,
+    PieceType::B,
+    PieceType::R,
+    PieceType::G,
+    PieceType::K,
+    PieceType::PP,
+    PieceType::PL,
+    PieceType::PN,
+    PieceType::PS,
+    PieceType::PB,
+    PieceType::PR,
+    PieceType::Num,
+];
+
+/// `PieceType as usize` から番号。並びは `PieceType` の K、R、B、G、S、N、L、P、PR、PB、PS、PN、PL、PP、Space。
+pub const PIECE_TYPE_TO_CODE : [u8; 15] = [8, 6, 5, 7, 4, 3, 2, 1, 14, 13, 12, 11, 10, 9, 0];
+
+/// 駒種類の番号。空マスは 0。
+pub fn piece_type_to_code(pt:PieceType) -> u8 {
+    PIECE_TYPE_TO_CODE.get(pt as usize).cloned().unwrap_or(0)
+}
+
+/// 番号から駒種類。番号が無ければ None。
+pub fn code_to_piece_type(code:u8) -> Option<PieceType> {
+    match CODE_TO_PIECE_TYPE.get(code as usize) {
+        Some(&PieceType::Num) | None => None,
+        Some(&pt) => Some(pt),
+    }
+}
+
+/// 先後付きの駒の番号。空マスは 0。
+pub fn piece_to_code(pc:Piece) -> u8 {
+    let pt = pc.piece_type();
+    if pt==PieceType::Space { return 0; }
+    piece_type_to_code(pt) + WHITE_OFFSET * pc.color().index() as u8
+}
+
+/// 番号から先後付きの駒。0 は空マス。番号が無ければ None。
+pub fn code_to_piece(code:u8) -> Option<Piece> {
+    if code==0 { return Some(Piece::Space); }
+    if 2 * WHITE_OFFSET<=code { return None; }
+    let pt = code_to_piece_type(code % WHITE_OFFSET)?;
+    if pt==PieceType::Space { return None; }
+    Some(Piece::from(Color::from_index((code / WHITE_OFFSET) as usize)?, pt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_mutual_inverses() {
+        for pt in PieceType::all() {
+            let code = PIECE_TYPE_TO_CODE[pt as usize];
+            assert!(0<code && code<WHITE_OFFSET - 1, "{:?}", pt);
+            assert_eq!(CODE_TO_PIECE_TYPE[code as usize], pt);
+            assert_eq!(code_to_piece_type(piece_type_to_code(pt)), Some(pt));
+        }
+        for code in 1..WHITE_OFFSET - 1 {
+            let pt = CODE_TO_PIECE_TYPE[code as usize];
+            assert_eq!(PIECE_TYPE_TO_CODE[pt as usize], code);
+        }
+        assert_eq!(piece_type_to_code(PieceType::Space), 0);
+        assert_eq!(code_to_piece_type(0), Some(PieceType::Space));
+        assert_eq!(code_to_piece_type(15), None);
+        assert_eq!(code_to_piece_type(16), None);
+    }
+
+    #[test]
+    fn pieces_round_trip_for_both_colors() {
+        for pc in Piece::all() {
+            let code = piece_to_code(pc);
+            assert_eq!(code_to_piece(code), Some(pc), "{:?}", pc);
+            assert_eq!(code / WHITE_OFFSET, pc.color().index() as u8);
+            assert_eq!(code % WHITE_OFFSET, piece_type_to_code(pc.piece_type()));
+        }
+        assert_eq!(piece_to_code(Piece::P0), 1);
+        assert_eq!(piece_to_code(Piece::PR1), 30);
+        assert_eq!(piece_to_code(Piece::Space), 0);
+        assert_eq!(code_to_piece(0), Some(Piece::Space));
+    }
+
+    #[test]
+    fn unused_codes() {
+        for &code in [15, 16, 31, 32, 255].iter() {
+            assert_eq!(code_to_piece(code), None, "{}", code);
+        }
+    }
+}
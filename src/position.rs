@@ -0,0 +1,315 @@
+//! 局面☆（＾～＾） 盤上と持ち駒と手番を1つにまとめたもの。
+use std::fmt;
+
+use hand::{HAND_PIECE_TYPES, Hand, hands_to_sfen};
+use {FILE0, FILE10, RANK0, RANK1, RANK10, STARTPOS};
+use HAND_PIECE_ARRAY_LN;
+use {Color, Piece, PieceType, UsiMovement};
+use {file_rank_to_cell, parse_board, parse_hand_piece, parse_movement, starts_with_and_forward};
+
+/// 先手から見た、1マスだけ動ける向き。(筋の増分, 段の増分)。段が減る向きが前。
+pub fn step_directions(pt:PieceType) -> &'static [(i8, i8)] {
+    use PieceType::*;
+    match pt {
+        K => &[(0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0), (0, 1), (-1, 1), (1, 1)],
+        G | PS | PN | PL | PP => &[(0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0), (0, 1)],
+        S => &[(0, -1), (-1, -1), (1, -1), (-1, 1), (1, 1)],
+        N => &[(-1, -2), (1, -2)],
+        P => &[(0, -1)],
+        PR => &[(-1, -1), (1, -1), (-1, 1), (1, 1)],
+        PB => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+        _ => &[],
+    }
+}
+/// 先手から見た、何マスでも走れる向き。
+pub fn slide_directions(pt:PieceType) -> &'static [(i8, i8)] {
+    use PieceType::*;
+    match pt {
+        R | PR => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+        B | PB => &[(-1, -1), (1, -1), (-1, 1), (1, 1)],
+        L => &[(0, -1)],
+        _ => &[],
+    }
+}
+/// 駒がその向きへ1マス動けるなら真。後手の駒は向きを180度回して調べる。
+pub fn can_step(pc:Piece, df:i8, dr:i8) -> bool {
+    let (df, dr) = if pc.color()==Color::Sente { (df, dr) } else { (-df, -dr) };
+    step_directions(pc.piece_type()).contains(&(df, dr)) || slide_directions(pc.piece_type()).contains(&(df, dr))
+}
+/// 駒がその向きへ何マスでも走れるなら真。
+pub fn can_slide(pc:Piece, df:i8, dr:i8) -> bool {
+    let (df, dr) = if pc.color()==Color::Sente { (df, dr) } else { (-df, -dr) };
+    slide_directions(pc.piece_type()).contains(&(df, dr))
+}
+/// 盤の中なら真。
+pub fn is_on_board(file:i8, rank:i8) -> bool {
+    FILE0<file && file<FILE10 && RANK0<rank && rank<RANK10
+}
+
+/// Zobrist ハッシュの乱数。splitmix64 で固定の種から作る。
+const fn splitmix64(x:u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+/// 盤上の駒の乱数。[マス][駒]。
+static ZOBRIST_BOARD : [[u64; 28]; 100] = {
+    let mut table = [[0; 28]; 100];
+    let mut cell = 0;
+    while cell<100 {
+        let mut pc = 0;
+        while pc<28 {
+            table[cell][pc] = splitmix64((cell * 28 + pc) as u64);
+            pc += 1;
+        }
+        cell += 1;
+    }
+    table
+};
+/// 持ち駒1枚あたりの乱数。先手、後手の順に `HAND_PIECE_TYPES` の並び。枚数を掛けて足す。
+const ZOBRIST_HAND : [u64; HAND_PIECE_ARRAY_LN] = {
+    let mut table = [0; HAND_PIECE_ARRAY_LN];
+    let mut i = 0;
+    while i<HAND_PIECE_ARRAY_LN {
+        table[i] = splitmix64((2800 + i) as u64);
+        i += 1;
+    }
+    table
+};
+/// 後手番の乱数。
+const ZOBRIST_TURN : u64 = splitmix64(2900);
+
+/// 局面。
+///
+/// # Members.
+///
+/// * `board` - 盤上。`file_rank_to_cell` で引く。
+/// * `hand` - 持ち駒。先手、後手の順。`Color::index` で引く。
+/// * `turn` - 手番。
+/// * `ply` - 次に指すのが何手目か。sfen の最後の数字。
+#[derive(Copy,Clone)]
+pub struct Position{
+    pub board : [Piece;100],
+    pub hand : [Hand; 2],
+    pub turn : Color,
+    pub ply : u32,
+}
+impl Position{
+    /// 平手初期局面。
+    pub fn startpos() -> Position {
+        let mut starts = 0;
+        Position{
+            board : parse_board(STARTPOS, &mut starts, STARTPOS.len()),
+            hand : [Hand::EMPTY; 2],
+            turn : Color::Sente,
+            ply : 1,
+        }
+    }
+
+    /// `<盤> <手番> <持ち駒> <手数>` の sfen を読み取る。手数は省略可。
+    pub fn from_sfen(sfen:&str) -> Position {
+        let mut starts = 0;
+        Position::parse_sfen(sfen, &mut starts, sfen.len())
+    }
+
+    fn parse_sfen(line:&str, starts:&mut usize, len:usize) -> Position {
+        let board = parse_board(line, starts, len);
+        starts_with_and_forward(line, starts, " ");
+        let turn = if starts_with_and_forward(line, starts, "w") {
+            Color::Gote
+        } else {
+            starts_with_and_forward(line, starts, "b");
+            Color::Sente
+        };
+        starts_with_and_forward(line, starts, " ");
+        let hand = parse_hand_piece(line, starts, len);
+        starts_with_and_forward(line, starts, " ");
+        let digits = line[*starts..].bytes().take_while(|x| x.is_ascii_digit()).count();
+        let ply = line[*starts..(*starts+digits)].parse().unwrap_or(1);
+        *starts += digits;
+        Position{ board, hand, turn, ply }
+    }
+
+    /// 盤上の駒。
+    pub fn piece(&self, file:i8, rank:i8) -> Piece {
+        self.board[file_rank_to_cell(file, rank)]
+    }
+
+    /// 持ち駒の枚数。
+    pub fn hand_count(&self, color:Color, pt:PieceType) -> i8 {
+        self.hand[color.index()].count(pt)
+    }
+
+    /// 玉のいるマス。(筋, 段)。いなければ None。
+    pub fn king_square(&self, color:Color) -> Option<(i8, i8)> {
+        let king = Piece::from(color, PieceType::K);
+        for file in FILE0+1..FILE10 {
+            for rank in RANK1..RANK10 {
+                if self.piece(file, rank)==king { return Some((file, rank)); }
+            }
+        }
+        None
+    }
+
+    /// マスに `by` 側の駒が利いていれば真。
+    pub fn is_attacked(&self, file:i8, rank:i8, by:Color) -> bool {
+        !self.attackers(file, rank, by).is_empty()
+    }
+
+    /// マスに利いている `by` 側の駒のマス。(筋, 段)。
+    pub fn attackers(&self, file:i8, rank:i8, by:Color) -> Vec<(i8, i8)> {
+        const DIRECTIONS : [(i8, i8); 8] = [(0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0), (0, 1), (-1, 1), (1, 1)];
+        let mut found = Vec::new();
+        for &(df, dr) in DIRECTIONS.iter() {
+            // 隣のマスから1歩で来る駒と、その先から走って来る駒。
+            let (mut f, mut r) = (file - df, rank - dr);
+            let mut adjacent = true;
+            while is_on_board(f, r) {
+                let pc = self.piece(f, r);
+                if !pc.is_space() {
+                    if pc.color()==by && (if adjacent { can_step(pc, df, dr) } else { can_slide(pc, df, dr) }) {
+                        found.push((f, r));
+                    }
+                    break;
+                }
+                adjacent = false;
+                f -= df;
+                r -= dr;
+            }
+        }
+        // 桂馬は飛び越えて来る。
+        let knight = Piece::from(by, PieceType::N);
+        let dr = if by==Color::Sente { -2 } else { 2 };
+        for df in [-1, 1].iter() {
+            let (f, r) = (file - df, rank - dr);
+            if is_on_board(f, r) && self.piece(f, r)==knight { found.push((f, r)); }
+        }
+        found
+    }
+
+    /// `color` 側の玉に王手が掛かっていれば真。玉がいなければ偽。
+    pub fn in_check(&self, color:Color) -> bool {
+        match self.king_square(color) {
+            Some((file, rank)) => self.is_attacked(file, rank, color.opponent()),
+            None => false,
+        }
+    }
+
+    /// 盤上と手番だけのハッシュ。持ち駒の優劣を比べたいときに使う。
+    pub fn board_key(&self) -> u64 {
+        let mut key = if self.turn==Color::Sente { 0 } else { ZOBRIST_TURN };
+        for (cell, pc) in self.board.iter().enumerate() {
+            if !pc.is_space() { key ^= ZOBRIST_BOARD[cell][*pc as usize]; }
+        }
+        key
+    }
+
+    /// 盤上、持ち駒、手番のハッシュ。手数は含めない。
+    pub fn key(&self) -> u64 {
+        let mut key = self.board_key();
+        for color in Color::all() {
+            for (i, &pt) in HAND_PIECE_TYPES.iter().enumerate() {
+                let count = self.hand[color.index()].count(pt);
+                key = key.wrapping_add(ZOBRIST_HAND[color.index() * HAND_PIECE_TYPES.len() + i].wrapping_mul(count as u64));
+            }
+        }
+        key
+    }
+
+    /// 指し手で局面を進める。取った駒を返す。取らなければ空マス。
+    ///
+    /// 合法手かどうかは調べない。
+    pub fn do_move(&mut self, movement:&UsiMovement) -> Piece {
+        let destination = file_rank_to_cell(movement.destination_file, movement.destination_rank);
+        let captured = self.board[destination];
+        match movement.drop {
+            PieceType::Space => {
+                let source = file_rank_to_cell(movement.source_file, movement.source_rank);
+                let mut pc = self.board[source];
+                if movement.promotion {
+                    pc = Piece::from(pc.color(), pc.piece_type().promote());
+                }
+                if !captured.is_space() {
+                    self.hand[self.turn.index()].add(captured.piece_type());
+                }
+                self.board[source] = Piece::Space;
+                self.board[destination] = pc;
+            },
+            pt => {
+                self.hand[self.turn.index()].remove(pt);
+                self.board[destination] = Piece::from(self.turn, pt);
+            },
+        }
+        self.turn = self.turn.opponent();
+        self.ply += 1;
+        captured
+    }
+
+    /// 盤上部分だけの sfen。
+    pub fn board_to_sfen(&self) -> String {
+        let mut text = String::new();
+        for rank in RANK1..RANK10 {
+            if RANK1<rank { text.push('/'); }
+            let mut spaces = 0;
+            for file in (FILE0+1..FILE10).rev() {
+                let pc = self.piece(file, rank);
+                if pc.is_space() {
+                    spaces += 1;
+                } else {
+                    if 0<spaces { text.push_str(&spaces.to_string()); spaces = 0; }
+                    text.push_str(pc.to_sfen());
+                }
+            }
+            if 0<spaces { text.push_str(&spaces.to_string()); }
+        }
+        text
+    }
+
+    /// 持ち駒部分だけの sfen。なければ `-`。
+    pub fn hand_to_sfen(&self) -> String {
+        hands_to_sfen(&self.hand)
+    }
+
+    /// `<盤> <手番> <持ち駒> <手数>` の sfen。
+    pub fn to_sfen(&self) -> String {
+        format!("{} {} {} {}",
+            self.board_to_sfen(),
+            self.turn.to_usi(),
+            self.hand_to_sfen(),
+            self.ply)
+    }
+}
+impl fmt::Display for Position{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_sfen())
+    }
+}
+
+/// position コマンドの `position` より後ろを字句解析。開始局面と、続く指し手を返す。
+///
+/// # Examples input.
+///
+/// * `startpos`
+/// * `startpos moves 7g7f 3c3d`
+/// * `sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1 moves 7g7f`
+pub fn parse_position(line:&str, starts:&mut usize, len:usize) -> (Position, Vec<UsiMovement>) {
+    let position = if starts_with_and_forward(line, starts, "startpos") {
+        Position::startpos()
+    } else {
+        starts_with_and_forward(line, starts, "sfen ");
+        Position::parse_sfen(line, starts, len)
+    };
+
+    let mut moves = Vec::new();
+    starts_with_and_forward(line, starts, " ");
+    if starts_with_and_forward(line, starts, "moves") {
+        starts_with_and_forward(line, starts, " ");
+        loop {
+            let (successful, movement) = parse_movement(line, starts, len);
+            if !successful { break; }
+            moves.push(movement);
+        }
+    }
+    (position, moves)
+}
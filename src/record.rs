@@ -0,0 +1,478 @@
+//! 棋譜ファイル☆（＾～＾） KIF と CSA の書き出しと、KIF と CSA の指し手の読み取り。
+use std::io;
+use std::io::Write;
+
+use diagram::{parse_bod, to_bod};
+use hand::HAND_PIECE_TYPES;
+use handicap::Handicap;
+use movegen::is_promotion_zone;
+use position::Position;
+use square::Square;
+use {FILE0, FILE10, RANK1, RANK10};
+use {Color, PieceType, UsiMovement};
+
+/// 対局の終わり方。最後に手番だった側から見て。
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum GameEnding{
+    /// 投了した。
+    Resign,
+    /// 入玉宣言した。宣言した側の勝ち。
+    Declaration,
+    /// 時間切れ。
+    TimeUp,
+    /// 応答しない、おかしな行を返したなどの反則。
+    IllegalAction,
+    /// 最大手数に達して引き分け。
+    MaxPly,
+    /// 千日手。引き分け。
+    Repetition,
+    /// 連続王手の千日手。王手を掛け続けた `checker` の負け。
+    PerpetualCheck{ checker : Color },
+}
+impl GameEnding{
+    /// 勝った側。引き分けなら None。`turn` は最後に手番だった側。
+    pub fn winner(self, turn:Color) -> Option<Color> {
+        match self {
+            GameEnding::Declaration => Some(turn),
+            GameEnding::MaxPly | GameEnding::Repetition => None,
+            GameEnding::PerpetualCheck{ checker } => Some(checker.opponent()),
+            _ => Some(turn.opponent()),
+        }
+    }
+}
+
+/// 1手分の記録。
+///
+/// # Members.
+///
+/// * `movement` - 指し手。
+/// * `millis` - 消費時間（ミリ秒）。
+#[derive(Copy,Clone,Debug)]
+pub struct RecordedMove{
+    pub movement : UsiMovement,
+    pub millis : u64,
+}
+
+/// 対局1局分の棋譜。
+///
+/// # Members.
+///
+/// * `names` - 先手、後手の対局者名。
+/// * `start` - 開始局面。
+/// * `moves` - 指し手。
+/// * `ending` - 終わり方。
+pub struct GameRecord{
+    pub names : [String;2],
+    pub start : Position,
+    pub moves : Vec<RecordedMove>,
+    pub ending : GameEnding,
+}
+
+/// CSA の駒名。
+pub fn csa_piece_name(pt:PieceType) -> &'static str {
+    use PieceType::*;
+    match pt {
+        K => "OU",
+        R => "HI",
+        B => "KA",
+        G => "KI",
+        S => "GI",
+        N => "KE",
+        L => "KY",
+        P => "FU",
+        PR => "RY",
+        PB => "UM",
+        PS => "NG",
+        PN => "NK",
+        PL => "NY",
+        PP => "TO",
+        Space | Num => " * ",
+    }
+}
+
+/// `csa_piece_name` の逆。
+fn parse_csa_piece_name(text:&str) -> Option<PieceType> {
+    PieceType::all().find(|x| csa_piece_name(*x)==text)
+}
+
+/// KIF の駒名。
+pub fn kif_piece_name(pt:PieceType) -> &'static str {
+    use PieceType::*;
+    match pt {
+        K => "玉",
+        R => "飛",
+        B => "角",
+        G => "金",
+        S => "銀",
+        N => "桂",
+        L => "香",
+        P => "歩",
+        PR => "龍",
+        PB => "馬",
+        PS => "成銀",
+        PN => "成桂",
+        PL => "成香",
+        PP => "と",
+        Space | Num => "",
+    }
+}
+
+/// 1～9 を全角数字に。
+pub fn num_to_zenkaku(num:i8) -> &'static str {
+    ["", "１", "２", "３", "４", "５", "６", "７", "８", "９"][num as usize]
+}
+/// 1～9 を漢数字に。
+pub fn num_to_kanji(num:i8) -> &'static str {
+    ["", "一", "二", "三", "四", "五", "六", "七", "八", "九"][num as usize]
+}
+
+/// CSA の1手。`+7776FU` のように、動かした後の駒名を付ける。`position` は指す前の局面。
+pub fn csa_move(position:&Position, movement:&UsiMovement) -> String {
+    let (source_file, source_rank, pt) = match movement.drop {
+        PieceType::Space => {
+            let pc = position.piece(movement.source_file, movement.source_rank);
+            let pt = if movement.promotion { pc.piece_type().promote() } else { pc.piece_type() };
+            (movement.source_file, movement.source_rank, pt)
+        },
+        pt => (0, 0, pt),
+    };
+    format!("{}{}{}{}{}{}",
+        position.turn.to_csa(),
+        source_file, source_rank,
+        movement.destination_file, movement.destination_rank,
+        csa_piece_name(pt))
+}
+
+/// CSA の1手を読み取る。`+7776FU` のように、手番の印と動かした後の駒名が要る。`position` は指す前の局面。
+///
+/// 手番が違う、移動元に自分の駒がいない、駒名が移動元の駒とも成った駒とも合わないなら None。
+/// 合法手かどうかは調べない。
+pub fn parse_csa_move(position:&Position, text:&str) -> Option<UsiMovement> {
+    let mut chars = text.chars();
+    if Color::from_csa(chars.next()?)?!=position.turn { return None; }
+    let rest = chars.as_str();
+    if rest.len()!=6 || !rest.is_ascii() { return None; }
+    let destination = Square::from_csa(&rest[2..4])?;
+    let pt = parse_csa_piece_name(&rest[4..6])?;
+    let mut movement = UsiMovement{
+        destination_file : destination.file(),
+        destination_rank : destination.rank(),
+        ..UsiMovement::default()
+    };
+    if &rest[0..2]=="00" {
+        if !HAND_PIECE_TYPES.contains(&pt) { return None; }
+        movement.drop = pt;
+        return Some(movement);
+    }
+    let source = Square::from_csa(&rest[0..2])?;
+    let pc = position.piece(source.file(), source.rank());
+    if pc.is_space() || pc.color()!=position.turn { return None; }
+    movement.source_file = source.file();
+    movement.source_rank = source.rank();
+    if pc.piece_type()!=pt {
+        if !pc.piece_type().can_promote() || pc.piece_type().promote()!=pt { return None; }
+        movement.promotion = true;
+    }
+    Some(movement)
+}
+
+/// 成れる位置の移動なら真。
+fn can_promote_move(position:&Position, movement:&UsiMovement) -> bool {
+    let pt = position.piece(movement.source_file, movement.source_rank).piece_type();
+    pt.can_promote() && (is_promotion_zone(position.turn, movement.source_rank) || is_promotion_zone(position.turn, movement.destination_rank))
+}
+
+/// KIF の1手。`７六歩(77)`、`同　歩(33)`、`５五角打`、`２二角成(88)`。
+///
+/// `previous` は1つ前の指し手。移動先が同じなら `同　` と書く。`position` は指す前の局面。
+pub fn kif_move(position:&Position, movement:&UsiMovement, previous:Option<&UsiMovement>) -> String {
+    let same = match previous {
+        Some(previous) => previous.destination_file==movement.destination_file && previous.destination_rank==movement.destination_rank,
+        None => false,
+    };
+    let mut text = if same {
+        "同　".to_string()
+    } else {
+        format!("{}{}", num_to_zenkaku(movement.destination_file), num_to_kanji(movement.destination_rank))
+    };
+    match movement.drop {
+        PieceType::Space => {
+            let pt = position.piece(movement.source_file, movement.source_rank).piece_type();
+            text.push_str(kif_piece_name(pt));
+            if movement.promotion {
+                text.push('成');
+            } else if can_promote_move(position, movement) {
+                text.push_str("不成");
+            }
+            text.push_str(&format!("({}{})", movement.source_file, movement.source_rank));
+        },
+        pt => {
+            text.push_str(kif_piece_name(pt));
+            text.push('打');
+        },
+    }
+    text
+}
+
+/// CSA の開始局面。平手や駒落ちなら `PI` 行、そうでなければ `P1`～`P9` と持ち駒。最後に手番。
+pub fn csa_position(position:&Position) -> String {
+    if let Some(handicap) = Handicap::detect(position) {
+        return format!("{}\n{}\n", handicap.csa(), position.turn.to_csa());
+    }
+    let mut text = String::new();
+    for rank in RANK1..RANK10 {
+        text.push_str(&format!("P{}", rank));
+        for file in (FILE0+1..FILE10).rev() {
+            let pc = position.piece(file, rank);
+            if pc.is_space() {
+                text.push_str(" * ");
+            } else {
+                text.push(pc.color().to_csa());
+                text.push_str(csa_piece_name(pc.piece_type()));
+            }
+        }
+        text.push('\n');
+    }
+    for color in Color::all() {
+        let mut line = String::new();
+        for (pt, count) in position.hand[color.index()].iter() {
+            for _ in 0..count {
+                line.push_str("00");
+                line.push_str(csa_piece_name(pt));
+            }
+        }
+        if !line.is_empty() {
+            text.push_str(&format!("P{}{}\n", color.to_csa(), line));
+        }
+    }
+    text.push(position.turn.to_csa());
+    text.push('\n');
+    text
+}
+
+/// CSA 形式で書き出す。
+pub fn write_csa<W:Write>(w:&mut W, record:&GameRecord) -> io::Result<()> {
+    writeln!(w, "V2.2")?;
+    writeln!(w, "N+{}", record.names[0])?;
+    writeln!(w, "N-{}", record.names[1])?;
+    write!(w, "{}", csa_position(&record.start))?;
+    let mut position = record.start;
+    for recorded in &record.moves {
+        writeln!(w, "{}", csa_move(&position, &recorded.movement))?;
+        writeln!(w, "T{}", recorded.millis / 1000)?;
+        position.do_move(&recorded.movement);
+    }
+    writeln!(w, "{}", match record.ending {
+        GameEnding::Resign => "%TORYO",
+        GameEnding::Declaration => "%KACHI",
+        GameEnding::TimeUp => "%TIME_UP",
+        GameEnding::IllegalAction => if position.turn==Color::Sente {"%+ILLEGAL_ACTION"} else {"%-ILLEGAL_ACTION"},
+        GameEnding::MaxPly => "%HIKIWAKE",
+        GameEnding::Repetition => "%SENNICHITE",
+        GameEnding::PerpetualCheck{ checker } => if checker==Color::Sente {"%+ILLEGAL_ACTION"} else {"%-ILLEGAL_ACTION"},
+    })
+}
+
+/// KIF 形式で書き出す。駒落ちなら先手を下手、後手を上手と書く。
+///
+/// 手合割のどれにも当てはまらない開始局面は、手合割の代わりに局面図（BOD）を書く。
+pub fn write_kif<W:Write>(w:&mut W, record:&GameRecord) -> io::Result<()> {
+    let handicap = match Handicap::detect(&record.start) {
+        Some(handicap) if record.start.turn==handicap.position().turn => Some(handicap),
+        _ => None,
+    };
+    let side = |color:Color| match (handicap, color) {
+        (Some(Handicap::Hirate), Color::Sente) | (None, Color::Sente) => "先手",
+        (Some(Handicap::Hirate), Color::Gote) | (None, Color::Gote) => "後手",
+        (_, Color::Sente) => "下手",
+        (_, Color::Gote) => "上手",
+    };
+
+    match handicap {
+        Some(handicap) => { writeln!(w, "手合割：{}", handicap.kif_name())?; },
+        None => { write!(w, "{}", to_bod(&record.start))?; },
+    }
+    writeln!(w, "{}：{}", side(Color::Sente), record.names[0])?;
+    writeln!(w, "{}：{}", side(Color::Gote), record.names[1])?;
+    writeln!(w, "手数----指手---------消費時間--")?;
+    let mut position = record.start;
+    let mut previous : Option<UsiMovement> = None;
+    let mut total = [0u64;2];
+    for (i, recorded) in record.moves.iter().enumerate() {
+        total[position.turn.index()] += recorded.millis / 1000;
+        let seconds = recorded.millis / 1000;
+        let sum = total[position.turn.index()];
+        writeln!(w, "{:>4} {}   ({:>2}:{:02}/{:02}:{:02}:{:02})",
+            i + 1,
+            kif_move(&position, &recorded.movement, previous.as_ref()),
+            seconds / 60, seconds % 60,
+            sum / 3600, sum / 60 % 60, sum % 60)?;
+        position.do_move(&recorded.movement);
+        previous = Some(recorded.movement);
+    }
+
+    let plies = record.moves.len();
+    writeln!(w, "{:>4} {}", plies + 1, match record.ending {
+        GameEnding::Resign => "投了",
+        GameEnding::Declaration => "入玉勝ち",
+        GameEnding::TimeUp => "切れ負け",
+        GameEnding::IllegalAction => "反則負け",
+        GameEnding::MaxPly => "持将棋",
+        GameEnding::Repetition => "千日手",
+        GameEnding::PerpetualCheck{ .. } => "反則負け",
+    })?;
+    match record.ending.winner(position.turn) {
+        Some(winner) => match record.ending {
+            GameEnding::TimeUp => writeln!(w, "まで{}手で時間切れにより{}の勝ち", plies, side(winner)),
+            GameEnding::Declaration => writeln!(w, "まで{}手で入玉宣言により{}の勝ち", plies, side(winner)),
+            GameEnding::IllegalAction => writeln!(w, "まで{}手で{}の反則勝ち", plies, side(winner)),
+            GameEnding::PerpetualCheck{ .. } => writeln!(w, "まで{}手で連続王手の千日手により{}の勝ち", plies, side(winner)),
+            _ => writeln!(w, "まで{}手で{}の勝ち", plies, side(winner)),
+        },
+        None if record.ending==GameEnding::Repetition => writeln!(w, "まで{}手で千日手", plies),
+        None => writeln!(w, "まで{}手で持将棋", plies),
+    }
+}
+
+/// KIF の1手を読み取る。`７六歩(77)` のように移動元が括弧で書かれている必要がある。
+///
+/// `previous` は1つ前の指し手。`同` のときに移動先を借りる。投了などの終わりの語なら None。
+pub fn parse_kif_move(text:&str, previous:Option<&UsiMovement>) -> Option<UsiMovement> {
+    let mut movement = UsiMovement::default();
+    let text = text.trim();
+
+    if text.starts_with('同') {
+        let previous = previous?;
+        movement.destination_file = previous.destination_file;
+        movement.destination_rank = previous.destination_rank;
+        let rest = text['同'.len_utf8()..].trim_start_matches([' ', '　']);
+        return parse_kif_move_piece(rest, movement);
+    }
+
+    let mut chars = text.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?;
+    movement.destination_file = "１２３４５６７８９".chars().position(|c| c==file)? as i8 + 1;
+    movement.destination_rank = "一二三四五六七八九".chars().position(|c| c==rank)? as i8 + 1;
+    parse_kif_move_piece(chars.as_str(), movement)
+}
+
+fn parse_kif_move_piece(text:&str, mut movement:UsiMovement) -> Option<UsiMovement> {
+    // 長い駒名から順に試す。
+    const NAMES : [(&str, PieceType); 20] = [
+        ("成銀", PieceType::PS), ("成桂", PieceType::PN), ("成香", PieceType::PL),
+        ("全", PieceType::PS), ("圭", PieceType::PN), ("杏", PieceType::PL),
+        ("龍", PieceType::PR), ("竜", PieceType::PR), ("馬", PieceType::PB), ("と", PieceType::PP),
+        ("玉", PieceType::K), ("王", PieceType::K), ("飛", PieceType::R), ("角", PieceType::B),
+        ("金", PieceType::G), ("銀", PieceType::S), ("桂", PieceType::N), ("香", PieceType::L),
+        ("歩", PieceType::P), ("", PieceType::Space),
+    ];
+    let &(name, pt) = NAMES.iter().find(|x| text.starts_with(x.0))?;
+    if name.is_empty() { return None; }
+    let mut rest = &text[name.len()..];
+
+    if rest.starts_with("打") {
+        movement.drop = pt;
+        return Some(movement);
+    }
+    if rest.starts_with("不成") {
+        rest = &rest["不成".len()..];
+    } else if rest.starts_with('成') {
+        movement.promotion = true;
+        rest = &rest['成'.len_utf8()..];
+    }
+
+    // 移動元 (77)。括弧がなければ打とみなす。
+    let rest = rest.trim_start();
+    if !rest.starts_with('(') {
+        movement.drop = pt;
+        return Some(movement);
+    }
+    let bytes = rest.as_bytes();
+    if bytes.len()<4 || !bytes[1].is_ascii_digit() || !bytes[2].is_ascii_digit() { return None; }
+    movement.source_file = (bytes[1] - b'0') as i8;
+    movement.source_rank = (bytes[2] - b'0') as i8;
+    Some(movement)
+}
+
+/// KIF の棋譜を読み取る。開始局面と指し手を返す。
+///
+/// 開始局面は局面図（BOD）か `手合割` から決める。どちらも書かれていなければ平手、知らない手合割なら None。
+/// 変化（分岐）は読まない。
+pub fn parse_kif(text:&str) -> Option<(Position, Vec<UsiMovement>)> {
+    let has_bod = text.lines().any(|x| x.trim_start().starts_with("+---"));
+    let mut start = if has_bod { parse_bod(text)? } else { Position::startpos() };
+    let mut moves : Vec<UsiMovement> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with("手合割：") || line.starts_with("手合割:") {
+            let name = line.trim_start_matches("手合割").trim_start_matches(['：', ':']);
+            if !has_bod { start = Handicap::from_kif_name(name)?.position(); }
+            continue;
+        }
+        if line.starts_with("変化：") { break; }
+
+        // 手数から始まる行だけが指し手。
+        let digits = line.bytes().take_while(|x| x.is_ascii_digit()).count();
+        if digits==0 { continue; }
+        // 指し手の後ろの消費時間は読まない。`同　歩` の空白は指し手に含める。
+        let body = line[digits..].trim_start();
+        let (prefix, rest) = if body.starts_with('同') {
+            ("同", body['同'.len_utf8()..].trim_start_matches([' ', '　']))
+        } else {
+            ("", body)
+        };
+        let text = format!("{}{}", prefix, rest.split(' ').next().unwrap_or(""));
+        match parse_kif_move(&text, moves.last()) {
+            Some(movement) => { moves.push(movement); },
+            None => { break; }, // 投了など
+        }
+    }
+    Some((start, moves))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csa_move_round_trip() {
+        let mut position = Position::from_sfen("lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b Pp 1");
+        for _ in 0..4 {
+            for movement in position.legal_moves().iter() {
+                let text = csa_move(&position, movement);
+                assert_eq!(parse_csa_move(&position, &text), Some(*movement), "{}", text);
+            }
+            let first = position.legal_moves()[0];
+            position.do_move(&first);
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_csa_moves() {
+        let position = Position::startpos();
+        assert!(parse_csa_move(&position, "+7776FU").is_some());
+        // 手番違い、空の升から、駒名違い、成れない駒の成り、持ち駒にできない駒を打つ。
+        for text in ["-7776FU", "+5554FU", "+7776KY", "+6978NK", "+0055OU", "+7776F", "+7776FU,T1"].iter() {
+            assert_eq!(parse_csa_move(&position, text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn repetition_endings() {
+        assert_eq!(GameEnding::Repetition.winner(Color::Sente), None);
+        assert_eq!(GameEnding::PerpetualCheck{ checker : Color::Sente }.winner(Color::Gote), Some(Color::Gote));
+        let record = GameRecord{
+            names : ["a".to_string(), "b".to_string()],
+            start : Position::startpos(),
+            moves : Vec::new(),
+            ending : GameEnding::PerpetualCheck{ checker : Color::Gote },
+        };
+        let mut csa = Vec::new();
+        write_csa(&mut csa, &record).unwrap();
+        assert!(String::from_utf8(csa).unwrap().ends_with("%-ILLEGAL_ACTION\n"));
+        let mut kif = Vec::new();
+        write_kif(&mut kif, &GameRecord{ ending : GameEnding::Repetition, ..record }).unwrap();
+        assert!(String::from_utf8(kif).unwrap().ends_with("まで0手で千日手\n"));
+    }
+}
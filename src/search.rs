@@ -0,0 +1,673 @@
+//! 反復深化の探索の枠組み☆（＾～＾）
+//!
+//! エンジンは `Search` で局面の読みだけを書く。反復深化、アスピレーション窓、MultiPV、
+//! `info` と `bestmove` の出力、`stop` と時間の見張りは `think` が受け持つ。
+//!
+//! `think_parallel` は Lazy SMP で、同じ読みを何本ものスレッドで回す。置換表を分け合うのはエンジンの役目。
+//!
+//! 評価値は手番の側から見た値。詰みは、その局面から n 手で詰ますなら `MATE - n`、詰まされるなら `n - MATE`。
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use engine_output::{BestMove, Info, Score, ScoreBound};
+use position::Position;
+use time_manager::TimeBudget;
+use UsiMovement;
+
+/// 詰みの評価値。詰むまでの手数の分だけ 0 に寄せて使う。
+pub const MATE : i32 = 30000;
+/// 評価値の上限。アルファベータの窓の端。
+pub const INFINITE : i32 = 32000;
+/// 読みの深さの上限。
+pub const MAX_PLY : u32 = 128;
+
+/// 詰みの評価値なら真。
+pub fn is_mate_score(score:i32) -> bool {
+    MATE - MAX_PLY as i32<=score.abs() && score.abs()<=MATE
+}
+
+/// USI の `score` にする。
+pub fn usi_score(score:i32) -> Score {
+    if !is_mate_score(score) { return Score::Cp(score); }
+    Score::Mate(if 0<score { MATE - score } else { -(MATE + score) })
+}
+
+/// 子の局面の評価値を、1手前の局面から見た値にする。詰みは1手遠くなる。
+fn from_child(score:i32) -> i32 {
+    let score = -score;
+    if !is_mate_score(score) { score } else if 0<score { score - 1 } else { score + 1 }
+}
+
+/// 1手前の局面の窓の端を、子の局面から見た値にする。`from_child` の逆。
+fn to_child(bound:i32) -> i32 {
+    if !is_mate_score(bound) { -bound } else if 0<bound { -(bound + 1) } else { -(bound - 1) }
+}
+
+/// 考える時間の制限。`started` から数える。
+#[derive(Copy,Clone,Debug)]
+struct TimeLimit{
+    started : Instant,
+    budget : TimeBudget,
+}
+
+/// `stop` の旗と時間の制限。複製は同じものを指すので、読み取りスレッドと探索スレッドで分け合う。
+#[derive(Clone)]
+pub struct SearchClock{
+    stop : Arc<AtomicBool>,
+    limit : Arc<Mutex<Option<TimeLimit>>>,
+}
+impl Default for SearchClock{
+    fn default() -> SearchClock {
+        SearchClock{
+            stop : Arc::new(AtomicBool::new(false)),
+            limit : Arc::new(Mutex::new(None)),
+        }
+    }
+}
+impl SearchClock{
+    pub fn new() -> SearchClock {
+        SearchClock::default()
+    }
+
+    /// 考え始める前に呼ぶ。`stop` を下ろし、時間の制限を外す。
+    pub fn start(&self) {
+        self.stop.store(false, Ordering::Relaxed);
+        *self.limit.lock().unwrap() = None;
+    }
+
+    /// `stop` を上げる。探索はすぐ終わる。
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// 今から時間を数える。`go` と `ponderhit` で呼ぶ。先読みと `go infinite` では呼ばない。
+    pub fn set_limit(&self, budget:TimeBudget) {
+        *self.limit.lock().unwrap() = Some(TimeLimit{ started : Instant::now(), budget });
+    }
+
+    /// 時間の制限があれば真。
+    pub fn is_limited(&self) -> bool {
+        self.limit.lock().unwrap().is_some()
+    }
+
+    /// 時間を数え始めてからの時間。制限が無ければ None。
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.limit.lock().unwrap().map(|x| x.started.elapsed())
+    }
+
+    /// `stop` が来たか、上限を過ぎたら真。`Search::search` の中で時々見る。
+    pub fn is_over(&self) -> bool {
+        if self.is_stopped() { return true; }
+        self.limit.lock().unwrap().is_some_and(|x| x.budget.maximum<=x.started.elapsed())
+    }
+
+    /// 目安を過ぎたら真。`changes` は最近の深さで最善手が変わった回数で、多いほど目安を延ばす。
+    pub fn is_soft_over(&self, changes:f64) -> bool {
+        self.limit.lock().unwrap().is_some_and(|x| x.budget.optimum_with_instability(changes)<=x.started.elapsed())
+    }
+}
+
+/// エンジンが書く読み。
+pub trait Search{
+    /// `think` の初めに呼ぶ。局面数などを 0 に戻す。
+    fn new_search(&mut self) {}
+
+    /// `position` を `depth` 手読んで、手番の側から見た評価値と、`position` からの読み筋を返す。
+    ///
+    /// `depth` が 0 でも呼ぶ。時々 `clock.is_over()` を見て、真ならすぐ返す。その結果は使わない。
+    fn search(&mut self, position:&Position, depth:u32, alpha:i32, beta:i32, clock:&SearchClock) -> (i32, Vec<UsiMovement>);
+
+    /// `new_search` から数えた局面数。
+    fn nodes(&self) -> u64;
+
+    /// 一番深く読んだ手数。`search` に渡した局面から数える。
+    fn seldepth(&self) -> Option<u32> { None }
+
+    /// 置換表の使用率。千分率。
+    fn hashfull(&self) -> Option<u32> { None }
+}
+
+/// 反復深化の設定。
+///
+/// # Members.
+///
+/// * `max_depth` - この深さまで読んだら終わる。
+/// * `multipv` - 評価値の順に、何手の読み筋を出すか。
+/// * `aspiration_depth` - この深さからアスピレーション窓を使う。
+/// * `aspiration_delta` - アスピレーション窓の最初の半分の幅。外れるたびに倍にする。
+#[derive(Copy,Clone,Debug)]
+pub struct SearchOptions{
+    pub max_depth : u32,
+    pub multipv : usize,
+    pub aspiration_depth : u32,
+    pub aspiration_delta : i32,
+}
+impl Default for SearchOptions{
+    fn default() -> SearchOptions {
+        SearchOptions{
+            max_depth : MAX_PLY,
+            multipv : 1,
+            aspiration_depth : 4,
+            aspiration_delta : 64,
+        }
+    }
+}
+
+/// 初手1つ分の読み。
+#[derive(Clone)]
+struct RootMove{
+    movement : UsiMovement,
+    score : i32,
+    /// 1つ前の深さの評価値。アスピレーション窓の中心。
+    previous_score : i32,
+    pv : Vec<UsiMovement>,
+}
+
+/// 1つのスレッドが読み終えた結果。
+///
+/// * `root` - 評価値の順に並べた初手。
+/// * `depth` - 読み終えた深さ。1つも読み終えなければ 0。
+struct Outcome{
+    root : Vec<RootMove>,
+    depth : u32,
+}
+
+/// 途中経過を出す間隔。
+const PROGRESS_INTERVAL : Duration = Duration::from_secs(1);
+
+/// 助手のスレッドが読みを飛ばす深さの周期と位相。助手ごとに順に割り当て、読む深さをばらけさせる。
+const SKIP_SIZE : [u32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE : [u32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+/// `thread_id` のスレッドが `depth` を飛ばすなら真。主スレッドは飛ばさない。
+#[allow(clippy::manual_is_multiple_of)]
+fn is_skipped(thread_id:usize, depth:u32) -> bool {
+    if thread_id==0 { return false; }
+    let i = (thread_id - 1) % SKIP_SIZE.len();
+    (depth + SKIP_PHASE[i]) / SKIP_SIZE[i] % 2 == 1
+}
+
+/// 1つのスレッドの反復深化。
+///
+/// * `thread_id` - 0 が主スレッド。出力と、時間を見て止めるのは主スレッドだけ。
+/// * `nodes` - スレッドごとの局面数。`info` では全部を足す。
+struct Driver<'a, S:Search + 'a, W:Write + 'a>{
+    searcher : &'a mut S,
+    clock : &'a SearchClock,
+    out : &'a mut W,
+    thread_id : usize,
+    nodes : &'a [AtomicU64],
+    started : Instant,
+    last_output : Instant,
+}
+impl<'a, S:Search, W:Write> Driver<'a, S, W>{
+    fn new(searcher:&'a mut S, clock:&'a SearchClock, out:&'a mut W, thread_id:usize, nodes:&'a [AtomicU64]) -> Driver<'a, S, W> {
+        let started = Instant::now();
+        Driver{ searcher, clock, out, thread_id, nodes, started, last_output : started }
+    }
+
+    fn print(&mut self, line:&dyn fmt::Display) {
+        if self.thread_id!=0 { return; }
+        // GUI が居なくなったときは、書けなくても読みを続ける。
+        let _ = writeln!(self.out, "{}", line);
+        let _ = self.out.flush();
+        self.last_output = Instant::now();
+    }
+
+    /// 自分の局面数を、ほかのスレッドから見えるようにする。
+    fn publish(&self) {
+        self.nodes[self.thread_id].store(self.searcher.nodes(), Ordering::Relaxed);
+    }
+
+    /// 全部のスレッドの局面数。ほかのスレッドの分は、最後に `publish` した値。
+    fn total_nodes(&self) -> u64 {
+        self.nodes.iter().enumerate()
+            .map(|(i, x)| if i==self.thread_id { self.searcher.nodes() } else { x.load(Ordering::Relaxed) })
+            .sum()
+    }
+
+    fn info(&self, depth:u32) -> Info {
+        let millis = self.started.elapsed().as_millis() as u64;
+        let nodes = self.total_nodes();
+        Info{
+            depth : Some(depth),
+            // 初手の1手を足す。
+            seldepth : self.searcher.seldepth().map(|x| x + 1),
+            time : Some(millis),
+            nodes : Some(nodes),
+            nps : Some(nodes * 1000 / millis.max(1)),
+            hashfull : self.searcher.hashfull(),
+            ..Info::default()
+        }
+    }
+
+    /// 初手を順に読み、一番良い評価値を返す。`beta` 以上の手が出たらそこで止める。打ち切られたら None。
+    ///
+    /// 一番良い手より悪いと分かっただけの手は、評価値を `-INFINITE` にして後ろに回す。
+    fn search_root(&mut self, position:&Position, moves:&mut [RootMove], depth:u32, alpha:i32, beta:i32) -> Option<i32> {
+        let mut best = -INFINITE;
+        for (i, root_move) in moves.iter_mut().enumerate() {
+            let mut next = *position;
+            next.do_move(&root_move.movement);
+            let bound = alpha.max(best);
+            let (score, pv) = self.searcher.search(&next, depth - 1, to_child(beta), to_child(bound), self.clock);
+            self.publish();
+            if self.clock.is_over() { return None; }
+            let score = from_child(score);
+            if i==0 || bound<score {
+                root_move.score = score;
+                root_move.pv = vec![root_move.movement];
+                root_move.pv.extend(pv);
+            } else {
+                root_move.score = -INFINITE;
+            }
+            best = best.max(score);
+
+            if PROGRESS_INTERVAL<=self.last_output.elapsed() {
+                let info = Info{ currmove : Some(root_move.movement), ..self.info(depth) };
+                self.print(&info);
+            }
+            if beta<=score { break; }
+        }
+        Some(best)
+    }
+}
+
+/// 評価値の高い順に並べる。同じ評価値なら元の順。
+fn sort_root_moves(moves:&mut [RootMove]) {
+    moves.sort_by_key(|x| -x.score);
+}
+
+/// 反復深化で考える。`clock` で打ち切られたら、読み終えた深さの結果を返す。
+///
+/// 主スレッドは、時間の制限があれば、目安を過ぎたか、指せる手が1つしか無いか、読み切った詰みを見つけたら終わる。
+/// 助手は `stop` か `max_depth` まで読み続ける。
+fn iterate<S:Search, W:Write>(driver:&mut Driver<S, W>, position:&Position, options:&SearchOptions) -> Outcome {
+    let clock = driver.clock;
+    let mut root : Vec<RootMove> = position.legal_moves().into_iter().map(|x| RootMove{
+        movement : x,
+        score : -INFINITE,
+        previous_score : -INFINITE,
+        pv : vec![x],
+    }).collect();
+    let multipv = options.multipv.max(1).min(root.len());
+    let mut completed_depth = 0;
+    // 最善手が変わった回数。深さを1つ進めるごとに半分にする。
+    let mut changes = 0.0;
+
+    for depth in 1..options.max_depth.max(1) + 1 {
+        if root.is_empty() { break; }
+        if is_skipped(driver.thread_id, depth) { continue; }
+        // 打ち切られたら、読み終えた深さの結果に戻す。
+        let completed = root.clone();
+        changes /= 2.0;
+        for x in root.iter_mut() { x.previous_score = x.score; }
+        let mut aborted = false;
+        for pv_index in 0..multipv {
+            let previous = root[pv_index].previous_score;
+            let mut delta = options.aspiration_delta.max(1);
+            let (mut alpha, mut beta) = if options.aspiration_depth<=depth && -INFINITE<previous && !is_mate_score(previous) {
+                ((previous - delta).max(-INFINITE), (previous + delta).min(INFINITE))
+            } else {
+                (-INFINITE, INFINITE)
+            };
+            loop {
+                let best = match driver.search_root(position, &mut root[pv_index..], depth, alpha, beta) {
+                    Some(x) => x,
+                    None => {
+                        aborted = true;
+                        break;
+                    },
+                };
+                sort_root_moves(&mut root[pv_index..]);
+                let bound = if best<=alpha && -INFINITE<alpha {
+                    // 窓の下に外れた。下を広げる。
+                    beta = (alpha + beta) / 2;
+                    alpha = (best - delta).max(-INFINITE);
+                    ScoreBound::Upper
+                } else if beta<=best && beta<INFINITE {
+                    beta = (best + delta).min(INFINITE);
+                    ScoreBound::Lower
+                } else {
+                    break;
+                };
+                if PROGRESS_INTERVAL<=driver.last_output.elapsed() {
+                    let info = Info{
+                        multipv : if 1<multipv { Some(pv_index as u32 + 1) } else { None },
+                        score : Some(usi_score(best)),
+                        bound,
+                        pv : root[pv_index].pv.clone(),
+                        ..driver.info(depth)
+                    };
+                    driver.print(&info);
+                }
+                delta = delta.saturating_mul(2);
+            }
+            if aborted { break; }
+            sort_root_moves(&mut root[..pv_index + 1]);
+        }
+        if aborted {
+            root = completed;
+            break;
+        }
+        if 0<completed_depth && root[0].movement!=completed[0].movement { changes += 1.0; }
+        completed_depth = depth;
+
+        for (i, x) in root.iter().take(multipv).enumerate() {
+            let info = Info{
+                multipv : if 1<multipv { Some(i as u32 + 1) } else { None },
+                score : Some(usi_score(x.score)),
+                pv : x.pv.clone(),
+                ..driver.info(depth)
+            };
+            driver.print(&info);
+        }
+
+        if driver.thread_id==0 && clock.is_limited() {
+            if clock.is_soft_over(changes) || root.len()==1 { break; }
+            // 読み切った詰みは、深く読んでも変わらない。
+            let score = root[0].score;
+            if is_mate_score(score) && (MATE - score.abs()) as u32<=depth { break; }
+        }
+    }
+
+    driver.publish();
+    Outcome{ root, depth : completed_depth }
+}
+
+/// 制限が無い間（先読みと `go infinite`）は、`stop` か `ponderhit` を待つ。
+fn wait_for_limit(clock:&SearchClock) {
+    while !clock.is_stopped() && !clock.is_limited() {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// `outcome` の最善手を `bestmove` にして書く。
+fn answer<S:Search, W:Write>(driver:&mut Driver<S, W>, outcome:&Outcome) -> BestMove {
+    let bestmove = match outcome.root.first() {
+        Some(x) => BestMove::Move{ best : x.movement, ponder : x.pv.get(1).cloned() },
+        None => BestMove::Resign,
+    };
+    driver.print(&bestmove);
+    bestmove
+}
+
+/// 反復深化で考えて、`info` と `bestmove` を `out` に書く。`bestmove` の中身も返す。
+///
+/// 時間の制限があれば、目安を過ぎたか、指せる手が1つしか無いか、読み切った詰みを見つけたら終わる。
+/// 制限が無い間（先読みと `go infinite`）は、読み終わっても `stop` か `ponderhit` を待ってから答える。
+pub fn think<S:Search, W:Write>(searcher:&mut S, position:&Position, options:&SearchOptions, clock:&SearchClock, out:&mut W) -> BestMove {
+    let nodes = [AtomicU64::new(0)];
+    searcher.new_search();
+    let mut driver = Driver::new(searcher, clock, out, 0, &nodes);
+    let outcome = iterate(&mut driver, position, options);
+    wait_for_limit(clock);
+    answer(&mut driver, &outcome)
+}
+
+/// 読み終えたスレッドの中から、`bestmove` にするものを選ぶ。
+///
+/// 初手ごとに、それを選んだスレッドの評価値と読んだ深さで票を数え、票の多いスレッドを選ぶ。
+/// 詰ます読みは票によらず優先し、その中では早く詰ますものを選ぶ。
+fn vote(outcomes:&[Outcome]) -> usize {
+    let finished : Vec<usize> = (0..outcomes.len()).filter(|&i| 0<outcomes[i].depth && !outcomes[i].root.is_empty()).collect();
+    let min_score = match finished.iter().map(|&i| outcomes[i].root[0].score).min() {
+        Some(x) => x,
+        None => { return 0; },
+    };
+    let mut votes = HashMap::new();
+    for &i in finished.iter() {
+        let best = &outcomes[i].root[0];
+        *votes.entry(best.movement).or_insert(0i64) += i64::from(best.score - min_score + 14) * i64::from(outcomes[i].depth);
+    }
+    let votes_of = |i:usize| votes[&outcomes[i].root[0].movement];
+    let is_winning = |score:i32| is_mate_score(score) && 0<score;
+
+    let mut chosen = finished[0];
+    for &i in finished.iter().skip(1) {
+        let (best, score) = (outcomes[chosen].root[0].score, outcomes[i].root[0].score);
+        if is_winning(best) {
+            if best<score { chosen = i; }
+        } else if is_winning(score) || votes_of(chosen)<votes_of(i) {
+            chosen = i;
+        }
+    }
+    chosen
+}
+
+/// `searchers` の数だけのスレッドで `think` する（Lazy SMP）。先頭が主スレッドで、`info` と `bestmove` を書く。
+///
+/// 助手のスレッドも同じ局面を同じように読むが、深さを飛ばしながら読むので、置換表を通して主スレッドの先回りをする。
+/// 主スレッドが答えを決めたら `clock.stop()` で助手を止め、全部のスレッドの票で最善手を選ぶ。
+/// `info` の局面数は全部のスレッドの合計。MultiPV のときは主スレッドの結果だけを使う。
+pub fn think_parallel<S:Search + Send, W:Write>(searchers:&mut [S], position:&Position, options:&SearchOptions, clock:&SearchClock, out:&mut W) -> BestMove {
+    let (main, helpers) = searchers.split_first_mut().expect("think_parallel needs at least one searcher.");
+    let nodes : Vec<AtomicU64> = (0..helpers.len() + 1).map(|_| AtomicU64::new(0)).collect();
+    let nodes = &nodes[..];
+    thread::scope(|scope| {
+        let handles : Vec<_> = helpers.iter_mut().enumerate().map(|(i, searcher)| {
+            scope.spawn(move || {
+                searcher.new_search();
+                let mut sink = io::sink();
+                let mut driver = Driver::new(searcher, clock, &mut sink, i + 1, nodes);
+                iterate(&mut driver, position, options)
+            })
+        }).collect();
+
+        main.new_search();
+        let mut driver = Driver::new(main, clock, out, 0, nodes);
+        let outcome = iterate(&mut driver, position, options);
+        wait_for_limit(clock);
+        clock.stop();
+        let mut outcomes = vec![outcome];
+        outcomes.extend(handles.into_iter().map(|x| x.join().unwrap()));
+
+        let chosen = if options.multipv<=1 { vote(&outcomes) } else { 0 };
+        if chosen!=0 {
+            // 主スレッドと違う読みを選んだら、その読み筋を出しておく。
+            let best = &outcomes[chosen].root[0];
+            let info = Info{
+                score : Some(usi_score(best.score)),
+                pv : best.pv.clone(),
+                ..driver.info(outcomes[chosen].depth)
+            };
+            driver.print(&info);
+        }
+        answer(&mut driver, &outcomes[chosen])
+    })
+}
+
+/// `think` を標準出力に書く。
+pub fn think_to_stdout<S:Search>(searcher:&mut S, position:&Position, options:&SearchOptions, clock:&SearchClock) -> BestMove {
+    think(searcher, position, options, clock, &mut io::stdout())
+}
+
+/// `think_parallel` を標準出力に書く。
+pub fn think_parallel_to_stdout<S:Search + Send>(searchers:&mut [S], position:&Position, options:&SearchOptions, clock:&SearchClock) -> BestMove {
+    think_parallel(searchers, position, options, clock, &mut io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine_output::parse_movement_token;
+
+    /// 玉だけの局面。先手の玉の手は 4h, 5h, 6h, 4i, 6i の5つ。
+    const KINGS : &str = "4k4/9/9/9/9/9/9/9/4K4 b - 1";
+
+    fn movement(text:&str) -> UsiMovement {
+        parse_movement_token(text).unwrap()
+    }
+
+    /// 初手ごとに決めた評価値を返す読み。書いていない初手は 0。
+    ///
+    /// * `scores` - 初手の後の局面の `key` と、先手から見た評価値。
+    /// * `jump` - この深さから、この初手の評価値を変える。
+    /// * `stop_at` - この深さを読み始めたら `stop` を上げる。時間切れの代わり。
+    /// * `calls` - 呼ばれた (局面の `key`, 深さ, alpha, beta)。
+    #[derive(Default)]
+    struct Scripted{
+        scores : Vec<(u64, i32)>,
+        jump : Option<(u32, u64, i32)>,
+        stop_at : Option<u32>,
+        calls : Vec<(u64, u32, i32, i32)>,
+    }
+    impl Search for Scripted{
+        fn search(&mut self, position:&Position, depth:u32, alpha:i32, beta:i32, clock:&SearchClock) -> (i32, Vec<UsiMovement>) {
+            let key = position.key();
+            self.calls.push((key, depth, alpha, beta));
+            if self.stop_at==Some(depth + 1) { clock.stop(); }
+            let mut score = self.scores.iter().find(|x| x.0==key).map_or(0, |x| x.1);
+            if let Some((from, jumped, x)) = self.jump {
+                if from<=depth + 1 && jumped==key { score = x; }
+            }
+            (-score, Vec::new())
+        }
+
+        fn nodes(&self) -> u64 {
+            self.calls.len() as u64
+        }
+    }
+
+    /// 初手 `text` の後の局面の `key`。
+    fn child_key(text:&str) -> u64 {
+        let mut position = Position::from_sfen(KINGS);
+        position.do_move(&movement(text));
+        position.key()
+    }
+
+    fn scripted(scores:&[(&str, i32)]) -> Scripted {
+        Scripted{ scores : scores.iter().map(|&(x, score)| (child_key(x), score)).collect(), ..Scripted::default() }
+    }
+
+    /// 十分な時間の制限を掛けた時計。制限が無いと `think` は `stop` を待ち続ける。
+    fn clock(optimum:Duration) -> SearchClock {
+        let clock = SearchClock::new();
+        clock.set_limit(TimeBudget{ optimum, maximum : Duration::from_secs(3600) });
+        clock
+    }
+
+    /// `think` して、書いた行と `bestmove` の指し手を返す。
+    fn run(searcher:&mut Scripted, options:&SearchOptions, clock:&SearchClock) -> (Vec<String>, String) {
+        let mut out = Vec::new();
+        let bestmove = think(searcher, &Position::from_sfen(KINGS), options, clock, &mut out);
+        let lines = String::from_utf8(out).unwrap().lines().map(|x| x.to_string()).collect();
+        match bestmove {
+            BestMove::Move{ best, .. } => (lines, best.to_string()),
+            other => panic!("{}", other),
+        }
+    }
+
+    /// `depth` 手の読みを終えたときの `info` の行。
+    fn infos_at(lines:&[String], depth:u32) -> Vec<String> {
+        let prefix = format!("info depth {} ", depth);
+        lines.iter().filter(|x| x.starts_with(&prefix) && x.contains(" pv ")).cloned().collect()
+    }
+
+    #[test]
+    fn multipv_is_sorted_by_score() {
+        let mut searcher = scripted(&[("5i5h", 30), ("5i4h", -20), ("5i6h", 50)]);
+        let options = SearchOptions{ max_depth : 3, multipv : 3, ..SearchOptions::default() };
+        let (lines, best) = run(&mut searcher, &options, &clock(Duration::from_secs(3600)));
+        assert_eq!(best, "5i6h");
+        let infos = infos_at(&lines, 3);
+        assert_eq!(infos.len(), 3, "{:?}", lines);
+        assert!(infos[0].contains("multipv 1 score cp 50 pv 5i6h"), "{}", infos[0]);
+        assert!(infos[1].contains("multipv 2 score cp 30 pv 5i5h"), "{}", infos[1]);
+        assert!(infos[2].contains("multipv 3 score cp 0 pv"), "{}", infos[2]);
+        assert_eq!(lines.last().unwrap(), "bestmove 5i6h");
+    }
+
+    #[test]
+    fn aspiration_fail_high_widens_the_window() {
+        let mut searcher = scripted(&[("5i5h", 100)]);
+        searcher.jump = Some((4, child_key("5i5h"), 500));
+        let options = SearchOptions{ max_depth : 4, ..SearchOptions::default() };
+        let (lines, best) = run(&mut searcher, &options, &clock(Duration::from_secs(3600)));
+        assert_eq!(best, "5i5h");
+        assert!(infos_at(&lines, 4)[0].contains("score cp 500 "), "{:?}", lines);
+        // 前の深さの 100 を中心に ±64 の窓で外れ、上だけ 500 + 64 まで広げて読み直す。子から見て符号は逆。
+        let key = child_key("5i5h");
+        let windows : Vec<(i32, i32)> = searcher.calls.iter().filter(|x| x.0==key && x.1==3).map(|x| (x.2, x.3)).collect();
+        assert_eq!(windows, [(-164, -36), (-564, -36)]);
+    }
+
+    #[test]
+    fn aspiration_fail_low_widens_the_window() {
+        let mut searcher = scripted(&[("5i5h", 100)]);
+        searcher.jump = Some((4, child_key("5i5h"), -300));
+        let options = SearchOptions{ max_depth : 4, ..SearchOptions::default() };
+        let (_, best) = run(&mut searcher, &options, &clock(Duration::from_secs(3600)));
+        assert_ne!(best, "5i5h");
+        // 下に外れたら、上は窓の真ん中まで寄せ、下はその深さで一番良かった 0 から 64 下まで広げる。
+        let key = child_key("5i5h");
+        let windows : Vec<(i32, i32)> = searcher.calls.iter().filter(|x| x.0==key && x.1==3).map(|x| (x.2, x.3)).collect();
+        assert_eq!(windows, [(-164, -36), (-100, 64)]);
+    }
+
+    #[test]
+    fn stops_after_the_optimum() {
+        // 目安が 0 なら、1手読み終えたところで止める。
+        let mut searcher = scripted(&[("5i4h", 10)]);
+        let options = SearchOptions::default();
+        let (lines, best) = run(&mut searcher, &options, &clock(Duration::from_secs(0)));
+        assert_eq!(best, "5i4h");
+        assert!(searcher.calls.iter().all(|x| x.1==0));
+        assert_eq!(infos_at(&lines, 1).len(), 1);
+    }
+
+    #[test]
+    fn stop_keeps_the_last_completed_depth() {
+        // 3手目を読み始めたところで時間が切れる。3手読みでは 5i6h が良く見えるが、読み終えていない。
+        let mut searcher = scripted(&[("5i4h", 10)]);
+        searcher.jump = Some((3, child_key("5i6h"), 900));
+        searcher.stop_at = Some(3);
+        let options = SearchOptions::default();
+        let (lines, best) = run(&mut searcher, &options, &clock(Duration::from_secs(3600)));
+        assert_eq!(best, "5i4h");
+        assert_eq!(infos_at(&lines, 2).len(), 1);
+        assert!(infos_at(&lines, 3).is_empty());
+    }
+
+    fn outcome(text:&str, score:i32, depth:u32) -> Outcome {
+        let movement = movement(text);
+        Outcome{ root : vec![RootMove{ movement, score, previous_score : score, pv : vec![movement] }], depth }
+    }
+
+    #[test]
+    fn vote_prefers_the_deeper_thread() {
+        assert_eq!(vote(&[outcome("7g7f", 10, 5), outcome("2g2f", 10, 10)]), 1);
+        assert_eq!(vote(&[outcome("7g7f", 10, 10), outcome("2g2f", 10, 5)]), 0);
+    }
+
+    #[test]
+    fn vote_prefers_the_better_score() {
+        assert_eq!(vote(&[outcome("7g7f", 0, 5), outcome("2g2f", 100, 5)]), 1);
+    }
+
+    #[test]
+    fn vote_adds_up_the_same_move() {
+        // 同じ手を選んだ2つのスレッドの票は合わせて数える。
+        let outcomes = [outcome("2g2f", 10, 8), outcome("7g7f", 10, 5), outcome("7g7f", 10, 5)];
+        assert_eq!(vote(&outcomes), 1);
+    }
+
+    #[test]
+    fn vote_prefers_the_quicker_mate() {
+        let outcomes = [outcome("7g7f", 300, 20), outcome("2g2f", MATE - 7, 3), outcome("5g5f", MATE - 3, 2)];
+        assert_eq!(vote(&outcomes), 2);
+        // 読み終えていないスレッドは数えない。
+        let outcomes = [outcome("7g7f", 10, 5), Outcome{ root : Vec::new(), depth : 0 }, outcome("2g2f", MATE - 1, 0)];
+        assert_eq!(vote(&outcomes), 0);
+    }
+}
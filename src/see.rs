@@ -0,0 +1,142 @@
+//! 駒の取り合いの損得☆（＾～＾） SEE（静的交換評価）。
+//!
+//! 1つのマスで、両方が一番安い駒から取り返していったときの駒の損得を数える。
+//! 取った駒は持ち駒になるので、取られた側は盤上の駒を失い、取った側は成る前の駒を持ち駒に得る。
+//! 走り駒の後ろにいる駒も、前の駒が取りに出れば利きが通る。
+use movegen::is_promotion_zone;
+use position::Position;
+use {Piece, PieceType, UsiMovement, file_rank_to_cell};
+
+/// 駒種類ごとの価値。`PieceType as usize` で引く。成駒も別に決める。
+#[derive(Copy,Clone,Debug)]
+pub struct PieceValues{
+    pub values : [i32; 14],
+}
+impl Default for PieceValues{
+    fn default() -> PieceValues {
+        PieceValues{
+            // 玉 飛 角 金 銀 桂 香 歩 竜 馬 全 圭 杏 と
+            values : [15000, 990, 855, 540, 495, 405, 315, 90, 1395, 945, 540, 540, 540, 540],
+        }
+    }
+}
+impl PieceValues{
+    pub fn value(&self, pt:PieceType) -> i32 {
+        self.values[pt as usize]
+    }
+
+    /// 駒種類の価値を変える。
+    pub fn set(&mut self, pt:PieceType, value:i32) {
+        self.values[pt as usize] = value;
+    }
+
+    /// その駒を取ったときの得。盤上の駒の価値と、持ち駒にした駒の価値。
+    pub fn capture_gain(&self, pt:PieceType) -> i32 {
+        self.value(pt) + self.value(pt.demote())
+    }
+
+    /// 成ったときの得。
+    pub fn promotion_gain(&self, pt:PieceType) -> i32 {
+        self.value(pt.promote()) - self.value(pt)
+    }
+}
+
+impl Position{
+    /// 指し手の後、そのマスで取り合ったときの手番の側の駒の得。
+    ///
+    /// 両方とも一番安い駒から取り返し、損になるところで止める。取り返す駒は成れるなら成る。
+    /// ピンと、王手を放っておく取り返しは考えない。玉は相手の利きが残っていれば取り返さない。
+    pub fn see(&self, movement:&UsiMovement, values:&PieceValues) -> i32 {
+        let to = (movement.destination_file, movement.destination_rank);
+        let captured = self.piece(to.0, to.1);
+        let mut gains = vec![if captured.is_space() { 0 } else { values.capture_gain(captured.piece_type()) }];
+        let mut position = *self;
+        let mut on_square = if movement.drop!=PieceType::Space {
+            movement.drop
+        } else {
+            let pt = self.piece(movement.source_file, movement.source_rank).piece_type();
+            position.board[file_rank_to_cell(movement.source_file, movement.source_rank)] = Piece::Space;
+            pt
+        };
+        if movement.promotion {
+            gains[0] += values.promotion_gain(on_square);
+            on_square = on_square.promote();
+        }
+
+        let mut color = self.turn.opponent();
+        while let Some(from) = position.attackers(to.0, to.1, color).into_iter()
+            .min_by_key(|x| values.value(position.piece(x.0, x.1).piece_type())) {
+            let mut pt = position.piece(from.0, from.1).piece_type();
+            position.board[file_rank_to_cell(from.0, from.1)] = Piece::Space;
+            if pt==PieceType::K && position.is_attacked(to.0, to.1, color.opponent()) { break; }
+            let mut gain = values.capture_gain(on_square);
+            if pt.can_promote() && (is_promotion_zone(color, from.1) || is_promotion_zone(color, to.1)) && 0<values.promotion_gain(pt) {
+                gain += values.promotion_gain(pt);
+                pt = pt.promote();
+            }
+            // 取り返すと決めたときの、取り返す側から見た得。
+            gains.push(gain - gains[gains.len() - 1]);
+            on_square = pt;
+            color = color.opponent();
+        }
+
+        // 後ろから、取り返すか止めるかを選ぶ。
+        while 1<gains.len() {
+            let last = gains.pop().unwrap_or(0);
+            let previous = gains.len() - 1;
+            gains[previous] = -(-gains[previous]).max(last);
+        }
+        gains[0]
+    }
+
+    /// `see` が `threshold` 以上なら真。
+    pub fn see_ge(&self, movement:&UsiMovement, threshold:i32, values:&PieceValues) -> bool {
+        threshold<=self.see(movement, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn see(sfen:&str, usi:&str) -> i32 {
+        let position = Position::from_sfen(sfen);
+        let movement = position.legal_moves().into_iter().find(|x| x.to_string()==usi).unwrap();
+        position.see(&movement, &PieceValues::default())
+    }
+
+    #[test]
+    fn free_capture() {
+        assert_eq!(see("4k4/9/4p4/9/4R4/9/9/9/4K4 b - 1", "5e5c"), 180);
+        // 成れば、竜と飛の価値の差も得。
+        assert_eq!(see("4k4/9/4p4/9/4R4/9/9/9/4K4 b - 1", "5e5c+"), 180 + 405);
+    }
+
+    #[test]
+    fn defended_capture() {
+        // 歩を取った飛車を金で取り返される。
+        assert_eq!(see("4k4/5g3/4p4/9/4R4/9/9/9/4K4 b - 1", "5e5c"), 180 - 1980);
+    }
+
+    #[test]
+    fn x_ray() {
+        // 後ろの飛車がいなければ、歩を取って金に取り返されて差し引き 0。
+        assert_eq!(see("4k4/5g3/4p4/4P4/9/9/9/9/4K4 b - 1", "5d5c"), 0);
+        // 飛車の利きが通るので、金は取り返さない。
+        assert_eq!(see("4k4/5g3/4p4/4P4/9/9/4R4/9/4K4 b - 1", "5d5c"), 180);
+    }
+
+    #[test]
+    fn drop_on_attacked_square() {
+        assert_eq!(see("4k4/9/9/4p4/9/9/9/9/4K4 b G 1", "G*5e"), -1080);
+    }
+
+    #[test]
+    fn king_does_not_recapture_defended_piece() {
+        assert_eq!(see("4k4/4p4/4G4/9/9/9/9/9/4K4 b - 1", "5c5b"), 180 - 1080);
+        assert_eq!(see("4k4/4p4/4G4/5N3/9/9/9/9/4K4 b - 1", "5c5b"), 180);
+        assert!(Position::from_sfen("4k4/4p4/4G4/5N3/9/9/9/9/4K4 b - 1").see_ge(
+            &UsiMovement{ source_file : 5, source_rank : 3, drop : PieceType::Space, destination_file : 5, destination_rank : 2, promotion : false },
+            180, &PieceValues::default()));
+    }
+}
@@ -0,0 +1,161 @@
+//! 升の番号☆（＾～＾）
+//!
+//! このクレートの盤は `file*10+rank` の 100 升の配列（`file_rank_to_cell`）だが、
+//! ほかのエンジンや学習データは、1一を 0 とした筋優先の 0～80 で数えることが多い。
+//!
+//! ```text
+//!   9  8  7  6  5  4  3  2  1
+//! 72 63 54 45 36 27 18  9  0  一
+//! 73 64 55 46 37 28 19 10  1  二
+//!           …
+//! 80 71 62 53 44 35 26 17  8  九
+//! ```
+//!
+//! `Square` はこの 0～80 を持ち、升の表し方の間を行き来する。
+//!
+//! * 配列の添字 - `cell`。`file_rank_to_cell` と同じ。
+//! * 0～80 - `index`。やねうら王や Apery の `Square` と同じ。
+//! * 筋と段 - `file`、`rank`。どちらも 1～9。
+//! * USI - `7g` のように、筋の数字と段のアルファベット。
+//! * CSA - `77` のように、筋の数字と段の数字。
+use std::fmt;
+
+use {file_rank_to_cell, num_to_lower_case};
+
+/// 盤上の1升。
+#[derive(Copy,Clone,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct Square(u8);
+impl Square{
+    /// 升の数。
+    pub const NUM : usize = 81;
+
+    /// 筋と段から。盤の外なら None。
+    pub fn new(file:i8, rank:i8) -> Option<Square> {
+        if (1..=9).contains(&file) && (1..=9).contains(&rank) {
+            Some(Square(((file - 1) * 9 + (rank - 1)) as u8))
+        } else {
+            None
+        }
+    }
+
+    /// 0～80 から。
+    pub fn from_index(index:usize) -> Option<Square> {
+        if index<Square::NUM { Some(Square(index as u8)) } else { None }
+    }
+
+    /// 盤の配列の添字から。0筋、0段、10段の番兵の升なら None。
+    pub fn from_cell(cell:usize) -> Option<Square> {
+        if 100<=cell { return None; }
+        Square::new((cell / 10) as i8, (cell % 10) as i8)
+    }
+
+    /// USI の `7g` から。
+    pub fn from_usi(text:&str) -> Option<Square> {
+        let bytes = text.as_bytes();
+        if bytes.len()!=2 { return None; }
+        Square::new(bytes[0].wrapping_sub(b'0') as i8, bytes[1].wrapping_sub(b'a' - 1) as i8)
+    }
+
+    /// CSA の `77` から。打の移動元の `00` は升ではないので None。
+    pub fn from_csa(text:&str) -> Option<Square> {
+        let bytes = text.as_bytes();
+        if bytes.len()!=2 { return None; }
+        Square::new(bytes[0].wrapping_sub(b'0') as i8, bytes[1].wrapping_sub(b'0') as i8)
+    }
+
+    /// 1一、1二、…、9九の順に全部の升。
+    pub fn all() -> impl Iterator<Item=Square> {
+        (0..Square::NUM as u8).map(Square)
+    }
+
+    pub fn file(self) -> i8 {
+        (self.0 / 9) as i8 + 1
+    }
+
+    pub fn rank(self) -> i8 {
+        (self.0 % 9) as i8 + 1
+    }
+
+    /// 0～80。
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// 盤の配列の添字。
+    pub fn cell(self) -> usize {
+        file_rank_to_cell(self.file(), self.rank())
+    }
+
+    /// 盤を 180 度回した升。後手から見た升。
+    pub fn flip(self) -> Square {
+        Square(Square::NUM as u8 - 1 - self.0)
+    }
+
+    pub fn to_usi(self) -> String {
+        format!("{}{}", self.file(), num_to_lower_case(self.rank()))
+    }
+
+    pub fn to_csa(self) -> String {
+        format!("{}{}", self.file(), self.rank())
+    }
+}
+impl fmt::Display for Square{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_usi())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_square_round_trips() {
+        assert_eq!(Square::all().count(), Square::NUM);
+        for (i, sq) in Square::all().enumerate() {
+            assert_eq!(sq.index(), i);
+            assert_eq!(Square::from_index(sq.index()), Some(sq));
+            assert_eq!(Square::new(sq.file(), sq.rank()), Some(sq));
+            assert_eq!(sq.cell(), file_rank_to_cell(sq.file(), sq.rank()));
+            assert_eq!(Square::from_cell(sq.cell()), Some(sq));
+            assert_eq!(Square::from_usi(&sq.to_usi()), Some(sq));
+            assert_eq!(Square::from_csa(&sq.to_csa()), Some(sq));
+        }
+    }
+
+    #[test]
+    fn layout() {
+        // 1一が 0、1九が 8、9九が 80。
+        assert_eq!(Square::new(1, 1).unwrap().index(), 0);
+        assert_eq!(Square::new(1, 9).unwrap().index(), 8);
+        assert_eq!(Square::new(2, 1).unwrap().index(), 9);
+        assert_eq!(Square::new(9, 9).unwrap().index(), 80);
+        let sq = Square::from_usi("7g").unwrap();
+        assert_eq!((sq.file(), sq.rank(), sq.to_csa(), sq.to_string()), (7, 7, "77".to_string(), "7g".to_string()));
+    }
+
+    #[test]
+    fn out_of_board() {
+        assert_eq!(Square::new(0, 5), None);
+        assert_eq!(Square::new(5, 10), None);
+        assert_eq!(Square::from_index(Square::NUM), None);
+        // 番兵の升。
+        for &cell in [0, 5, 9, 10, 50, 90, 100].iter() { assert_eq!(Square::from_cell(cell), None, "{}", cell); }
+        assert_eq!(Square::from_usi("0a"), None);
+        assert_eq!(Square::from_usi("1j"), None);
+        assert_eq!(Square::from_usi("7g+"), None);
+        assert_eq!(Square::from_csa("00"), None);
+        assert_eq!(Square::from_csa("7"), None);
+    }
+
+    #[test]
+    fn flip_is_an_involution() {
+        for sq in Square::all() {
+            let flipped = sq.flip();
+            assert_eq!(flipped.flip(), sq);
+            assert_eq!((flipped.file(), flipped.rank()), (10 - sq.file(), 10 - sq.rank()));
+        }
+        // 真ん中の5五だけが動かない。
+        assert_eq!(Square::all().filter(|x| x.flip()==*x).collect::<Vec<Square>>(), [Square::new(5, 5).unwrap()]);
+    }
+}
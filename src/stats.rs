@@ -0,0 +1,354 @@
+//! エンジンの強さ比べの統計☆（＾～＾）
+//!
+//! 対局結果を1局ずつ（先後入れ替えのペアなら2局ずつ）足していって、
+//! レーティング差とその信頼区間、LOS（優越確率）、SPRT（逐次確率比検定）を出す。
+//! 結果はいつでも問い合わせられるので、SPRT の結論が出たところで打ち切ってもいい。
+
+/// 1局の結果。エンジン1から見て。
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum GameResult{
+    Win,
+    Draw,
+    Loss,
+}
+impl GameResult{
+    /// 勝ち 1、引き分け 0.5、負け 0。
+    pub fn score(self) -> f64 {
+        match self {
+            GameResult::Win => 1.0,
+            GameResult::Draw => 0.5,
+            GameResult::Loss => 0.0,
+        }
+    }
+}
+
+/// 勝率からレーティング差。
+pub fn elo_from_score(score:f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// レーティング差から勝率。
+pub fn score_from_elo(elo:f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// 誤差関数。Abramowitz and Stegun 7.1.26 の近似で、誤差は 1.5e-7 以下。
+fn erf(x:f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let y = 1.0 - t * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429)))) * (-x * x).exp();
+    if x<0.0 { -y } else { y }
+}
+
+/// 標準正規分布の累積分布関数。
+pub fn normal_cdf(x:f64) -> f64 {
+    0.5 * (1.0 + erf(x / 2f64.sqrt()))
+}
+
+/// 標準正規分布の累積分布関数の逆関数。Acklam の近似で、相対誤差は 1.2e-9 以下。
+pub fn normal_quantile(p:f64) -> f64 {
+    const A : [f64;6] = [-3.969_683_028_665_376e1, 2.209_460_984_245_205e2, -2.759_285_104_469_687e2, 1.383_577_518_672_69e2, -3.066_479_806_614_716e1, 2.506_628_277_459_239];
+    const B : [f64;5] = [-5.447_609_879_822_406e1, 1.615_858_368_580_409e2, -1.556_989_798_598_866e2, 6.680_131_188_771_972e1, -1.328_068_155_288_572e1];
+    const C : [f64;6] = [-7.784_894_002_430_293e-3, -3.223_964_580_411_365e-1, -2.400_758_277_161_838, -2.549_732_539_343_734, 4.374_664_141_464_968, 2.938_163_982_698_783];
+    const D : [f64;4] = [7.784_695_709_041_462e-3, 3.224_671_290_700_398e-1, 2.445_134_137_142_996, 3.754_408_661_907_416];
+    const LOW : f64 = 0.024_25;
+
+    if p<=0.0 { return f64::NEG_INFINITY; }
+    if 1.0<=p { return f64::INFINITY; }
+    if p<LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0]*q + C[1])*q + C[2])*q + C[3])*q + C[4])*q + C[5]) / ((((D[0]*q + D[1])*q + D[2])*q + D[3])*q + 1.0)
+    } else if p<=1.0 - LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0]*r + A[1])*r + A[2])*r + A[3])*r + A[4])*r + A[5])*q / (((((B[0]*r + B[1])*r + B[2])*r + B[3])*r + B[4])*r + 1.0)
+    } else {
+        -normal_quantile(1.0 - p)
+    }
+}
+
+/// レーティング差の推定。
+///
+/// # Members.
+///
+/// * `elo` - 推定値。
+/// * `lower` - 信頼区間の下端。
+/// * `upper` - 信頼区間の上端。
+#[derive(Copy,Clone,Debug)]
+pub struct EloEstimate{
+    pub elo : f64,
+    pub lower : f64,
+    pub upper : f64,
+}
+impl EloEstimate{
+    /// 信頼区間の幅の半分。`+/-` で表示する用。
+    pub fn error(&self) -> f64 {
+        (self.upper - self.lower) / 2.0
+    }
+}
+
+/// 対局結果の集計。エンジン1から見て。
+///
+/// # Members.
+///
+/// * `wdl` - 勝ち、引き分け、負けの数。ペアで足した分も含む。ペアの2局を除いた残りが、ペアにならなかった対局。
+/// * `pentanomial` - 先後入れ替えのペア2局の合計得点が 0, 0.5, 1, 1.5, 2 だった数。
+#[derive(Clone,Debug,Default)]
+pub struct MatchStats{
+    pub wdl : [u64;3],
+    pub pentanomial : [u64;5],
+}
+impl MatchStats{
+    pub fn new() -> MatchStats {
+        MatchStats::default()
+    }
+
+    /// 1局足す。
+    pub fn add_game(&mut self, result:GameResult) {
+        self.wdl[match result {
+            GameResult::Win => 0,
+            GameResult::Draw => 1,
+            GameResult::Loss => 2,
+        }] += 1;
+    }
+
+    /// 同じ開始局面で先後を入れ替えた2局を足す。
+    pub fn add_pair(&mut self, first:GameResult, second:GameResult) {
+        self.add_game(first);
+        self.add_game(second);
+        self.pentanomial[((first.score() + second.score()) * 2.0) as usize] += 1;
+    }
+
+    /// 対局数。
+    pub fn games(&self) -> u64 {
+        self.wdl.iter().sum()
+    }
+
+    /// ペアの数。
+    pub fn pairs(&self) -> u64 {
+        self.pentanomial.iter().sum()
+    }
+
+    /// 勝率。
+    pub fn score(&self) -> f64 {
+        let games = self.games();
+        if games==0 { return 0.5; }
+        (self.wdl[0] as f64 + self.wdl[1] as f64 * 0.5) / games as f64
+    }
+
+    /// ペアにならなかった対局の数。
+    pub fn unpaired(&self) -> u64 {
+        self.games().saturating_sub(2 * self.pairs())
+    }
+
+    /// 1局あたりの得点（0～1）の平均と、その平均の分散。
+    ///
+    /// ペアは2局の平均を1標本、ペアにならなかった1局はそのまま1標本として、局数で重み付けして合わせる。
+    /// ペアにならなかった局の分散は、数が少なくても見積もれるように、全部の対局の三項分布から取る。
+    /// 分散は、どの結果にも均等に合わせて1標本分の仮の数を足して見積もる。全勝や全敗でも0にならず、
+    /// 局数が増えるほど小さくなる。
+    fn estimate(&self) -> (f64, f64) {
+        let pairs = self.pairs() as f64;
+        let unpaired = self.unpaired() as f64;
+        let games = 2.0 * pairs + unpaired;
+        if games==0.0 { return (0.5, 0.0); }
+        let mean = self.score();
+        let spread = |weights:&[u64], scores:&[f64]| {
+            let prior = 1.0 / weights.len() as f64;
+            let n = weights.iter().sum::<u64>() as f64 + 1.0;
+            weights.iter().zip(scores).map(|(w, s)| (*w as f64 + prior) * (s - mean).powi(2)).sum::<f64>() / n
+        };
+        let pair_variance = spread(&self.pentanomial, &[0.0, 0.25, 0.5, 0.75, 1.0]);
+        let game_variance = spread(&self.wdl, &[1.0, 0.5, 0.0]);
+        (mean, (4.0 * pairs * pair_variance + unpaired * game_variance) / (games * games))
+    }
+
+    /// レーティング差と、`confidence`（0.95 なら95%）の信頼区間。
+    pub fn elo(&self, confidence:f64) -> EloEstimate {
+        let (mean, variance) = self.estimate();
+        let clamp = |x:f64| x.clamp(1e-6, 1.0 - 1e-6);
+        let margin = normal_quantile(1.0 - (1.0 - confidence) / 2.0) * variance.sqrt();
+        EloEstimate{
+            elo : elo_from_score(clamp(mean)),
+            lower : elo_from_score(clamp(mean - margin)),
+            upper : elo_from_score(clamp(mean + margin)),
+        }
+    }
+
+    /// LOS。エンジン1の方が強い確率。
+    pub fn los(&self) -> f64 {
+        let (mean, variance) = self.estimate();
+        if variance==0.0 {
+            return if 0.5<mean { 1.0 } else if mean<0.5 { 0.0 } else { 0.5 };
+        }
+        normal_cdf((mean - 0.5) / variance.sqrt())
+    }
+}
+
+/// SPRT の結論。
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum SprtDecision{
+    /// 帰無仮説（レーティング差は elo0）を採る。
+    AcceptH0,
+    /// 対立仮説（レーティング差は elo1）を採る。
+    AcceptH1,
+    /// まだ決まらない。
+    Continue,
+}
+
+/// 逐次確率比検定の設定。
+///
+/// # Members.
+///
+/// * `elo0` - 帰無仮説のレーティング差。
+/// * `elo1` - 対立仮説のレーティング差。
+/// * `alpha` - 第1種の誤りの確率。
+/// * `beta` - 第2種の誤りの確率。
+#[derive(Copy,Clone,Debug)]
+pub struct Sprt{
+    pub elo0 : f64,
+    pub elo1 : f64,
+    pub alpha : f64,
+    pub beta : f64,
+}
+impl Sprt{
+    pub fn new(elo0:f64, elo1:f64, alpha:f64, beta:f64) -> Sprt {
+        Sprt{ elo0, elo1, alpha, beta }
+    }
+
+    /// 対数尤度比の下限と上限。下限を割れば H0、上限を超えれば H1。
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    /// 対数尤度比。正規近似の一般化 SPRT。ペアは五項分布、ペアにならなかった局は三項分布で見て合わせる。
+    pub fn llr(&self, stats:&MatchStats) -> f64 {
+        let (mean, variance) = stats.estimate();
+        if variance==0.0 { return 0.0; }
+        let s0 = score_from_elo(self.elo0);
+        let s1 = score_from_elo(self.elo1);
+        (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn decision(&self, stats:&MatchStats) -> SprtDecision {
+        let llr = self.llr(stats);
+        let (lower, upper) = self.bounds();
+        if llr<=lower {
+            SprtDecision::AcceptH0
+        } else if upper<=llr {
+            SprtDecision::AcceptH1
+        } else {
+            SprtDecision::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a:f64, b:f64) -> bool {
+        (a - b).abs()<1e-6
+    }
+
+    #[test]
+    fn mixes_pairs_and_unpaired_games() {
+        use self::GameResult::*;
+        let mut stats = MatchStats::new();
+        // ペアの合計得点が 0, 0.5, 0.5, 1, 1, 1, 1.5, 1.5, 1.5, 2 の 10 ペア。
+        for &(first, second, n) in [(Loss, Loss, 1), (Draw, Loss, 2), (Win, Loss, 3), (Win, Draw, 3), (Win, Win, 1)].iter() {
+            for _ in 0..n { stats.add_pair(first, second); }
+        }
+        // ペアにならなかった 3 局。
+        stats.add_game(Win);
+        stats.add_game(Win);
+        stats.add_game(Draw);
+
+        assert_eq!(stats.games(), 23);
+        assert_eq!(stats.pairs(), 10);
+        assert_eq!(stats.unpaired(), 3);
+        // 平均は 13 / 23。分散は (4 * 10 * ペアの分散 + 3 * 1局の分散) / 23^2。各分散には1標本分の仮の数が入る。
+        let (mean, variance) = stats.estimate();
+        assert!(close(mean, 13.0 / 23.0));
+        assert!(close(variance, 0.007563389215715894));
+        assert!(close(stats.elo(0.95).elo, 45.57734092273467));
+        assert!(close(Sprt::new(0.0, 10.0, 0.05, 0.05).llr(&stats), 0.11037367793711468));
+        assert!(close(stats.los(), 0.7733435598076672));
+    }
+
+    #[test]
+    fn unpaired_games_change_the_estimate() {
+        let mut paired = MatchStats::new();
+        paired.add_pair(GameResult::Win, GameResult::Loss);
+        paired.add_pair(GameResult::Win, GameResult::Draw);
+        let mut mixed = paired.clone();
+        mixed.add_game(GameResult::Win);
+        assert!(close(paired.estimate().0, 2.5 / 4.0));
+        assert!(close(mixed.estimate().0, 3.5 / 5.0));
+        assert!(paired.elo(0.95).elo<mixed.elo(0.95).elo);
+    }
+
+    #[test]
+    fn bounds() {
+        let (lower, upper) = Sprt::new(0.0, 5.0, 0.05, 0.05).bounds();
+        assert!(close(lower, (0.05f64 / 0.95).ln()));
+        assert!(close(upper, 19f64.ln()));
+        assert_eq!(Sprt::new(0.0, 5.0, 0.05, 0.05).decision(&MatchStats::new()), SprtDecision::Continue);
+    }
+
+    /// 同じ結果のペアを足し続けて、結論が出たペアの数。LLR は足すたびに決まった向きへ動く。
+    fn pairs_until_decided(sprt:&Sprt, first:GameResult, second:GameResult) -> (u64, SprtDecision) {
+        let mut stats = MatchStats::new();
+        let mut last = sprt.llr(&stats);
+        for pairs in 1..1000 {
+            stats.add_pair(first, second);
+            let llr = sprt.llr(&stats);
+            assert!(llr!=last, "{} pairs", pairs);
+            last = llr;
+            let decision = sprt.decision(&stats);
+            if decision!=SprtDecision::Continue { return (pairs, decision); }
+        }
+        panic!("no decision");
+    }
+
+    #[test]
+    fn all_wins_accept_h1() {
+        use self::GameResult::*;
+        let sprt = Sprt::new(0.0, 5.0, 0.05, 0.05);
+        let (pairs, decision) = pairs_until_decided(&sprt, Win, Win);
+        assert_eq!(decision, SprtDecision::AcceptH1);
+        assert!(1<pairs && pairs<100, "{}", pairs);
+        // ペアにならない局だけでも止まる。
+        let mut stats = MatchStats::new();
+        for _ in 0..100 { stats.add_game(Win); }
+        assert_eq!(sprt.decision(&stats), SprtDecision::AcceptH1);
+    }
+
+    #[test]
+    fn all_losses_accept_h0() {
+        use self::GameResult::*;
+        let sprt = Sprt::new(0.0, 5.0, 0.05, 0.05);
+        let (pairs, decision) = pairs_until_decided(&sprt, Loss, Loss);
+        assert_eq!(decision, SprtDecision::AcceptH0);
+        assert!(pairs<100, "{}", pairs);
+        let mut stats = MatchStats::new();
+        for _ in 0..100 { stats.add_game(Loss); }
+        assert_eq!(sprt.decision(&stats), SprtDecision::AcceptH0);
+    }
+
+    #[test]
+    fn llr_crosses_the_bounds_with_the_sample_size() {
+        use self::GameResult::*;
+        let sprt = Sprt::new(0.0, 10.0, 0.05, 0.05);
+        let (lower, upper) = sprt.bounds();
+        // 勝ち越しでも、数が少なければ続ける。
+        let mut stats = MatchStats::new();
+        stats.add_pair(Win, Draw);
+        stats.add_pair(Win, Loss);
+        assert!(lower<sprt.llr(&stats) && sprt.llr(&stats)<upper);
+        assert_eq!(sprt.decision(&stats), SprtDecision::Continue);
+        let (_, decision) = pairs_until_decided(&sprt, Win, Draw);
+        assert_eq!(decision, SprtDecision::AcceptH1);
+        let (_, decision) = pairs_until_decided(&sprt, Draw, Loss);
+        assert_eq!(decision, SprtDecision::AcceptH0);
+    }
+}
@@ -0,0 +1,284 @@
+//! SVG の局面図☆（＾～＾） 画像のライブラリを使わずに、文字列で組み立てる。
+//!
+//! 左に後手の持ち駒、真ん中に盤、右に先手の持ち駒を並べる。後手の駒は 180 度回して描く。
+//! 升の大きさを `c` とすると、図の大きさは横 13.5c、縦 10.5c。
+//!
+//! ```text
+//! ☖         ９ ８ ７ ６ ５ ４ ３ ２ １
+//! 飛     +---------------------------+
+//! 歩二   |                           | 一
+//!        |           盤              | …       ☗
+//!        |                           | 九      角
+//!        +---------------------------+         金
+//! ```
+use diagram::{bod_piece_name, count_to_kanji};
+use hand::Hand;
+use position::Position;
+use record::{num_to_kanji, num_to_zenkaku};
+use {FILE0, FILE10, RANK1, RANK10};
+use {Color, PieceType, UsiMovement};
+
+/// 局面図の描き方。
+///
+/// # Members.
+///
+/// * `cell_size` - 1升の幅と高さ（ピクセル）。
+/// * `last_move` - 直前の指し手。移動元と移動先の升に色を塗る。
+/// * `arrows` - 候補手。移動元から移動先へ矢印を引く。打つ手は持ち駒から引く。
+#[derive(Clone)]
+pub struct SvgOptions{
+    pub cell_size : u32,
+    pub last_move : Option<UsiMovement>,
+    pub arrows : Vec<UsiMovement>,
+}
+impl Default for SvgOptions{
+    fn default() -> SvgOptions {
+        SvgOptions{
+            cell_size : 40,
+            last_move : None,
+            arrows : Vec::new(),
+        }
+    }
+}
+
+/// 図の中の位置と大きさ。升の大きさから決まる。
+struct Layout{
+    c : u32,
+    /// 盤の左上。
+    board_x : u32,
+    board_y : u32,
+    /// 持ち駒の列の左端。先手、後手の順。
+    hand_x : [u32; 2],
+    width : u32,
+    height : u32,
+}
+impl Layout{
+    fn new(c:u32) -> Layout {
+        let m = c / 2;
+        let board_x = m + c + m;
+        let board_y = c;
+        let sente_x = board_x + 9 * c + c;
+        Layout{
+            c,
+            board_x,
+            board_y,
+            hand_x : [sente_x, m],
+            width : sente_x + c + m,
+            height : board_y + 9 * c + m,
+        }
+    }
+
+    /// 升の中心。
+    fn center(&self, file:i8, rank:i8) -> (u32, u32) {
+        (self.board_x + (9 - file as u32) * self.c + self.c / 2, self.board_y + (rank as u32 - 1) * self.c + self.c / 2)
+    }
+
+    /// 持ち駒の列の `i` 番目の中心。0 番目は ☗☖ の印。後手は上から、先手は下から詰める。
+    fn hand_center(&self, color:Color, i:usize, len:usize) -> (u32, u32) {
+        let x = self.hand_x[color.index()] + self.c / 2;
+        let row = match color {
+            Color::Gote => i,
+            Color::Sente => 9 - len + i,
+        };
+        (x, self.board_y + row as u32 * self.c + self.c / 2)
+    }
+}
+
+/// 持ち駒の列に書く文字。先頭は ☗☖ の印、続けて `歩十` のように駒と枚数。
+fn hand_labels(hand:Hand, color:Color) -> Vec<String> {
+    let mut labels = vec![if color==Color::Sente { "☗".to_string() } else { "☖".to_string() }];
+    for (pt, count) in hand.iter() {
+        let mut label = bod_piece_name(pt).to_string();
+        if 1<count { label.push_str(&count_to_kanji(count)); }
+        labels.push(label);
+    }
+    labels
+}
+
+/// 局面図の SVG。
+pub fn render_svg(position:&Position, options:&SvgOptions) -> String {
+    let layout = Layout::new(options.cell_size.max(8));
+    let c = layout.c;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" font-family=\"serif\">\n",
+        layout.width, layout.height);
+    svg.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"#ffffff\"/>\n", layout.width, layout.height));
+    svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#f3d9a4\"/>\n",
+        layout.board_x, layout.board_y, 9 * c, 9 * c));
+
+    // 直前の指し手の升。
+    if let Some(movement) = options.last_move {
+        let mut cells = vec![(movement.destination_file, movement.destination_rank)];
+        if movement.drop==PieceType::Space { cells.push((movement.source_file, movement.source_rank)); }
+        for (file, rank) in cells {
+            let (x, y) = layout.center(file, rank);
+            svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#f7a35c\" fill-opacity=\"0.6\"/>\n",
+                x - c / 2, y - c / 2, c, c));
+        }
+    }
+
+    // 罫線と星。
+    for i in 0..10 {
+        let (x, y) = (layout.board_x + i * c, layout.board_y + i * c);
+        let stroke = if i==0 || i==9 { 2 } else { 1 };
+        svg.push_str(&format!("<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\" stroke=\"#000000\" stroke-width=\"{3}\"/>\n",
+            x, layout.board_y, layout.board_y + 9 * c, stroke));
+        svg.push_str(&format!("<line x1=\"{1}\" y1=\"{0}\" x2=\"{2}\" y2=\"{0}\" stroke=\"#000000\" stroke-width=\"{3}\"/>\n",
+            y, layout.board_x, layout.board_x + 9 * c, stroke));
+    }
+    for &(i, j) in [(3, 3), (6, 3), (3, 6), (6, 6)].iter() {
+        svg.push_str(&format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"#000000\"/>\n",
+            layout.board_x + i * c, layout.board_y + j * c, (c / 16).max(1)));
+    }
+
+    // 筋と段の番号。
+    let label_size = c * 2 / 5;
+    for file in (FILE0+1..FILE10).rev() {
+        let (x, _) = layout.center(file, 1);
+        svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\">{}</text>\n",
+            x, layout.board_y - c / 8, label_size, num_to_zenkaku(file)));
+    }
+    for rank in RANK1..RANK10 {
+        let (_, y) = layout.center(1, rank);
+        svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+            layout.board_x + 9 * c + c / 4, y, label_size, num_to_kanji(rank)));
+    }
+
+    // 盤上の駒。成り駒は赤。
+    let piece_size = c * 3 / 4;
+    for file in (FILE0+1..FILE10).rev() {
+        for rank in RANK1..RANK10 {
+            let pc = position.piece(file, rank);
+            if pc.is_space() { continue; }
+            let (x, y) = layout.center(file, rank);
+            let pt = pc.piece_type();
+            let fill = if pt.is_promoted() { "#c00000" } else { "#000000" };
+            let rotate = if pc.color()==Color::Gote { format!(" transform=\"rotate(180 {} {})\"", x, y) } else { String::new() };
+            svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-size=\"{}\" fill=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\"{}>{}</text>\n",
+                x, y, piece_size, fill, rotate, bod_piece_name(pt)));
+        }
+    }
+
+    // 持ち駒。
+    for color in Color::all() {
+        let labels = hand_labels(position.hand[color.index()], color);
+        for (i, label) in labels.iter().enumerate() {
+            let (x, y) = layout.hand_center(color, i, labels.len());
+            // 枚数付きは、升の幅に収まるように小さく。
+            let len = label.chars().count() as u32;
+            let size = if len<=1 { piece_size } else { c * 9 / 10 / len };
+            svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+                x, y, size, label));
+        }
+    }
+
+    // 候補手の矢印。
+    if !options.arrows.is_empty() {
+        svg.push_str("<defs><marker id=\"arrowhead\" viewBox=\"0 0 10 10\" refX=\"5\" refY=\"5\" markerWidth=\"3\" markerHeight=\"3\" orient=\"auto\">\
+            <path d=\"M0,0 L10,5 L0,10 z\" fill=\"#1f5fbf\"/></marker></defs>\n");
+    }
+    for movement in options.arrows.iter() {
+        let from = if movement.drop==PieceType::Space {
+            Some(layout.center(movement.source_file, movement.source_rank))
+        } else {
+            let hand = position.hand[position.turn.index()];
+            let len = hand.iter().count() + 1;
+            hand.iter().position(|x| x.0==movement.drop).map(|i| layout.hand_center(position.turn, i + 1, len))
+        };
+        let to = layout.center(movement.destination_file, movement.destination_rank);
+        match from {
+            Some(from) => {
+                svg.push_str(&format!("<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#1f5fbf\" stroke-opacity=\"0.7\" stroke-width=\"{}\" marker-end=\"url(#arrowhead)\"/>\n",
+                    from.0, from.1, to.0, to.1, (c / 8).max(1)));
+            },
+            // 持っていない駒を打つ手は、移動先を丸で囲むだけ。
+            None => {
+                svg.push_str(&format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"none\" stroke=\"#1f5fbf\" stroke-width=\"{}\"/>\n",
+                    to.0, to.1, c * 2 / 5, (c / 8).max(1)));
+            },
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine_output::parse_movement_token;
+
+    fn movement(text:&str) -> UsiMovement {
+        parse_movement_token(text).unwrap()
+    }
+
+    /// `needle` を含む行。
+    fn lines_with<'a>(svg:&'a str, needle:&str) -> Vec<&'a str> {
+        svg.lines().filter(|x| x.contains(needle)).collect()
+    }
+
+    #[test]
+    fn well_formed() {
+        let svg = render_svg(&Position::startpos(), &SvgOptions::default());
+        // 升 40 なら横 13.5 升、縦 10.5 升。
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"540\" height=\"420\" viewBox=\"0 0 540 420\""), "{}", svg);
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<svg").count(), 1);
+        assert_eq!(svg.matches("<text").count(), svg.matches("</text>").count());
+        for line in svg.lines().skip(1) {
+            assert!(line.starts_with('<') && line.ends_with('>'), "{}", line);
+            assert!(line.starts_with("</") || line.ends_with("/>") || line.ends_with("</text>") || line.ends_with("</defs>"), "{}", line);
+        }
+        // 盤上の駒 40 枚と、持ち駒の ☗☖。
+        assert_eq!(lines_with(&svg, "font-size=\"30\"").len(), 40 + 2);
+    }
+
+    #[test]
+    fn gote_pieces_are_rotated() {
+        let svg = render_svg(&Position::startpos(), &SvgOptions::default());
+        assert_eq!(svg.matches("rotate(180 ").count(), 20);
+        // ５一の後手の玉は升の中心で回し、５九の先手の玉は回さない。
+        let gote_king = lines_with(&svg, "x=\"260\" y=\"60\"");
+        assert_eq!(gote_king.len(), 1);
+        assert!(gote_king[0].contains("transform=\"rotate(180 260 60)\">玉<"), "{}", gote_king[0]);
+        let sente_king = lines_with(&svg, "x=\"260\" y=\"380\"");
+        assert_eq!(sente_king.len(), 1);
+        assert!(!sente_king[0].contains("rotate") && sente_king[0].ends_with(">玉</text>"), "{}", sente_king[0]);
+    }
+
+    #[test]
+    fn hand_counts() {
+        let svg = render_svg(&Position::from_sfen("4k4/9/9/9/9/9/9/9/4K4 b R10P2g 1"), &SvgOptions::default());
+        for label in [">☗<", ">飛<", ">歩十<", ">☖<", ">金二<"].iter() {
+            assert_eq!(svg.matches(label).count(), 1, "{}", label);
+        }
+        // 持ち駒が無ければ印だけ。
+        let svg = render_svg(&Position::from_sfen("4k4/9/9/9/9/9/9/9/4K4 b - 1"), &SvgOptions::default());
+        assert!(svg.contains(">☗<") && svg.contains(">☖<"));
+        assert!(!svg.contains(">歩"));
+    }
+
+    #[test]
+    fn last_move_only_when_asked() {
+        let position = Position::startpos();
+        assert!(!render_svg(&position, &SvgOptions::default()).contains("#f7a35c"));
+        // 盤上の手は移動元と移動先、打つ手は移動先だけ。
+        let svg = render_svg(&position, &SvgOptions{ last_move : Some(movement("7g7f")), ..SvgOptions::default() });
+        assert_eq!(svg.matches("#f7a35c").count(), 2);
+        let svg = render_svg(&position, &SvgOptions{ last_move : Some(movement("P*5e")), ..SvgOptions::default() });
+        assert_eq!(svg.matches("#f7a35c").count(), 1);
+    }
+
+    #[test]
+    fn arrows_only_when_asked() {
+        let position = Position::from_sfen("4k4/9/9/9/9/9/9/9/4K4 b G 1");
+        let svg = render_svg(&position, &SvgOptions::default());
+        assert!(!svg.contains("arrowhead") && !svg.contains("#1f5fbf"));
+        let options = SvgOptions{ arrows : vec![movement("5i4h"), movement("G*5b"), movement("S*5c")], ..SvgOptions::default() };
+        let svg = render_svg(&position, &options);
+        assert_eq!(svg.matches("<marker id=\"arrowhead\"").count(), 1);
+        // 盤上の手と持っている駒を打つ手は矢印。持っていない駒を打つ手は丸。
+        assert_eq!(svg.matches("marker-end=\"url(#arrowhead)\"").count(), 2);
+        assert_eq!(lines_with(&svg, "<circle").iter().filter(|x| x.contains("#1f5fbf")).count(), 1);
+    }
+}
@@ -0,0 +1,183 @@
+//! 思考時間の割り振り☆（＾～＾）
+//!
+//! `go` の持ち時間から、この1手の目安（optimum）と上限（maximum）を決める。
+//! 目安を過ぎたら次の深さを始めず、上限を過ぎたら探索を打ち切る。
+//!
+//! * 切れ負け - 残りの手数で割った分だけ使い、上限も残り時間の一部に抑える。
+//! * 秒読み - 使わなければ消える秒読みは、目安の時点で使い切るつもりで割り振る。
+//! * 加算 - 加算の分は毎手使ってよい。
+use std::time::Duration;
+
+use {Color, GoParams};
+
+/// 対局が終わるまでの手数の見込み。両方の手を数える。
+const MOVE_HORIZON : u32 = 160;
+/// 残り手数の見込みの下限。自分の手を数える。終盤でも残り時間を一度に使わない。
+const MIN_MOVES_LEFT : u64 = 16;
+/// 上限は目安の何倍まで。
+const MAXIMUM_RATIO : u64 = 4;
+/// 切れ負けと加算で、1手に使ってよい残り時間の割合の分母。
+const SUDDEN_DEATH_DIVISOR : u64 = 5;
+/// 最善手が1回変わるごとに、目安を何割延ばすか。
+const INSTABILITY_WEIGHT : f64 = 0.5;
+
+/// エンジンのオプションで変える値。ミリ秒。
+///
+/// # Members.
+///
+/// * `network_delay` - 通信の遅れ。使える時間から引く。`NetworkDelay` オプション。
+/// * `minimum_thinking_time` - これより短くは考えない。`MinimumThinkingTime` オプション。
+/// * `slow_mover` - 目安を何パーセントにするか。100 で標準。`SlowMover` オプション。
+#[derive(Copy,Clone,Debug)]
+pub struct TimeOptions{
+    pub network_delay : u64,
+    pub minimum_thinking_time : u64,
+    pub slow_mover : u64,
+}
+impl Default for TimeOptions{
+    fn default() -> TimeOptions {
+        TimeOptions{
+            network_delay : 120,
+            minimum_thinking_time : 100,
+            slow_mover : 100,
+        }
+    }
+}
+
+/// この1手に使う時間。
+///
+/// # Members.
+///
+/// * `optimum` - 目安。これを過ぎたら次の深さを始めない。
+/// * `maximum` - 上限。これを過ぎたら探索を打ち切る。
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct TimeBudget{
+    pub optimum : Duration,
+    pub maximum : Duration,
+}
+impl TimeBudget{
+    /// 最善手が安定しないときに延ばした目安。`changes` は最近の深さで最善手が変わった回数。上限は超えない。
+    pub fn optimum_with_instability(&self, changes:f64) -> Duration {
+        self.optimum.mul_f64(1.0 + INSTABILITY_WEIGHT * changes.max(0.0)).min(self.maximum)
+    }
+}
+
+/// 手番 `turn` の側の持ち時間から、この1手の目安と上限を決める。`ply` は sfen の手数。
+///
+/// `go ponder` でも同じに決めておき、`ponderhit` から数える。
+pub fn allot(params:&GoParams, turn:Color, ply:u32, options:&TimeOptions) -> TimeBudget {
+    let (time, inc) = if turn==Color::Sente { (params.btime, params.binc) } else { (params.wtime, params.winc) };
+    let byoyomi = params.byoyomi;
+    // 秒読みならこれを超えると負ける。切れ負けと加算は、残り時間の一部までしか使わない。
+    // 加算は指した後に足されることがあるので、残り時間は超えない。
+    let hard = if 0<byoyomi { time + inc + byoyomi } else { (time / SUDDEN_DEATH_DIVISOR + inc).min(time) };
+    let hard = hard.saturating_sub(options.network_delay);
+
+    let moves_left = (u64::from(MOVE_HORIZON.saturating_sub(ply)) / 2).max(MIN_MOVES_LEFT);
+    let share = time / moves_left * options.slow_mover / 100;
+    let (optimum, maximum) = if 0<byoyomi {
+        // 秒読みは残しても次の手に持ち越せないので、目安に丸ごと入れる。
+        let optimum = share + inc + byoyomi;
+        (optimum, optimum + share * (MAXIMUM_RATIO - 1))
+    } else {
+        let optimum = share + inc;
+        (optimum, optimum * MAXIMUM_RATIO)
+    };
+
+    let optimum = optimum.saturating_sub(options.network_delay).max(options.minimum_thinking_time).min(hard);
+    let maximum = maximum.saturating_sub(options.network_delay).max(optimum).min(hard);
+    TimeBudget{
+        optimum : Duration::from_millis(optimum),
+        maximum : Duration::from_millis(maximum),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(budget:&TimeBudget) -> (u64, u64) {
+        (budget.optimum.as_millis() as u64, budget.maximum.as_millis() as u64)
+    }
+
+    fn sente(btime:u64, byoyomi:u64, binc:u64) -> GoParams {
+        GoParams{ btime, byoyomi, binc, ..GoParams::default() }
+    }
+
+    #[test]
+    fn byoyomi_only() {
+        // 持ち時間が無ければ、秒読みから通信の遅れを引いた分を目安にも上限にも使う。
+        let budget = allot(&sente(0, 10_000, 0), Color::Sente, 1, &TimeOptions::default());
+        assert_eq!(millis(&budget), (9_880, 9_880));
+    }
+
+    #[test]
+    fn byoyomi_with_time() {
+        // 残り手数の見込みは (160 - 1) / 2 = 79 手。1手の分は 60000 / 79 = 759。
+        let budget = allot(&sente(60_000, 10_000, 0), Color::Sente, 1, &TimeOptions::default());
+        assert_eq!(millis(&budget), (759 + 10_000 - 120, 759 * 4 + 10_000 - 120));
+    }
+
+    #[test]
+    fn sudden_death() {
+        let budget = allot(&sente(600_000, 0, 0), Color::Sente, 1, &TimeOptions::default());
+        assert_eq!(millis(&budget), (7_594 - 120, 7_594 * 4 - 120));
+        // 残り時間が少なければ、上限は残り時間の 1/5 に抑える。最低思考時間よりも優先する。
+        let budget = allot(&sente(1_000, 0, 0), Color::Sente, 150, &TimeOptions::default());
+        assert_eq!(millis(&budget), (80, 80));
+    }
+
+    #[test]
+    fn increment() {
+        let budget = allot(&sente(10_000, 0, 5_000), Color::Sente, 1, &TimeOptions::default());
+        assert_eq!(millis(&budget), (126 + 5_000 - 120, 10_000 / 5 + 5_000 - 120));
+        // 加算が残り時間より多くても、残り時間は超えない。
+        let budget = allot(&sente(1_000, 0, 5_000), Color::Sente, 1, &TimeOptions::default());
+        assert_eq!(millis(&budget), (880, 880));
+    }
+
+    #[test]
+    fn uses_the_side_to_move() {
+        let params = GoParams{ btime : 1_000, wtime : 600_000, binc : 0, winc : 1_000, ..GoParams::default() };
+        let gote = allot(&params, Color::Gote, 2, &TimeOptions::default());
+        assert_eq!(millis(&gote), (7_594 + 1_000 - 120, (7_594 + 1_000) * 4 - 120));
+        assert!(allot(&params, Color::Sente, 1, &TimeOptions::default()).maximum<gote.optimum);
+    }
+
+    #[test]
+    fn options_are_applied() {
+        let params = sente(60_000, 0, 0);
+        let base = millis(&allot(&params, Color::Sente, 1, &TimeOptions{ network_delay : 0, minimum_thinking_time : 0, slow_mover : 100 }));
+        assert_eq!(base, (759, 759 * 4));
+        let delayed = millis(&allot(&params, Color::Sente, 1, &TimeOptions{ network_delay : 500, minimum_thinking_time : 0, slow_mover : 100 }));
+        assert_eq!(delayed, (759 - 500, 759 * 4 - 500));
+        let minimum = millis(&allot(&params, Color::Sente, 1, &TimeOptions{ network_delay : 0, minimum_thinking_time : 3_000, slow_mover : 100 }));
+        assert_eq!(minimum, (3_000, 3_036));
+        let slow = millis(&allot(&params, Color::Sente, 1, &TimeOptions{ network_delay : 0, minimum_thinking_time : 0, slow_mover : 200 }));
+        assert_eq!(slow, (1_518, 1_518 * 4));
+    }
+
+    #[test]
+    fn infinite_has_no_clock() {
+        // `go infinite` は持ち時間を送らない。時間は数えないので、割り振りは 0。
+        let budget = allot(&GoParams{ infinite : true, ..GoParams::default() }, Color::Sente, 1, &TimeOptions::default());
+        assert_eq!(millis(&budget), (0, 0));
+    }
+
+    #[test]
+    fn never_exceeds_the_remaining_time() {
+        let options = TimeOptions::default();
+        for &time in [0, 500, 3_000, 60_000, 600_000, 3_600_000].iter() {
+            for &(byoyomi, inc) in [(0, 0), (1_000, 0), (10_000, 0), (0, 1_000), (0, 10_000)].iter() {
+                for &ply in [1, 80, 159, 300].iter() {
+                    let budget = allot(&sente(time, byoyomi, inc), Color::Sente, ply, &options);
+                    let (optimum, maximum) = millis(&budget);
+                    let remaining = if 0<byoyomi { time + byoyomi } else { time };
+                    assert!(optimum<=maximum, "{} {} {} {}", time, byoyomi, inc, ply);
+                    assert!(maximum<=remaining.saturating_sub(options.network_delay), "{} {} {} {}", time, byoyomi, inc, ply);
+                    assert!(budget.optimum_with_instability(3.0)<=budget.maximum);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! 置換表☆（＾～＾）
+//!
+//! 局面のハッシュから、前に読んだ指し手、評価値、深さ、境界を引く。
+//! 4項目を1つのクラスタにまとめ、クラスタはキャッシュの1行に収める。
+//!
+//! 項目は 64bit の原子変数2つ。片方にはハッシュと中身の排他的論理和を入れておき、
+//! 引くときに中身と合わせてハッシュに戻らなければ無かったことにする。
+//! これでロックを取らずに、いくつもの探索スレッドから同時に読み書きできる。
+//! 書き込みが重なって壊れた項目は、外れとして捨てられるだけ。
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use compact_move::Move16;
+use engine_output::ScoreBound;
+use search::is_mate_score;
+use UsiMovement;
+
+/// 1クラスタの項目数。
+const CLUSTER_SIZE : usize = 4;
+/// 世代は 6bit で回る。
+const GENERATION_CYCLE : u8 = 64;
+/// 古い世代の項目は、1世代につき深さ何手分だけ安く見て上書きする。
+const AGE_WEIGHT : i32 = 8;
+/// `hashfull` を数えるときに見るクラスタの数。4項目ずつなので 1000 項目。
+const HASHFULL_SAMPLE : usize = 250;
+
+/// 引いた項目。
+///
+/// # Members.
+///
+/// * `movement` - 最善手か、βカットした手。無いこともある。
+/// * `score` - 評価値。詰みはこの局面からの手数なので、`score_from_table` で直して使う。
+/// * `depth` - 残りの深さ。255 で頭打ち。
+/// * `bound` - `score` が真の値か、下限か、上限か。
+/// * `generation` - 書き込んだときの世代。
+#[derive(Copy,Clone,Debug)]
+pub struct TableEntry{
+    pub movement : Option<UsiMovement>,
+    pub score : i32,
+    pub depth : u32,
+    pub bound : ScoreBound,
+    pub generation : u8,
+}
+
+/// ルートから `ply` 手目の局面の評価値を、書き込む値にする。
+/// 詰みの評価値はルートからの手数で数えているので、その局面からの手数に直す。
+pub fn score_to_table(score:i32, ply:u32) -> i32 {
+    if !is_mate_score(score) { score } else if 0<score { score + ply as i32 } else { score - ply as i32 }
+}
+
+/// `score_to_table` の逆。
+pub fn score_from_table(score:i32, ply:u32) -> i32 {
+    if !is_mate_score(score) { score } else if 0<score { score - ply as i32 } else { score + ply as i32 }
+}
+
+/// 指し手を `Move16` に詰める。手が無ければ `Move16::NONE`。
+fn encode_move(movement:&Option<UsiMovement>) -> u16 {
+    movement.as_ref().map_or(Move16::NONE, Move16::from_usi_movement).0
+}
+
+/// `encode_move` の逆。
+fn decode_move(bits:u16) -> Option<UsiMovement> {
+    Move16(bits).to_usi_movement()
+}
+
+fn encode_bound(bound:ScoreBound) -> u64 {
+    match bound {
+        ScoreBound::Exact => 0,
+        ScoreBound::Lower => 1,
+        ScoreBound::Upper => 2,
+    }
+}
+
+fn decode_bound(bits:u64) -> ScoreBound {
+    match bits {
+        1 => ScoreBound::Lower,
+        2 => ScoreBound::Upper,
+        _ => ScoreBound::Exact,
+    }
+}
+
+/// 中身の 64bit。下位から `Move16` の指し手 16bit、評価値 16bit、深さ+1 を 8bit、境界 2bit、世代 6bit。
+/// 深さの欄が 0 なら空き。
+fn pack(movement:u16, score:i32, depth:u32, bound:ScoreBound, generation:u8) -> u64 {
+    u64::from(movement)
+        | u64::from(score as i16 as u16) << 16
+        | u64::from(depth.min(254) + 1) << 32
+        | encode_bound(bound) << 40
+        | u64::from(generation) << 42
+}
+
+fn depth_of(data:u64) -> u32 {
+    (data >> 32 & 0xff) as u32
+}
+
+fn generation_of(data:u64) -> u8 {
+    (data >> 42 & 0x3f) as u8
+}
+
+fn unpack(data:u64) -> TableEntry {
+    TableEntry{
+        movement : decode_move(data as u16),
+        score : i32::from((data >> 16) as u16 as i16),
+        depth : depth_of(data) - 1,
+        bound : decode_bound(data >> 40 & 0x3),
+        generation : generation_of(data),
+    }
+}
+
+#[derive(Default)]
+struct Slot{
+    /// ハッシュと `data` の排他的論理和。
+    check : AtomicU64,
+    data : AtomicU64,
+}
+impl Slot{
+    /// `key` の項目なら中身を返す。
+    fn load(&self, key:u64) -> Option<u64> {
+        let data = self.data.load(Ordering::Relaxed);
+        let check = self.check.load(Ordering::Relaxed);
+        if depth_of(data)!=0 && check ^ data==key { Some(data) } else { None }
+    }
+
+    fn store(&self, key:u64, data:u64) {
+        self.check.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.check.store(0, Ordering::Relaxed);
+        self.data.store(0, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+#[repr(align(64))]
+struct Cluster{
+    slots : [Slot; CLUSTER_SIZE],
+}
+
+/// 置換表。`Arc` に入れて探索スレッドで分け合う。
+pub struct TranspositionTable{
+    clusters : Vec<Cluster>,
+    generation : AtomicU8,
+}
+impl TranspositionTable{
+    /// `megabytes` の大きさで作る。
+    pub fn new(megabytes:usize) -> TranspositionTable {
+        let mut table = TranspositionTable{
+            clusters : Vec::new(),
+            generation : AtomicU8::new(0),
+        };
+        table.resize(megabytes);
+        table
+    }
+
+    /// `USI_Hash` に合わせて `megabytes` の大きさで作り直す。中身は消える。
+    pub fn resize(&mut self, megabytes:usize) {
+        let len = (megabytes * 1024 * 1024 / mem::size_of::<Cluster>()).max(1);
+        self.clusters = Vec::new();
+        self.clusters.resize_with(len, Cluster::default);
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// 大きさ。メガバイト。
+    pub fn megabytes(&self) -> usize {
+        self.clusters.len() * mem::size_of::<Cluster>() / (1024 * 1024)
+    }
+
+    /// 全部消す。`usinewgame` で呼ぶ。
+    pub fn clear(&self) {
+        for cluster in self.clusters.iter() {
+            for slot in cluster.slots.iter() { slot.clear(); }
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// 考え始めるたびに呼ぶ。世代を進め、前の思考の項目を上書きされやすくする。
+    pub fn new_search(&self) {
+        let next = (self.generation.load(Ordering::Relaxed) + 1) % GENERATION_CYCLE;
+        self.generation.store(next, Ordering::Relaxed);
+    }
+
+    /// 今の世代。
+    pub fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    fn cluster(&self, key:u64) -> &Cluster {
+        // 上位の桁で表の長さに割り付ける。
+        let index = ((u128::from(key) * self.clusters.len() as u128) >> 64) as usize;
+        &self.clusters[index]
+    }
+
+    /// `key` の局面を引く。
+    pub fn probe(&self, key:u64) -> Option<TableEntry> {
+        self.cluster(key).slots.iter().find_map(|x| x.load(key)).map(unpack)
+    }
+
+    /// `key` の局面を書き込む。詰みの評価値は先に `score_to_table` で直しておく。
+    ///
+    /// 同じ局面の項目があればそこに、無ければクラスタの中で一番浅くて古い項目に書く。
+    /// 同じ局面で指し手が無ければ、前の指し手を残す。
+    pub fn save(&self, key:u64, movement:Option<UsiMovement>, score:i32, depth:u32, bound:ScoreBound) {
+        let generation = self.generation();
+        let cluster = self.cluster(key);
+        let mut movement = encode_move(&movement);
+        let slot = match cluster.slots.iter().find(|x| x.load(key).is_some()) {
+            Some(slot) => {
+                if movement==Move16::NONE.0 {
+                    if let Some(data) = slot.load(key) { movement = data as u16; }
+                }
+                slot
+            },
+            None => {
+                cluster.slots.iter().min_by_key(|x| {
+                    let data = x.data.load(Ordering::Relaxed);
+                    if depth_of(data)==0 { return i32::MIN; }
+                    let age = (GENERATION_CYCLE + generation - generation_of(data)) % GENERATION_CYCLE;
+                    depth_of(data) as i32 - AGE_WEIGHT * age as i32
+                }).unwrap()
+            },
+        };
+        slot.store(key, pack(movement, score, depth, bound, generation));
+    }
+
+    /// 今の世代で埋まっている割合。千分率。`info hashfull` に使う。
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation();
+        let sample = &self.clusters[..self.clusters.len().min(HASHFULL_SAMPLE)];
+        let used = sample.iter()
+            .flat_map(|x| x.slots.iter())
+            .filter(|x| {
+                let data = x.data.load(Ordering::Relaxed);
+                depth_of(data)!=0 && generation_of(data)==generation
+            })
+            .count();
+        (used * 1000 / (sample.len() * CLUSTER_SIZE)) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine_output::parse_movement_token;
+    use search::MATE;
+
+    fn movement(text:&str) -> Option<UsiMovement> {
+        parse_movement_token(text)
+    }
+
+    /// 引いた指し手の USI 表記。
+    fn probed_move(table:&TranspositionTable, key:u64) -> Option<String> {
+        table.probe(key).and_then(|x| x.movement).map(|x| x.to_string())
+    }
+
+    /// クラスタが1つだけの表。どの局面も同じクラスタに入る。
+    fn one_cluster() -> TranspositionTable {
+        let table = TranspositionTable::new(0);
+        assert_eq!(table.clusters.len(), 1);
+        table
+    }
+
+    #[test]
+    fn save_and_probe() {
+        let table = TranspositionTable::new(1);
+        table.new_search();
+        table.save(0x1234_5678_9abc_def0, movement("7g7f"), -321, 12, ScoreBound::Upper);
+        let entry = table.probe(0x1234_5678_9abc_def0).unwrap();
+        assert_eq!(entry.movement.map(|x| x.to_string()), Some("7g7f".to_string()));
+        assert_eq!((entry.score, entry.depth, entry.bound, entry.generation), (-321, 12, ScoreBound::Upper, 1));
+        assert!(table.probe(0x0fed_cba9_8765_4321).is_none());
+
+        // 指し手の無い項目と、深さの頭打ち。
+        table.save(42, None, 0, 1000, ScoreBound::Lower);
+        let entry = table.probe(42).unwrap();
+        assert!(entry.movement.is_none());
+        assert_eq!(entry.depth, 254);
+    }
+
+    #[test]
+    fn same_position_keeps_the_previous_move() {
+        let table = TranspositionTable::new(1);
+        table.save(7, movement("P*5e"), 10, 3, ScoreBound::Exact);
+        table.save(7, None, 20, 5, ScoreBound::Lower);
+        let entry = table.probe(7).unwrap();
+        assert_eq!(entry.movement.map(|x| x.to_string()), Some("P*5e".to_string()));
+        assert_eq!((entry.score, entry.depth), (20, 5));
+        table.save(7, movement("2b8h+"), 30, 1, ScoreBound::Exact);
+        assert_eq!(probed_move(&table, 7), Some("2b8h+".to_string()));
+    }
+
+    #[test]
+    fn mate_scores_are_stored_from_the_position() {
+        let ply = 5;
+        for &score in [MATE - 9, -MATE + 9, 123].iter() {
+            assert_eq!(score_from_table(score_to_table(score, ply), ply), score);
+        }
+        // ルートから5手目の局面で9手詰めなら、その局面からは4手詰め。
+        assert_eq!(score_to_table(MATE - 9, ply), MATE - 4);
+    }
+
+    #[test]
+    fn replaces_the_shallowest_entry() {
+        let table = one_cluster();
+        for &(key, depth) in [(1, 10), (2, 2), (3, 7), (4, 5)].iter() {
+            table.save(key, None, 0, depth, ScoreBound::Exact);
+        }
+        table.save(5, None, 0, 1, ScoreBound::Exact);
+        assert!(table.probe(2).is_none());
+        for &key in [1, 3, 4, 5].iter() { assert!(table.probe(key).is_some(), "{}", key); }
+        // 同じ局面は、浅くても自分の項目に書く。
+        table.save(1, None, 0, 0, ScoreBound::Exact);
+        for &key in [1, 3, 4, 5].iter() { assert!(table.probe(key).is_some(), "{}", key); }
+    }
+
+    #[test]
+    fn replaces_old_generations_first() {
+        let table = one_cluster();
+        table.save(1, None, 0, 20, ScoreBound::Exact);
+        table.new_search();
+        table.new_search();
+        for &key in [2, 3, 4].iter() { table.save(key, None, 0, 5, ScoreBound::Exact); }
+        // 2世代前の深さ 20 は、今の世代の深さ 5 より安く見る。
+        table.save(5, None, 0, 1, ScoreBound::Exact);
+        assert!(table.probe(1).is_none());
+        for &key in [2, 3, 4, 5].iter() { assert!(table.probe(key).is_some(), "{}", key); }
+    }
+
+    #[test]
+    fn rejects_a_torn_entry() {
+        let table = one_cluster();
+        table.save(1, movement("7g7f"), 100, 3, ScoreBound::Exact);
+        table.save(2, movement("3c3d"), -50, 4, ScoreBound::Lower);
+        let slots = &table.clusters[0].slots;
+        let (first, second) = (slots.iter().position(|x| x.load(1).is_some()).unwrap(), slots.iter().position(|x| x.load(2).is_some()).unwrap());
+        // 別のスレッドの書き込みが重なって、中身だけ局面2のものになった。
+        slots[first].data.store(slots[second].data.load(Ordering::Relaxed), Ordering::Relaxed);
+        assert!(table.probe(1).is_none());
+        assert_eq!(probed_move(&table, 2), Some("3c3d".to_string()));
+    }
+
+    #[test]
+    fn resize_and_clear() {
+        let mut table = TranspositionTable::new(1);
+        assert_eq!(table.megabytes(), 1);
+        table.new_search();
+        table.save(9, movement("7g7f"), 0, 1, ScoreBound::Exact);
+        table.clear();
+        assert!(table.probe(9).is_none());
+        assert_eq!(table.generation(), 0);
+
+        table.save(9, movement("7g7f"), 0, 1, ScoreBound::Exact);
+        table.resize(2);
+        assert_eq!(table.megabytes(), 2);
+        assert!(table.probe(9).is_none());
+    }
+
+    #[test]
+    fn hashfull_counts_the_current_generation() {
+        let table = one_cluster();
+        assert_eq!(table.hashfull(), 0);
+        table.save(1, None, 0, 1, ScoreBound::Exact);
+        table.save(2, None, 0, 1, ScoreBound::Exact);
+        assert_eq!(table.hashfull(), 500);
+        table.new_search();
+        assert_eq!(table.hashfull(), 0);
+        for key in 3..7 { table.save(key, None, 0, 1, ScoreBound::Exact); }
+        assert_eq!(table.hashfull(), 1000);
+    }
+}
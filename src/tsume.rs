@@ -0,0 +1,251 @@
+//! 詰将棋の検討☆（＾～＾） 作品として傷が無いかを調べる。
+//!
+//! 玉方の持ち駒は残り駒全部とみなす。作意は `solve_mate` の最短手順で、
+//! その手順の上で余詰、駒余り、玉方のもっと長い逃れ、不要な序を調べる。
+use std::fmt;
+
+use engine_output::Checkmate;
+use hand::HAND_PIECE_TYPES;
+use mate::{MateLimits, solve_mate, solve_mate_within};
+use position::Position;
+use validation::{PositionError, ValidationRules};
+use {PieceType, UsiMovement};
+
+/// 作意の途中の別の手。
+///
+/// # Members.
+///
+/// * `ply` - 作意の何手目の代わりか。1 始まり。
+/// * `movement` - 作意と違う手。
+/// * `line` - 余詰なら、その手から詰むまでの手順。
+#[derive(Clone,Debug)]
+pub struct Deviation{
+    pub ply : usize,
+    pub movement : UsiMovement,
+    pub line : Vec<UsiMovement>,
+}
+
+/// 検討の結果。
+///
+/// # Members.
+///
+/// * `errors` - 局面の反則。
+/// * `solution` - 作意。詰まなければ `Checkmate::NoMate`、調べ切れなければ `Checkmate::Timeout`。反則があれば解かずに None。
+/// * `alternate_mates` - 余詰。作意の攻め方の手の代わりに、残りの手数以内で詰む王手。
+/// * `leftover` - 駒余り。詰んだときの攻め方の持ち駒。
+/// * `longer_defences` - 作意より長く逃れる玉方の手。あれば作意は最長の抵抗ではない。
+/// * `unverified` - 探索量が足りず、余詰か長い逃れかを確かめられなかった手。
+/// * `redundant_opening` - 初手から続く、省ける攻め方と玉方の組の数。
+#[derive(Clone)]
+pub struct TsumeReport{
+    pub errors : Vec<PositionError>,
+    pub solution : Option<Checkmate>,
+    pub alternate_mates : Vec<Deviation>,
+    pub leftover : Vec<(PieceType, i8)>,
+    pub longer_defences : Vec<Deviation>,
+    pub unverified : Vec<Deviation>,
+    pub redundant_opening : usize,
+}
+impl TsumeReport{
+    /// 傷が1つも無ければ真。確かめられなかった手があれば偽。
+    pub fn is_sound(&self) -> bool {
+        self.errors.is_empty()
+            && matches!(self.solution, Some(Checkmate::Mate(_)))
+            && self.alternate_mates.is_empty()
+            && self.leftover.is_empty()
+            && self.longer_defences.is_empty()
+            && self.unverified.is_empty()
+            && self.redundant_opening==0
+    }
+}
+impl fmt::Display for TsumeReport{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        for error in self.errors.iter() { writeln!(f, "反則: {}", error)?; }
+        match self.solution {
+            Some(Checkmate::Mate(ref line)) => {
+                write!(f, "作意: {}手詰", line.len())?;
+                for movement in line.iter() { write!(f, " {}", movement)?; }
+                writeln!(f)?;
+            },
+            Some(Checkmate::NoMate) => { writeln!(f, "不詰")?; },
+            Some(_) => { writeln!(f, "解けなかった")?; },
+            None => {},
+        }
+        for x in self.alternate_mates.iter() {
+            write!(f, "余詰: {}手目 {}", x.ply, x.movement)?;
+            for movement in x.line.iter() { write!(f, " {}", movement)?; }
+            writeln!(f)?;
+        }
+        if !self.leftover.is_empty() {
+            write!(f, "駒余り:")?;
+            for &(pt, count) in self.leftover.iter() { write!(f, " {}{}", pt, count)?; }
+            writeln!(f)?;
+        }
+        for x in self.longer_defences.iter() { writeln!(f, "長い逃れ: {}手目 {}", x.ply, x.movement)?; }
+        for x in self.unverified.iter() { writeln!(f, "未確認: {}手目 {}", x.ply, x.movement)?; }
+        if 0<self.redundant_opening { writeln!(f, "不要な序: 初手から{}組", self.redundant_opening)?; }
+        Ok(())
+    }
+}
+
+/// 攻め方の手を指した後の局面が、`plies` 手以内に詰むか。玉方の全部の応手で調べる。
+///
+/// 詰むなら応手の中で一番長い手順を返す。詰まなければ `Checkmate::NoMate`。
+fn defender_mated_within(position:&Position, plies:u32, limits:&MateLimits) -> Checkmate {
+    let replies = position.legal_moves();
+    if replies.is_empty() { return Checkmate::Mate(Vec::new()); }
+    if plies<2 { return Checkmate::NoMate; }
+    let mut longest : Vec<UsiMovement> = Vec::new();
+    for reply in replies.iter() {
+        let mut next = *position;
+        next.do_move(reply);
+        match solve_mate_within(&next, plies - 1, limits) {
+            Checkmate::Mate(line) => {
+                if longest.len()<=line.len() {
+                    longest = line;
+                    longest.insert(0, *reply);
+                }
+            },
+            other => { return other; },
+        }
+    }
+    Checkmate::Mate(longest)
+}
+
+/// sfen の詰将棋を検討する。`limits` は1回の探索ごとの制限。
+pub fn check_tsume(sfen:&str, limits:&MateLimits) -> TsumeReport {
+    let original = Position::from_sfen(sfen);
+    let mut report = TsumeReport{
+        errors : original.validate(ValidationRules::Tsume).err().unwrap_or_default(),
+        solution : None,
+        alternate_mates : Vec::new(),
+        leftover : Vec::new(),
+        longer_defences : Vec::new(),
+        unverified : Vec::new(),
+        redundant_opening : 0,
+    };
+    if !report.errors.is_empty() { return report; }
+    let start = original.with_defender_hand_filled();
+    let solution = solve_mate(&start, limits);
+    report.solution = Some(solution.clone());
+    let solution = match solution {
+        Checkmate::Mate(line) => line,
+        _ => { return report; },
+    };
+
+    let attacker = start.turn;
+    let mut position = start;
+    for (i, played) in solution.iter().enumerate() {
+        let remaining = (solution.len() - i) as u32;
+        for movement in position.legal_moves().iter().filter(|x| *x!=played) {
+            let mut next = position;
+            next.do_move(movement);
+            let deviation = |line:Vec<UsiMovement>| Deviation{ ply : i + 1, movement : *movement, line };
+            if position.turn==attacker {
+                // 作意と同じか短い手数で詰む別の王手は余詰。
+                if !next.in_check(next.turn) { continue; }
+                match defender_mated_within(&next, remaining - 1, limits) {
+                    Checkmate::Mate(line) => { report.alternate_mates.push(deviation(line)); },
+                    Checkmate::NoMate => {},
+                    _ => { report.unverified.push(deviation(Vec::new())); },
+                }
+            } else {
+                // 玉方の別の応手は、作意の残りの手数以内で詰まなければいけない。
+                match solve_mate_within(&next, remaining - 1, limits) {
+                    Checkmate::Mate(_) => {},
+                    Checkmate::NoMate => { report.longer_defences.push(deviation(Vec::new())); },
+                    _ => { report.unverified.push(deviation(Vec::new())); },
+                }
+            }
+        }
+        position.do_move(played);
+    }
+
+    for &pt in HAND_PIECE_TYPES.iter() {
+        let count = position.hand_count(attacker, pt);
+        if 0<count { report.leftover.push((pt, count)); }
+    }
+    report.redundant_opening = count_redundant_opening(&start, &solution, &report, limits);
+    report
+}
+
+/// 初手から数えて、外しても作品が成り立つ組の数。
+///
+/// 持ち駒の動かない（打ちも駒取りも無い）組だけを見る。その組の後の局面から解き直して、
+/// 作意の残りと同じ手数で詰み、その先に傷が無ければ、序として省ける。
+fn count_redundant_opening(start:&Position, solution:&[UsiMovement], report:&TsumeReport, limits:&MateLimits) -> usize {
+    if !report.leftover.is_empty() { return 0; }
+    let mut position = *start;
+    let mut count = 0;
+    for pair in solution.chunks(2).filter(|x| x.len()==2) {
+        let mut next = position;
+        next.do_move(&pair[0]);
+        next.do_move(&pair[1]);
+        if next.hand!=position.hand { break; }
+        let played = 2 * (count + 1);
+        let flawless = report.alternate_mates.iter()
+            .chain(report.longer_defences.iter())
+            .chain(report.unverified.iter())
+            .all(|x| x.ply<=played);
+        if !flawless { break; }
+        match solve_mate(&next, limits) {
+            Checkmate::Mate(ref line) if line.len()==solution.len() - played => {},
+            _ => { break; },
+        }
+        count += 1;
+        position = next;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> MateLimits {
+        MateLimits{ nodes : Some(100_000), time : None }
+    }
+
+    #[test]
+    fn forced_replies_are_sound() {
+        // ▲２一飛打 △１二玉（応手はこれしか無い） ▲２二飛成 まで3手詰。
+        let sfen = "8k/6G2/9/9/9/9/9/9/9 b R 1";
+        let report = check_tsume(sfen, &limits());
+        let mut position = Position::from_sfen(sfen).with_defender_hand_filled();
+        match report.solution {
+            Some(Checkmate::Mate(ref line)) => {
+                assert_eq!(line.iter().map(|x| x.to_string()).collect::<Vec<String>>(), ["R*2a", "1a1b", "2a2b+"]);
+                position.do_move(&line[0]);
+            },
+            _ => panic!("{}", report),
+        }
+        assert_eq!(position.legal_moves().len(), 1);
+        assert!(report.is_sound(), "{}", report);
+    }
+
+    #[test]
+    fn reports_alternate_mates_and_leftover() {
+        let report = check_tsume("8l/6GSk/7pp/9/9/9/9/9/9 b RB 1", &limits());
+        assert!(!report.is_sound());
+        assert_eq!(report.alternate_mates.len(), 1);
+        assert_eq!(report.alternate_mates[0].movement.to_string(), "B*2a");
+        assert_eq!(report.leftover, [(PieceType::R, 1), (PieceType::B, 1)]);
+    }
+
+    #[test]
+    fn reports_redundant_opening() {
+        // ▲２三金 △１一玉 ▲２二金 まで3手詰。初手の組は持ち駒が動かず、
+        // △１一玉の局面から解き直しても ▲２二金 の1手詰で傷が無い。
+        let report = check_tsume("9/8k/9/8G/7R1/9/9/9/9 b - 1", &limits());
+        match report.solution {
+            Some(Checkmate::Mate(ref line)) => {
+                assert_eq!(line.iter().map(|x| x.to_string()).collect::<Vec<String>>(), ["1d2c", "1b1a", "2c2b"]);
+            },
+            _ => panic!("{}", report),
+        }
+        assert_eq!(report.redundant_opening, 1);
+        assert!(report.alternate_mates.is_empty() && report.longer_defences.is_empty() && report.leftover.is_empty());
+        assert!(!report.is_sound());
+        assert!(report.to_string().contains("不要な序: 初手から1組"));
+    }
+}
@@ -0,0 +1,213 @@
+//! 局面の検査☆（＾～＾） `parse_board` は何でも読むので、将棋としておかしくないか後から調べる。
+use std::fmt;
+
+use position::Position;
+use {FILE0, FILE10, RANK1, RANK10};
+use {Color, Piece, PieceType};
+
+/// 駒の種類ごとの、1組（40枚）の中の枚数。
+pub const PIECE_TYPE_TOTALS : [(PieceType, i8); 8] = [
+    (PieceType::K, 2),
+    (PieceType::R, 2),
+    (PieceType::B, 2),
+    (PieceType::G, 4),
+    (PieceType::S, 4),
+    (PieceType::N, 4),
+    (PieceType::L, 4),
+    (PieceType::P, 18),
+];
+
+/// どこまで厳しく調べるか。
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum ValidationRules{
+    /// 普通の対局。玉は両方に1枚ずつ。
+    Normal,
+    /// 詰将棋。手番の側が攻め方。攻め方の玉は無くてもいい。
+    /// 玉方の持ち駒は「残り駒全部」とみなすので、枚数を数えない。
+    Tsume,
+}
+
+/// 見つかった問題。
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum PositionError{
+    /// 玉の数がおかしい。
+    KingCount{ color : Color, count : i8 },
+    /// 盤上と持ち駒を合わせて、1組の枚数より多い。
+    TooManyPieces{ piece_type : PieceType, count : i8, max : i8 },
+    /// 二歩。
+    Nifu{ color : Color, file : i8 },
+    /// 行き所のない駒。1段目の歩や香、1、2段目の桂。
+    DeadPiece{ file : i8, rank : i8, piece : Piece },
+    /// 手番でない側の玉に王手が掛かっている。
+    OpponentInCheck{ color : Color },
+}
+impl fmt::Display for PositionError{
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PositionError::KingCount{ color, count } => { write!(f,"{}の玉が{}枚ある。", color, count) },
+            PositionError::TooManyPieces{ piece_type, count, max } => { write!(f,"{}が{}枚ある。{}枚まで。", piece_type, count, max) },
+            PositionError::Nifu{ color, file } => { write!(f,"{}の{}筋が二歩。", color, file) },
+            PositionError::DeadPiece{ file, rank, piece } => { write!(f,"{}{}の{}は行き所がない。", file, rank, piece) },
+            PositionError::OpponentInCheck{ color } => { write!(f,"手番でない{}の玉に王手が掛かっている。", color) },
+        }
+    }
+}
+
+/// 駒種類が盤上と持ち駒に何枚あるか。成り駒は成る前の駒として数える。`hands` で数える側の持ち駒を選ぶ。
+fn count_piece_type(position:&Position, pt:PieceType, hands:[bool;2]) -> i8 {
+    let mut count = 0;
+    for file in FILE0+1..FILE10 {
+        for rank in RANK1..RANK10 {
+            let pc = position.piece(file, rank);
+            if !pc.is_space() && pc.piece_type().demote()==pt { count += 1; }
+        }
+    }
+    if pt!=PieceType::K {
+        for color in Color::all() {
+            if hands[color.index()] { count += position.hand_count(color, pt); }
+        }
+    }
+    count
+}
+
+/// 自分から見て前から何段目かで、そこにいると動けない駒なら真。
+fn is_dead_rank(pt:PieceType, rank_from_front:i8) -> bool {
+    match pt {
+        PieceType::P | PieceType::L => rank_from_front<=1,
+        PieceType::N => rank_from_front<=2,
+        _ => false,
+    }
+}
+
+impl Position{
+    /// 局面の問題を全部挙げる。問題がなければ Ok。
+    pub fn validate(&self, rules:ValidationRules) -> Result<(), Vec<PositionError>> {
+        let mut errors = Vec::new();
+        let attacker = self.turn;
+        let defender = self.turn.opponent();
+
+        // 玉の数。
+        for color in Color::all() {
+            let king = Piece::from(color, PieceType::K);
+            let mut count = 0;
+            for file in FILE0+1..FILE10 {
+                for rank in RANK1..RANK10 {
+                    if self.piece(file, rank)==king { count += 1; }
+                }
+            }
+            let allowed = rules==ValidationRules::Tsume && color==attacker && count==0;
+            if count!=1 && !allowed {
+                errors.push(PositionError::KingCount{ color, count });
+            }
+        }
+
+        // 駒の枚数。詰将棋では玉方の持ち駒を数えない。
+        let mut hands = [true;2];
+        if rules==ValidationRules::Tsume { hands[defender.index()] = false; }
+        for &(pt, max) in PIECE_TYPE_TOTALS.iter() {
+            let count = count_piece_type(self, pt, hands);
+            if max<count {
+                errors.push(PositionError::TooManyPieces{ piece_type : pt, count, max });
+            }
+        }
+
+        // 二歩と、行き所のない駒。
+        for file in FILE0+1..FILE10 {
+            let mut pawns = [0;2];
+            for rank in RANK1..RANK10 {
+                let pc = self.piece(file, rank);
+                if pc.is_space() { continue; }
+                let color = pc.color();
+                let pt = pc.piece_type();
+                if pt==PieceType::P { pawns[color.index()] += 1; }
+                let rank_from_front = if color==Color::Sente { rank } else { RANK10 - rank };
+                if is_dead_rank(pt, rank_from_front) {
+                    errors.push(PositionError::DeadPiece{ file, rank, piece : pc });
+                }
+            }
+            for color in Color::all() {
+                if 1<pawns[color.index()] { errors.push(PositionError::Nifu{ color, file }); }
+            }
+        }
+
+        // 手番でない側が王手されたままなのは、直前の手が反則。
+        if self.in_check(defender) {
+            errors.push(PositionError::OpponentInCheck{ color : defender });
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// 詰将棋の玉方の持ち駒を「残り駒全部」にした局面。玉は持ち駒にしない。
+    pub fn with_defender_hand_filled(&self) -> Position {
+        let mut position = *self;
+        let defender = self.turn.opponent();
+        let mut hands = [true;2];
+        hands[defender.index()] = false;
+        for &(pt, max) in PIECE_TYPE_TOTALS.iter().skip(1) {
+            let rest = max - count_piece_type(self, pt, hands);
+            position.hand[defender.index()].set(pt, rest.max(0));
+        }
+        position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(sfen:&str, rules:ValidationRules) -> Vec<PositionError> {
+        Position::from_sfen(sfen).validate(rules).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn startpos_is_valid() {
+        assert_eq!(Position::startpos().validate(ValidationRules::Normal), Ok(()));
+    }
+
+    #[test]
+    fn king_count() {
+        assert_eq!(errors("4k4/9/9/9/9/9/9/9/9 b G 1", ValidationRules::Normal),
+            [PositionError::KingCount{ color : Color::Sente, count : 0 }]);
+        // 詰将棋なら攻め方の玉は無くてもいい。玉方の玉は要る。
+        assert_eq!(errors("4k4/9/9/9/9/9/9/9/9 b G 1", ValidationRules::Tsume), []);
+        assert_eq!(errors("9/9/9/9/9/9/9/9/4K4 b G 1", ValidationRules::Tsume),
+            [PositionError::KingCount{ color : Color::Gote, count : 0 }]);
+    }
+
+    #[test]
+    fn too_many_pieces() {
+        assert_eq!(errors("4k4/9/9/9/9/9/9/9/4K4 b 5G 1", ValidationRules::Normal),
+            [PositionError::TooManyPieces{ piece_type : PieceType::G, count : 5, max : 4 }]);
+        // 成り駒は成る前の駒として数える。
+        assert_eq!(errors("4k4/9/9/9/9/9/9/9/+B+B2K4 b B 1", ValidationRules::Normal),
+            [PositionError::TooManyPieces{ piece_type : PieceType::B, count : 3, max : 2 }]);
+        // 詰将棋では玉方の持ち駒を数えない。
+        assert_eq!(errors("4k4/9/9/9/9/9/9/9/4K4 b G 4g 1", ValidationRules::Tsume), []);
+    }
+
+    #[test]
+    fn nifu_and_dead_pieces() {
+        assert_eq!(errors("4k4/9/9/9/4P4/4P4/9/9/4K4 b - 1", ValidationRules::Normal),
+            [PositionError::Nifu{ color : Color::Sente, file : 5 }]);
+        assert_eq!(errors("N3k4/9/9/9/9/9/9/9/4K3l b - 1", ValidationRules::Normal), [
+            PositionError::DeadPiece{ file : 1, rank : 9, piece : Piece::L1 },
+            PositionError::DeadPiece{ file : 9, rank : 1, piece : Piece::N0 },
+        ]);
+    }
+
+    #[test]
+    fn opponent_in_check() {
+        assert_eq!(errors("4k4/9/9/9/4R4/9/9/9/4K4 b - 1", ValidationRules::Normal),
+            [PositionError::OpponentInCheck{ color : Color::Gote }]);
+    }
+
+    #[test]
+    fn fills_defender_hand() {
+        let position = Position::from_sfen("4k4/9/9/9/9/9/9/9/9 b 2G3p 1").with_defender_hand_filled();
+        assert_eq!(position.hand_count(Color::Gote, PieceType::G), 2);
+        assert_eq!(position.hand_count(Color::Gote, PieceType::P), 18);
+        assert_eq!(position.hand_count(Color::Gote, PieceType::R), 2);
+        assert_eq!(position.hand_count(Color::Sente, PieceType::G), 2);
+    }
+}
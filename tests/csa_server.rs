@@ -0,0 +1,235 @@
+//! csa_server を子プロセスで起動して、2つの接続で対局させる☆（＾～＾）
+//!
+//! `--port 0` で空いているポートを使い、`--games 1` で1局指したら止める。
+
+extern crate kifuwarabe_usi;
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+const TIMEOUT : Duration = Duration::from_secs(10);
+
+/// 起動したサーバー。棋譜は `out` に保存される。
+struct Server{
+    child : Child,
+    port : u16,
+    out : PathBuf,
+}
+impl Drop for Server{
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// `args` に `--port 0 --games 1 --out <一時フォルダー>` を足して起動する。
+fn server(test:&str, args:&[&str]) -> Server {
+    let out = env::temp_dir().join(format!("kifuwarabe_usi_csa_{}_{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&out);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_csa_server"))
+        .args(["--port", "0", "--games", "1", "--out"])
+        .arg(&out)
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn csa_server");
+    let mut line = String::new();
+    BufReader::new(child.stdout.as_mut().unwrap()).read_line(&mut line).expect("listening line");
+    let port = line.trim().trim_start_matches("listening on port ").parse().expect("port");
+    Server{ child, port, out }
+}
+
+/// 対局者の接続。
+struct Player{
+    reader : BufReader<TcpStream>,
+    writer : TcpStream,
+}
+impl Player{
+    fn login(server:&Server, name:&str) -> Player {
+        let stream = TcpStream::connect(("127.0.0.1", server.port)).expect("connect");
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut player = Player{ reader : BufReader::new(stream.try_clone().unwrap()), writer : stream };
+        player.login_again(name);
+        player
+    }
+
+    /// 同じ接続でログインする。対局が終わった後も、これで次の対局を待つ。
+    fn login_again(&mut self, name:&str) {
+        self.send(&format!("LOGIN {} pass", name));
+        assert_eq!(self.recv(), format!("LOGIN:{} OK", name));
+    }
+
+    fn send(&mut self, line:&str) {
+        writeln!(self.writer, "{}", line).expect("send");
+    }
+
+    fn recv(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).expect("recv");
+        line.trim_end().to_string()
+    }
+
+    /// `Game_Summary` を読んで `AGREE` する。Game_Summary の行を返す。
+    fn agree(&mut self) -> Vec<String> {
+        let mut summary = Vec::new();
+        loop {
+            let line = self.recv();
+            summary.push(line.clone());
+            if line=="END Game_Summary" { break; }
+        }
+        let id = summary.iter().find(|x| x.starts_with("Game_ID:")).expect("Game_ID")["Game_ID:".len()..].to_string();
+        self.send(&format!("AGREE {}", id));
+        summary
+    }
+}
+
+/// 2人ログインして対局を始める。先手、後手の順。
+fn start(server:&Server) -> (Player, Player, Vec<String>) {
+    let mut sente = Player::login(server, "alice");
+    let mut gote = Player::login(server, "bob");
+    let summary = sente.agree();
+    gote.agree();
+    assert!(sente.recv().starts_with("START:"));
+    assert!(gote.recv().starts_with("START:"));
+    (sente, gote, summary)
+}
+
+/// 指した手を、両方が `<手>,T<秒>` で受け取る。
+fn play(mover:&mut Player, other:&mut Player, text:&str) {
+    mover.send(text);
+    assert_eq!(mover.recv(), format!("{},T0", text));
+    assert_eq!(other.recv(), format!("{},T0", text));
+}
+
+/// 保存された棋譜。サーバーが止まるのを待ってから読む。
+fn record(mut server:Server) -> String {
+    server.child.wait().expect("server exit");
+    let entry = fs::read_dir(&server.out).expect("out dir").next().expect("record file").unwrap();
+    fs::read_to_string(entry.path()).expect("read record")
+}
+
+#[test]
+fn summary_and_resign() {
+    let server = server("resign", &["--time", "600", "--byoyomi", "10", "--inc", "5"]);
+    let (mut sente, mut gote, summary) = start(&server);
+    for line in ["Name+:alice", "Name-:bob", "Your_Turn:+", "To_Move:+", "Total_Time:600", "Byoyomi:10", "Increment:5"].iter() {
+        assert!(summary.iter().any(|x| x==line), "{}", line);
+    }
+
+    play(&mut sente, &mut gote, "+7776FU");
+    // カンマの後ろのコメントは指し手に含めない。
+    gote.send("-3334FU,'comment");
+    assert_eq!(gote.recv(), "-3334FU,T0");
+    assert_eq!(sente.recv(), "-3334FU,T0");
+    sente.send("%TORYO");
+    for (player, result) in [(&mut sente, "#LOSE"), (&mut gote, "#WIN")].iter_mut() {
+        assert_eq!(player.recv(), "%TORYO,T0");
+        assert_eq!(player.recv(), "#RESIGN");
+        assert_eq!(player.recv(), *result);
+    }
+
+    let text = record(server);
+    assert!(text.contains("N+alice"), "{}", text);
+    assert!(text.contains("+7776FU"), "{}", text);
+    assert!(text.contains("-3334FU"), "{}", text);
+    assert!(text.contains("%TORYO"), "{}", text);
+}
+
+#[test]
+fn illegal_move_loses() {
+    let server = server("illegal", &["--byoyomi", "10"]);
+    let (mut sente, mut gote, _) = start(&server);
+    // 歩は2升進めない。
+    sente.send("+7775FU");
+    for (player, result) in [(&mut sente, "#LOSE"), (&mut gote, "#WIN")].iter_mut() {
+        assert_eq!(player.recv(), "#ILLEGAL_MOVE");
+        assert_eq!(player.recv(), *result);
+    }
+}
+
+#[test]
+fn invalid_declaration_loses() {
+    let server = server("kachi", &["--byoyomi", "10"]);
+    let (mut sente, mut gote, _) = start(&server);
+    sente.send("%KACHI");
+    for (player, result) in [(&mut sente, "#LOSE"), (&mut gote, "#WIN")].iter_mut() {
+        assert_eq!(player.recv(), "%KACHI");
+        assert_eq!(player.recv(), "#ILLEGAL_MOVE");
+        assert_eq!(player.recv(), *result);
+    }
+}
+
+#[test]
+fn valid_declaration_wins() {
+    let sfen = "RB1K1BR2/GGGG5/SSSS5/9/9/9/9/9/4k4 b - 1";
+    let server = server("jishogi", &["--byoyomi", "10", "--sfen", sfen]);
+    let (mut sente, mut gote, _) = start(&server);
+    sente.send("%KACHI");
+    for (player, result) in [(&mut sente, "#WIN"), (&mut gote, "#LOSE")].iter_mut() {
+        assert_eq!(player.recv(), "%KACHI");
+        assert_eq!(player.recv(), "#JISHOGI");
+        assert_eq!(player.recv(), *result);
+    }
+}
+
+#[test]
+fn time_up_loses() {
+    let server = server("timeup", &["--byoyomi", "1"]);
+    let (mut sente, mut gote, _) = start(&server);
+    // 秒読み1秒で、2秒待てば時間切れ。
+    for (player, result) in [(&mut sente, "#LOSE"), (&mut gote, "#WIN")].iter_mut() {
+        assert_eq!(player.recv(), "#TIME_UP");
+        assert_eq!(player.recv(), *result);
+    }
+}
+
+#[test]
+fn repetition_is_a_draw() {
+    let server = server("sennichite", &["--byoyomi", "10"]);
+    let (mut sente, mut gote, _) = start(&server);
+    for _ in 0..3 {
+        play(&mut sente, &mut gote, "+5968OU");
+        play(&mut gote, &mut sente, "-5142OU");
+        play(&mut sente, &mut gote, "+6859OU");
+        play(&mut gote, &mut sente, "-4251OU");
+    }
+    for player in [&mut sente, &mut gote].iter_mut() {
+        assert_eq!(player.recv(), "#SENNICHITE");
+        assert_eq!(player.recv(), "#DRAW");
+    }
+    assert!(record(server).contains("%SENNICHITE"));
+}
+
+#[test]
+fn players_log_in_again_for_the_next_game() {
+    let mut server = server("again", &["--byoyomi", "10", "--games", "2"]);
+    let (mut alice, bob, _) = start(&server);
+    // 後手の接続が切れたら、後手の手番で負け。
+    drop(bob);
+    alice.send("+7776FU");
+    assert_eq!(alice.recv(), "+7776FU,T0");
+    assert_eq!(alice.recv(), "#ABNORMAL");
+    assert_eq!(alice.recv(), "#WIN");
+
+    // 続ける側は同じ接続でログインし直す。切れた側の名前は、新しい接続で使える。
+    alice.login_again("alice");
+    let mut bob = Player::login(&server, "bob");
+    alice.agree();
+    bob.agree();
+    assert!(alice.recv().starts_with("START:"));
+    assert!(bob.recv().starts_with("START:"));
+    alice.send("%TORYO");
+    for (player, result) in [(&mut alice, "#LOSE"), (&mut bob, "#WIN")].iter_mut() {
+        assert_eq!(player.recv(), "%TORYO,T0");
+        assert_eq!(player.recv(), "#RESIGN");
+        assert_eq!(player.recv(), *result);
+    }
+
+    server.child.wait().expect("server exit");
+    assert_eq!(fs::read_dir(&server.out).expect("out dir").count(), 2);
+}
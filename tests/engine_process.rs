@@ -0,0 +1,118 @@
+//! `UsiEngineProcess` で、例のエンジン（mock_engine、scripted_engine）を子プロセスとして動かす☆（＾～＾）
+//!
+//! `cargo test` は例も作るので、テストの実行ファイルの隣の `examples` フォルダーから起動する。
+//! 例だけ作られていなければ、ここで `cargo build --example` する。
+
+extern crate kifuwarabe_usi;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use kifuwarabe_usi::GoParams;
+use kifuwarabe_usi::engine_output::{BestMove, UsiOptionType};
+use kifuwarabe_usi::engine_process::{EngineEvent, UsiEngineProcess};
+use kifuwarabe_usi::position::Position;
+
+const TIMEOUT : Duration = Duration::from_secs(10);
+
+/// 例の実行ファイルのパス。
+fn example(name:&str) -> PathBuf {
+    // target/debug/deps/<テスト> から target/debug/examples/<例> へ。
+    let exe = env::current_exe().expect("test executable");
+    let dir = exe.parent().and_then(|x| x.parent()).expect("target directory").join("examples");
+    let path = dir.join(format!("{}{}", name, env::consts::EXE_SUFFIX));
+    if !path.exists() {
+        let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let status = Command::new(cargo)
+            .args(["build", "--example", name])
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .status()
+            .expect("cargo build");
+        assert!(status.success(), "cargo build --example {}", name);
+    }
+    path
+}
+
+/// 台本を一時ファイルに書いて、そのパスを返す。テストごとに別のファイル。
+fn script(test:&str, text:&str) -> String {
+    let path = env::temp_dir().join(format!("kifuwarabe_usi_{}_{}.txt", test, std::process::id()));
+    fs::write(&path, text).expect("write script");
+    path.to_string_lossy().into_owned()
+}
+
+/// 台本を読ませた scripted_engine。
+fn scripted(test:&str, text:&str) -> UsiEngineProcess {
+    let mut engine = UsiEngineProcess::spawn(example("scripted_engine"), TIMEOUT).expect("spawn scripted_engine");
+    engine.set_option("ScriptFile", &script(test, text)).expect("setoption");
+    engine
+}
+
+/// `bestmove` が来るまで `info` などを読み飛ばす。
+fn recv_bestmove(engine:&UsiEngineProcess) -> io::Result<BestMove> {
+    loop {
+        if let EngineEvent::BestMove(x) = engine.recv_event(Some(TIMEOUT))? { return Ok(x); }
+    }
+}
+
+#[test]
+fn handshake() {
+    let engine = UsiEngineProcess::spawn(example("mock_engine"), TIMEOUT).expect("spawn mock_engine");
+    assert_eq!(engine.id().name, "MockEngine");
+    assert_eq!(engine.id().author, "muzudho");
+    let names : Vec<&str> = engine.options().iter().map(|x| x.name.as_str()).collect();
+    assert_eq!(names, ["USI_Hash", "Style", "BookFile"]);
+    assert_eq!(engine.options()[0].option_type, UsiOptionType::Spin{ default : Some(16), min : Some(1), max : Some(1024) });
+    assert_eq!(engine.options()[1].option_type, UsiOptionType::Combo{
+        default : Some("Normal".to_string()),
+        vars : vec!["Normal".to_string(), "Aggressive".to_string()],
+    });
+    engine.quit(TIMEOUT).expect("quit");
+}
+
+#[test]
+fn is_ready_times_out_without_readyok() {
+    let mut engine = scripted("noreply", "[isready]\nnoreply\n");
+    let error = engine.is_ready(Duration::from_millis(300)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn go_returns_bestmove() {
+    let mut engine = scripted("bestmove", "[startpos moves 7g7f]\ninfo depth 1 score cp 0 pv 3c3d\nbestmove 3c3d\n");
+    engine.is_ready(TIMEOUT).expect("isready");
+    engine.new_game().expect("usinewgame");
+
+    let mut moves = Vec::new();
+    // 台本に無い局面では最初の合法手。
+    engine.position("startpos", &moves).expect("position");
+    engine.go(&GoParams{ byoyomi : 1000, ..GoParams::default() }).expect("go");
+    let first = Position::startpos().legal_moves()[0];
+    match recv_bestmove(&engine).expect("bestmove") {
+        BestMove::Move{ best, .. } => { assert_eq!(best, first); },
+        other => panic!("{}", other),
+    }
+
+    moves.push(Position::startpos().legal_moves().into_iter().find(|x| x.to_string()=="7g7f").unwrap());
+    engine.position("startpos", &moves).expect("position");
+    engine.go(&GoParams{ byoyomi : 1000, ..GoParams::default() }).expect("go");
+    match recv_bestmove(&engine).expect("bestmove") {
+        BestMove::Move{ best, .. } => { assert_eq!(best.to_string(), "3c3d"); },
+        other => panic!("{}", other),
+    }
+    engine.quit(TIMEOUT).expect("quit");
+}
+
+#[test]
+fn engine_exits_mid_game() {
+    let mut engine = scripted("exit", "[go]\nexit\n");
+    engine.is_ready(TIMEOUT).expect("isready");
+    engine.new_game().expect("usinewgame");
+    engine.position("startpos", &[]).expect("position");
+    engine.go(&GoParams{ byoyomi : 1000, ..GoParams::default() }).expect("go");
+    let error = engine.recv_event(Some(TIMEOUT)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}